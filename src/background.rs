@@ -0,0 +1,28 @@
+use std::env;
+use std::sync::OnceLock;
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+const DEFAULT_BACKGROUND_CONCURRENCY: usize = 2;
+
+static BACKGROUND_LIMIT: OnceLock<Semaphore> = OnceLock::new();
+
+fn background_concurrency() -> usize {
+    env::var("BACKGROUND_CONCURRENCY")
+        .ok()
+        .and_then(|val| val.parse::<usize>().ok())
+        .filter(|limit| *limit > 0)
+        .unwrap_or(DEFAULT_BACKGROUND_CONCURRENCY)
+}
+
+/// Wait for a slot before a background task talks to Transmission or Jackett
+///
+/// Only background tasks take permits, so automation is capped without ever
+/// blocking interactive commands.
+pub async fn background_permit() -> SemaphorePermit<'static> {
+    BACKGROUND_LIMIT
+        .get_or_init(|| Semaphore::new(background_concurrency()))
+        .acquire()
+        .await
+        .expect("background semaphore is never closed")
+}
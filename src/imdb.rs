@@ -29,6 +29,18 @@ fn imdb_title(imdb_url: String) -> Result<String, String> {
     }
 }
 
+/// IMDb id (e.g. tt0133093) from an IMDb title URL
+pub fn imdb_id(imdb_url: String) -> Result<String, String> {
+    let id = imdb_title(imdb_url)?;
+
+    let is_id = id.len() > 2 && id.starts_with("tt") && id[2..].chars().all(|c| c.is_ascii_digit());
+    if is_id {
+        Ok(id)
+    } else {
+        Err(format!("{} is not an IMDb id", id))
+    }
+}
+
 #[derive(serde::Deserialize, Debug, Clone)]
 struct OmdbData {
     #[serde(rename(deserialize = "Response"))]
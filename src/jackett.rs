@@ -1,11 +1,15 @@
 use hyper::{body::to_bytes, client, Body, Uri};
 use std::env;
 use std::fs;
+use std::sync::OnceLock;
 
 use size_format::SizeFormatterSI;
 use std::str::FromStr;
 use url::form_urlencoded;
 
+use crate::search::SearchFilters;
+use crate::selection::check_index;
+use crate::telegram::escape_html;
 use crate::transmission::Media;
 
 #[derive(serde::Deserialize)]
@@ -13,22 +17,82 @@ struct Indexer {
     #[serde(rename(deserialize = "Name"))]
     #[allow(dead_code)]
     name: String,
+    // Set when this indexer failed while the search as a whole succeeded
+    #[serde(rename(deserialize = "Error"), default)]
+    error: Option<String>,
 }
 
+impl Indexer {
+    fn failed(&self) -> bool {
+        self.error.as_ref().is_some_and(|error| !error.is_empty())
+    }
+}
+
+// Aliases cover the camelCase names used by Prowlarr and other Torznab
+// proxies, missing fields fall back to empty values instead of failing the
+// whole search
 #[derive(serde::Deserialize, Clone)]
-struct Torrent {
-    #[serde(rename(deserialize = "Seeders"))]
+pub(crate) struct Torrent {
+    #[serde(rename(deserialize = "Seeders"), alias = "seeders", default)]
     seeders: i64,
-    #[serde(rename(deserialize = "MagnetUri"))]
+    #[serde(rename(deserialize = "MagnetUri"), alias = "magnetUri", alias = "magnetUrl", default)]
     magnet_uri: Option<String>,
-    #[serde(rename(deserialize = "Title"))]
+    #[serde(rename(deserialize = "Title"), alias = "title", default)]
     title: String,
-    #[serde(rename(deserialize = "Category"))]
+    #[serde(
+        rename(deserialize = "Category"),
+        alias = "category",
+        alias = "categories",
+        default,
+        deserialize_with = "category_ids"
+    )]
     categories: Vec<i64>,
-    #[serde(rename(deserialize = "Size"))]
+    #[serde(rename(deserialize = "Size"), alias = "size", default)]
     size: u64,
-    #[serde(rename(deserialize = "Link"))]
+    #[serde(rename(deserialize = "Link"), alias = "link", alias = "downloadUrl", default)]
     torrent_url: Option<String>,
+    #[serde(rename(deserialize = "Tracker"), alias = "tracker", alias = "indexer", default)]
+    tracker: Option<String>,
+    #[serde(
+        rename(deserialize = "TrackerId"),
+        alias = "trackerId",
+        alias = "indexerId",
+        default,
+        deserialize_with = "string_or_number"
+    )]
+    tracker_id: Option<String>,
+    #[serde(rename(deserialize = "PublishDate"), alias = "publishDate", default)]
+    publish_date: Option<String>,
+    #[serde(rename(deserialize = "Peers"), alias = "peers", default)]
+    peers: Option<i64>,
+}
+
+/// Categories as plain ids (Jackett) or `{"id": 2000, "name": ...}` objects (Prowlarr)
+fn category_ids<'de, D>(deserializer: D) -> Result<Vec<i64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let values: Option<Vec<serde_json::Value>> = serde::Deserialize::deserialize(deserializer)?;
+
+    Ok(values
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|value| value.as_i64().or_else(|| value.get("id").and_then(|id| id.as_i64())))
+        .collect())
+}
+
+/// Indexer ids are strings on Jackett and numbers on Prowlarr
+fn string_or_number<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<serde_json::Value> = serde::Deserialize::deserialize(deserializer)?;
+
+    Ok(match value {
+        Some(serde_json::Value::String(id)) => Some(id),
+        Some(serde_json::Value::Number(id)) => Some(id.to_string()),
+        _ => None,
+    })
 }
 
 #[derive(serde::Deserialize)]
@@ -49,6 +113,26 @@ pub struct TorrentLocation {
 #[derive(Clone)]
 pub struct TelegramJackettResponse {
     torrents: Vec<Torrent>,
+    // (failed, queried) when some indexers errored
+    failed_indexers: Option<(usize, usize)>,
+    // Results before the list was cut to JACKETT_MAX_RESULTS
+    total_results: usize,
+    // Set by /search-tv and /search-movie, picks don't need a category then
+    media: Option<Media>,
+    // Day of the search, ages are counted from it so replies keep matching
+    // the list after midnight
+    searched_on: i64,
+}
+
+impl TelegramJackettResponse {
+    /// Title of the best ranked result
+    pub fn top_title(&self) -> Option<String> {
+        self.torrents.first().map(|t| t.title.clone())
+    }
+}
+
+fn failed_indexers_note(failed: usize, queried: usize) -> String {
+    format!("({}/{} indexers failed)", failed, queried)
 }
 
 fn jackett_url() -> String {
@@ -58,6 +142,67 @@ fn jackett_url() -> String {
     }
 }
 
+fn blacklisted_indexers() -> Vec<String> {
+    match env::var("JACKETT_BLACKLIST_INDEXERS") {
+        Ok(val) => val
+            .split(',')
+            .map(|x| x.trim().to_lowercase())
+            .filter(|x| !x.is_empty())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn is_blacklisted(torrent: &Torrent, blacklist: &[String]) -> bool {
+    [&torrent.tracker_id, &torrent.tracker]
+        .iter()
+        .filter_map(|field| field.as_ref())
+        .any(|name| blacklist.contains(&name.to_lowercase()))
+}
+
+const DEFAULT_MAX_RESULTS: usize = 20;
+// More results than this risk going over Telegram's message size
+const MAX_RESULTS_LIMIT: usize = 50;
+
+fn max_results() -> usize {
+    env::var("JACKETT_MAX_RESULTS")
+        .ok()
+        .and_then(|val| val.trim().parse::<usize>().ok())
+        .filter(|max| *max > 0)
+        .map_or(DEFAULT_MAX_RESULTS, |max| max.min(MAX_RESULTS_LIMIT))
+}
+
+fn max_per_indexer() -> Option<usize> {
+    env::var("SEARCH_MAX_PER_INDEXER")
+        .ok()
+        .and_then(|val| val.trim().parse::<usize>().ok())
+        .filter(|max| *max > 0)
+}
+
+/// Keep at most `max` results per indexer and interleave them, so one large
+/// indexer can't fill the whole list. Expects results sorted by seeders.
+fn cap_per_indexer(results: Vec<Torrent>, max: usize) -> Vec<Torrent> {
+    let mut groups: Vec<(Option<String>, Vec<Torrent>)> = Vec::new();
+
+    for torrent in results {
+        let indexer = torrent.tracker_id.clone().or_else(|| torrent.tracker.clone());
+        match groups.iter_mut().find(|(name, _)| *name == indexer) {
+            Some((_, group)) => group.push(torrent),
+            None => groups.push((indexer, vec![torrent])),
+        }
+    }
+
+    let mut groups: Vec<_> = groups.into_iter().map(|(_, group)| group.into_iter()).collect();
+
+    // Round-robin: the best of every indexer first, then the second best...
+    let mut interleaved = Vec::new();
+    for _ in 0..max {
+        interleaved.extend(groups.iter_mut().filter_map(|group| group.next()));
+    }
+
+    interleaved
+}
+
 fn jackett_token() -> Result<String, String> {
     match env::var("JACKETT_TOKEN") {
         Ok(token) => Ok(token),
@@ -92,58 +237,196 @@ fn jackett_token() -> Result<String, String> {
     }
 }
 
-pub async fn request_jackett(query_string: String) -> Result<TelegramJackettResponse, String> {
+/// Indexer ids from JACKETT_PARALLEL_INDEXERS, queried one request each
+/// instead of through `all`, so a slow indexer doesn't hold up the others
+fn parallel_indexers() -> Vec<String> {
+    env::var("JACKETT_PARALLEL_INDEXERS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Lowercased info-hash of a magnet link, to spot the same torrent on several indexers
+fn info_hash(torrent: &Torrent) -> Option<String> {
+    let magnet = torrent.magnet_uri.as_ref()?;
+    let start = magnet.to_ascii_lowercase().find("xt=urn:btih:")? + "xt=urn:btih:".len();
+    let hash = magnet[start..].split('&').next()?.to_lowercase();
+    Some(hash).filter(|hash| !hash.is_empty())
+}
+
+/// Keep the best seeded copy of results sharing an info-hash
+fn dedupe_by_info_hash(mut results: Vec<Torrent>) -> Vec<Torrent> {
+    results.sort_by_key(|t| -t.seeders);
+
+    let mut seen = std::collections::HashSet::new();
+    results.retain(|t| info_hash(t).is_none_or(|hash| seen.insert(hash)));
+    results
+}
+
+async fn fetch_jackett<C>(
+    client: &client::Client<C>,
+    indexer: &str,
+    token: &str,
+    query_string: &str,
+    categories: &[u32],
+) -> Result<JackettResponse, String>
+where
+    C: client::connect::Connect + Clone + Send + Sync + 'static,
+{
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    serializer
+        .append_pair("apikey", token)
+        .append_pair("Query", query_string);
+    for category in categories {
+        serializer.append_pair("Category[]", &category.to_string());
+    }
+    let encoded_path: String = serializer.finish();
+
+    // Ids come from JACKETT_PARALLEL_INDEXERS, keep them to one path segment
+    let indexer = form_urlencoded::byte_serialize(indexer.as_bytes())
+        .collect::<String>()
+        .replace('+', "%20");
+    let url = format!("{}/api/v2.0/indexers/{}/results?{}", jackett_url(), indexer, encoded_path);
+
+    let uri = Uri::from_str(&url).map_err(|err| format!("Url misconfigured {}", err))?;
+
+    let jackett_response = client
+        .get(uri)
+        .await
+        .map_err(|err| format!("Jacket Response: {}", err))?;
+
+    let body = to_bytes(jackett_response.into_body())
+        .await
+        .map_err(|err| format!("From Jackett to body: {}", err))?;
+
+    let str = String::from_utf8_lossy(&body);
+    serde_json::from_str(&str).map_err(|err| format!("Not JSON {}", err))
+}
+
+/// Search every indexer, narrowed by the categories, seeders and title filters of `filters`
+///
+/// With JACKETT_PARALLEL_INDEXERS the listed indexers are searched
+/// concurrently and their results merged, otherwise Jackett's `all` is used.
+pub async fn request_jackett(query_string: String, filters: SearchFilters) -> Result<TelegramJackettResponse, String> {
     let https = hyper_rustls::HttpsConnector::with_native_roots();
     let client: client::Client<_> = client::Client::builder().build(https);
 
     let token = jackett_token()?;
+    let categories = filters.categories();
 
-    let encoded_path: String = form_urlencoded::Serializer::new(String::new())
-        .append_pair("apikey", token.as_str())
-        .append_pair("Query", query_string.as_str())
-        .finish();
+    let indexers = parallel_indexers();
+    let (results, failed, queried) = if indexers.is_empty() {
+        let formatted_body = fetch_jackett(&client, "all", &token, &query_string, &categories).await?;
+        if formatted_body.indexers.len() == 0 && formatted_body.results.len() == 0 {
+            return Err("Empty indexers. Please add one in your jackett configuration".to_string());
+        }
 
-    let url = [
-        jackett_url(),
-        String::from("/api/v2.0/indexers/all/results?"),
-        encoded_path,
-    ]
-    .join("");
+        let queried = formatted_body.indexers.len();
+        let failed = formatted_body.indexers.iter().filter(|indexer| indexer.failed()).count();
+        (formatted_body.results, failed, queried)
+    } else {
+        let responses = futures::future::join_all(
+            indexers
+                .iter()
+                .map(|indexer| fetch_jackett(&client, indexer, &token, &query_string, &categories)),
+        )
+        .await;
 
-    let uri = Uri::from_str(&url);
-    if let Err(err) = uri {
-        return Err(format!("Url misconfigured {}", err));
+        let mut results = Vec::new();
+        let mut failed = 0;
+        let mut first_error = None;
+        for (indexer, response) in indexers.iter().zip(responses) {
+            match response {
+                Ok(body) => {
+                    if body.indexers.iter().any(|indexer| indexer.failed()) {
+                        failed += 1;
+                    }
+                    results.extend(body.results);
+                }
+                Err(err) => {
+                    println!("Jackett indexer {}: {}", indexer, err);
+                    first_error.get_or_insert(format!("{}: {}", indexer, err));
+                    failed += 1;
+                }
+            }
+        }
+
+        // Not "no results", the search never reached an indexer
+        if failed == indexers.len() {
+            let reason = first_error.map(|err| format!(", {}", err)).unwrap_or_default();
+            return Err(format!("Every Jackett indexer failed{}", reason));
+        }
+
+        (dedupe_by_info_hash(results), failed, indexers.len())
+    };
+
+    if failed == 0 {
+        return rank_results(results, filters);
     }
 
-    let jackett_response = client.get(uri.unwrap()).await;
-    if let Err(err) = jackett_response {
-        return Err(format!("Jacket Response: {}", err));
+    match rank_results(results, filters) {
+        Ok(mut response) => {
+            response.failed_indexers = Some((failed, queried));
+            Ok(response)
+        }
+        Err(err) => Err(format!("{} {}", err, failed_indexers_note(failed, queried))),
     }
+}
 
-    let body: Body = jackett_response.unwrap().into_body();
-    let body = to_bytes(body).await;
+fn env_min_seeders() -> Option<i64> {
+    env::var("JACKETT_MIN_SEEDERS")
+        .ok()
+        .and_then(|val| val.trim().parse::<i64>().ok())
+        .filter(|min| *min > 0)
+}
 
-    if let Err(err) = body {
-        return Err(format!("From Jackett to body: {}", err));
+/// Blacklist, filter, sort and cap the raw results of any search backend
+///
+/// `filters.min_seeders` overrides JACKETT_MIN_SEEDERS for this search.
+pub(crate) fn rank_results(mut results: Vec<Torrent>, filters: SearchFilters) -> Result<TelegramJackettResponse, String> {
+    let blacklist = blacklisted_indexers();
+    if !blacklist.is_empty() {
+        results.retain(|t| !is_blacklisted(t, &blacklist));
     }
 
-    let new_body = body.unwrap();
-    let str = String::from_utf8_lossy(&new_body);
-
-    let v = serde_json::from_str(&str);
-    if let Err(err) = v {
-        return Err(format!("Not JSON {}", err.to_string()));
+    // Indexers don't always honour the categories asked for
+    if let Some(media) = &filters.media {
+        let ranges = category_ranges().of(media);
+        results.retain(|t| t.categories.is_empty() || in_ranges(&t.categories, ranges));
     }
 
-    let mut formatted_body: JackettResponse = v.unwrap();
-    if formatted_body.indexers.len() == 0 && formatted_body.results.len() == 0 {
-        return Err("Empty indexers. Please add one in your jackett configuration".to_string());
+    let title_filters = filters.title_filters();
+    if !title_filters.is_empty() {
+        let found = results.len();
+        results.retain(|t| filters.matches_title(&t.title));
+        if found > 0 && results.is_empty() {
+            return Err(format!("No results matching {}", title_filters.join(" ")));
+        }
     }
 
-    formatted_body.results.sort_by_key(|d1| -d1.seeders);
-    let torrents = formatted_body.results.into_iter().take(20).collect();
+    results.sort_by_key(|d1| -d1.seeders);
+    if let Some(min) = filters.min_seeders.or_else(env_min_seeders) {
+        let found = results.len();
+        results.retain(|t| t.seeders >= min);
+        if found > 0 && results.is_empty() {
+            return Err(format!("No results above {} seeders", min));
+        }
+    }
+    if let Some(max) = max_per_indexer() {
+        results = cap_per_indexer(results, max);
+    }
+    let total_results = results.len();
+    let torrents = results.into_iter().take(max_results()).collect();
 
-    let response = TelegramJackettResponse { torrents };
+    let response = TelegramJackettResponse {
+        torrents,
+        failed_indexers: None,
+        total_results,
+        media: filters.media,
+        searched_on: days_since_epoch(),
+    };
 
     if response.torrents.len() == 0 {
         return Err("No results were returned for your search".to_string());
@@ -152,35 +435,464 @@ pub async fn request_jackett(query_string: String) -> Result<TelegramJackettResp
     Ok(response)
 }
 
-pub fn format_telegram_response(response: TelegramJackettResponse) -> String {
-    let info = format_torrent(response);
+/// Value of the `name` attribute of an XML element
+fn xml_attribute(element: &str, name: &str) -> Option<String> {
+    let needle = format!(" {}=\"", name);
+    let start = element.find(&needle)? + needle.len();
+    let end = start + element[start..].find('"')?;
+    Some(decode_html_entities(element[start..end].to_string()))
+}
+
+/// The first `<tag ...>` of `xml` up to its `>`
+fn xml_element<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{} ", tag)).or_else(|| xml.find(&format!("<{}>", tag)))?;
+    let end = start + xml[start..].find('>')?;
+    Some(&xml[start..end])
+}
+
+/// Text of the first `<tag>` of `xml`
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let element = xml_element(xml, tag)?;
+    let start = xml.find(element)? + element.len() + 1;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(decode_html_entities(xml[start..end].trim().to_string()))
+}
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// "Sat, 01 Jan 2000 10:00:00 +0000" as "2000-01-01", the part `publish_age` reads
+fn rfc2822_date(date: &str) -> Option<String> {
+    let mut parts = date.split_whitespace().filter(|part| !part.ends_with(','));
+    let day: u32 = parts.next()?.parse().ok()?;
+    let month = parts.next()?.to_lowercase();
+    let month = MONTHS.iter().position(|name| month.starts_with(name))? + 1;
+    let year: u32 = parts.next()?.parse().ok()?;
+
+    Some(format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+/// Results of a Torznab RSS feed, in the shape of Jackett's JSON results
+fn parse_torznab(xml: &str) -> Vec<Torrent> {
+    xml.split("<item>")
+        .skip(1)
+        .filter_map(|item| item.split("</item>").next())
+        .filter_map(|item| {
+            let attributes: Vec<(String, String)> = item
+                .match_indices("<torznab:attr ")
+                .filter_map(|(start, _)| {
+                    let element = &item[start..start + item[start..].find('>')?];
+                    Some((xml_attribute(element, "name")?, xml_attribute(element, "value")?))
+                })
+                .collect();
+            let attribute = |name: &str| {
+                attributes
+                    .iter()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.clone())
+            };
+            let categories: Vec<i64> = attributes
+                .iter()
+                .filter(|(key, _)| key == "category")
+                .filter_map(|(_, value)| value.parse().ok())
+                .collect();
+            let indexer = xml_element(item, "jackettindexer");
+
+            serde_json::from_value(serde_json::json!({
+                "Title": xml_text(item, "title")?,
+                "Seeders": attribute("seeders").and_then(|value| value.parse::<i64>().ok()).unwrap_or(0),
+                "Peers": attribute("peers").and_then(|value| value.parse::<i64>().ok()),
+                "MagnetUri": attribute("magneturl"),
+                "Link": xml_text(item, "link"),
+                "Size": xml_text(item, "size").and_then(|size| size.parse::<u64>().ok()).unwrap_or(0),
+                "Category": categories,
+                "Tracker": xml_text(item, "jackettindexer"),
+                "TrackerId": indexer.and_then(|element| xml_attribute(element, "id")),
+                "PublishDate": xml_text(item, "pubDate").and_then(|date| rfc2822_date(&date)),
+            }))
+            .ok()
+        })
+        .collect()
+}
+
+async fn fetch_torznab<C>(
+    client: &client::Client<C>,
+    token: &str,
+    search_type: &str,
+    imdb_id: &str,
+) -> Result<Vec<Torrent>, String>
+where
+    C: client::connect::Connect + Clone + Send + Sync + 'static,
+{
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("apikey", token)
+        .append_pair("t", search_type)
+        .append_pair("imdbid", imdb_id)
+        .finish();
+    let url = format!("{}/api/v2.0/indexers/all/results/torznab/api?{}", jackett_url(), query);
+
+    let uri = Uri::from_str(&url).map_err(|err| format!("Url misconfigured {}", err))?;
+
+    let jackett_response = client
+        .get(uri)
+        .await
+        .map_err(|err| format!("Jacket Response: {}", err))?;
+
+    let body = to_bytes(jackett_response.into_body())
+        .await
+        .map_err(|err| format!("From Jackett to body: {}", err))?;
+    let body = String::from_utf8_lossy(&body);
+
+    // Torznab errors are an <error code=".." description=".."/> document
+    if let Some(description) = xml_element(&body, "error").and_then(|element| xml_attribute(element, "description")) {
+        return Err(format!("Jackett: {}", description));
+    }
+
+    Ok(parse_torznab(&body))
+}
+
+/// Search by IMDb id
+///
+/// Goes through Jackett's Torznab API with `imdbid`, as a movie and as a
+/// show, on the indexers that support it. Finding nothing is an error, the
+/// caller falls back to searching the title then.
+pub async fn request_jackett_by_imdb(imdb_id: String) -> Result<TelegramJackettResponse, String> {
+    let https = hyper_rustls::HttpsConnector::with_native_roots();
+    let client: client::Client<_> = client::Client::builder().build(https);
+
+    let token = jackett_token()?;
+
+    let (movies, shows) = futures::future::join(
+        fetch_torznab(&client, &token, "movie", &imdb_id),
+        fetch_torznab(&client, &token, "tvsearch", &imdb_id),
+    )
+    .await;
+
+    let mut results = Vec::new();
+    let mut errors = Vec::new();
+    for response in vec![movies, shows] {
+        match response {
+            Ok(found) => results.extend(found),
+            Err(err) => errors.push(err),
+        }
+    }
+
+    if results.is_empty() {
+        return Err(errors
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| format!("No results for {}", imdb_id)));
+    }
+
+    rank_results(dedupe_by_info_hash(results), SearchFilters::default())
+}
+
+/// How search results are listed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchFormat {
+    /// One line per result
+    Compact,
+    /// Size, seeders, peers, indexer and age on their own lines
+    Detailed,
+}
+
+const SEARCH_FORMATS: &[SearchFormat] = &[SearchFormat::Compact, SearchFormat::Detailed];
+
+impl SearchFormat {
+    pub fn parse(value: &str) -> Option<SearchFormat> {
+        match value.trim().to_lowercase().as_str() {
+            "compact" => Some(SearchFormat::Compact),
+            "detailed" => Some(SearchFormat::Detailed),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SearchFormat::Compact => "compact",
+            SearchFormat::Detailed => "detailed",
+        }
+    }
+}
+
+/// Format picked with /search-format in the chat, then SEARCH_FORMAT, then compact
+pub fn search_format(chat: i64) -> SearchFormat {
+    crate::prefs::prefs()
+        .search_formats
+        .get(&chat)
+        .and_then(|format| SearchFormat::parse(format))
+        .or_else(|| env::var("SEARCH_FORMAT").ok().and_then(|format| SearchFormat::parse(&format)))
+        .unwrap_or(SearchFormat::Compact)
+}
+
+pub fn format_telegram_response(response: TelegramJackettResponse, format: SearchFormat) -> String {
+    let info = format_torrent(response, format);
 
     format!("<pre>{}</pre>", info)
 }
 
-fn format_torrent(response: TelegramJackettResponse) -> String {
-    return response
-        .torrents
+/// Days since 1970-01-01 of a `YYYY-MM-DD` date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// How long ago a result was published, from the date at the start of
+/// Jackett's and Prowlarr's timestamps
+fn publish_age(publish_date: &str, today: i64) -> Option<String> {
+    let mut parts = publish_date.get(..10)?.split('-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+    if year < 1971 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let days = (today - days_from_civil(year, month, day)).max(0);
+    let (amount, unit) = match days {
+        0 => return Some("today".to_string()),
+        1..=59 => (days, "day"),
+        60..=729 => (days / 30, "month"),
+        _ => (days / 365, "year"),
+    };
+
+    Some(format!("{} {}{} ago", amount, unit, if amount == 1 { "" } else { "s" }))
+}
+
+fn days_since_epoch() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| (elapsed.as_secs() / 86400) as i64)
+        .unwrap_or(0)
+}
+
+// Titles and indexers are HTML-escaped for the <pre> block, replies are
+// matched after `decode_html_entities`
+fn format_torrent(response: TelegramJackettResponse, format: SearchFormat) -> String {
+    let mut note = response
+        .failed_indexers
+        .map(|(failed, queried)| format!("\n{}", failed_indexers_note(failed, queried)))
+        .unwrap_or_default();
+    if response.total_results > response.torrents.len() {
+        note.push_str(&format!(
+            "\n(showing top {} of {})",
+            response.torrents.len(),
+            response.total_results
+        ));
+    }
+
+    let list = match format {
+        SearchFormat::Compact => format_compact(&response.torrents),
+        SearchFormat::Detailed => response
+            .torrents
+            .iter()
+            .enumerate()
+            .map(|(i, t)| format_detailed(i + 1, t, response.searched_on))
+            .collect(),
+    };
+
+    list + &note
+}
+
+// Longer titles are cut so a result still fits on a phone screen
+const COMPACT_TITLE_CHARS: usize = 40;
+
+fn shorten_title(title: &str) -> String {
+    if title.chars().count() <= COMPACT_TITLE_CHARS {
+        return title.to_string();
+    }
+
+    let mut short: String = title.chars().take(COMPACT_TITLE_CHARS - 1).collect();
+    short.push('…');
+    short
+}
+
+fn pad(text: &str, width: usize) -> String {
+    " ".repeat(width.saturating_sub(text.chars().count()))
+}
+
+/// One result per line, in columns: position, title, size, seeders, peers
+/// and indexer
+fn format_compact(torrents: &[Torrent]) -> String {
+    let rows: Vec<[String; 5]> = torrents
         .iter()
         .enumerate()
-        .fold(String::from(""), |text, (i, t)| {
-            text + format!(
-                "{}. {} - {}B - {}\n",
-                i + 1,
-                t.title,
-                SizeFormatterSI::new(t.size),
-                t.seeders
-            )
-            .as_str()
-        });
+        .map(|(i, t)| {
+            [
+                format!("{}.", i + 1),
+                shorten_title(&t.title),
+                format!("{}B", SizeFormatterSI::new(t.size)),
+                format!("S:{}", t.seeders),
+                t.peers.map(|peers| format!("P:{}", peers)).unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    rows.iter()
+        .zip(torrents)
+        .map(|([position, title, size, seeders, peers], t)| {
+            let indexer = t
+                .tracker
+                .as_ref()
+                .map(|tracker| format!(" [{}]", escape_html(tracker)))
+                .unwrap_or_default();
+
+            // Escaped after padding, the entities don't take up any room
+            let line = format!(
+                "{}{} {}{} {}{} {}{} {}{}{}",
+                position,
+                pad(position, widths[0]),
+                escape_html(title),
+                pad(title, widths[1]),
+                pad(size, widths[2]),
+                size,
+                pad(seeders, widths[3]),
+                seeders,
+                pad(peers, widths[4]),
+                peers,
+                indexer
+            );
+            format!("{}\n", line.trim_end())
+        })
+        .collect()
+}
+
+fn format_detailed(position: usize, t: &Torrent, today: i64) -> String {
+    let mut text = format!(
+        "{}. {}\n   Size: {}B\n   Seeders: {}\n",
+        position,
+        escape_html(&t.title),
+        SizeFormatterSI::new(t.size),
+        t.seeders
+    );
+
+    if let Some(peers) = t.peers {
+        text.push_str(&format!("   Peers: {}\n", peers));
+    }
+
+    if let Some(tracker) = &t.tracker {
+        text.push_str(&format!("   Indexer: {}\n", escape_html(tracker)));
+    }
+    if let Some(age) = t.publish_date.as_deref().and_then(|date| publish_age(date, today)) {
+        text.push_str(&format!("   Age: {}\n", age));
+    }
+
+    text
 }
 
-fn is_movie(categories: Vec<i64>) -> bool {
-    return categories.iter().any(|c| c >= &2000 && c < &3000);
+type CategoryRanges = Vec<(i64, i64)>;
+
+/// Torznab categories counted as each media
+pub struct Categories {
+    movie: CategoryRanges,
+    tv: CategoryRanges,
+    anime: CategoryRanges,
 }
 
-fn is_tv_show(categories: Vec<i64>) -> bool {
-    return categories.iter().any(|c| c >= &3000 && c < &4000);
+static CATEGORY_RANGES: OnceLock<Categories> = OnceLock::new();
+
+/// Parse "2000-2999,5070" into inclusive ranges, a single number is a range of one
+fn parse_category_ranges(value: &str) -> Result<CategoryRanges, String> {
+    value
+        .split(',')
+        .map(|range| range.trim())
+        .filter(|range| !range.is_empty())
+        .map(|range| {
+            let invalid = || format!("Invalid category range: {}", range);
+            let (start, end) = match range.split_once('-') {
+                Some((start, end)) => (start.trim(), end.trim()),
+                None => (range, range),
+            };
+            let start = start.parse::<i64>().map_err(|_| invalid())?;
+            let end = end.parse::<i64>().map_err(|_| invalid())?;
+            if start > end {
+                return Err(invalid());
+            }
+            Ok((start, end))
+        })
+        .collect()
+}
+
+fn load_category_ranges(name: &str, default: (i64, i64)) -> CategoryRanges {
+    match env::var(name) {
+        Ok(value) => parse_category_ranges(&value).unwrap_or_else(|e| {
+            println!("{} in {}. Using the default categories", e, name);
+            vec![default]
+        }),
+        Err(_) => vec![default],
+    }
+}
+
+/// Categories from MOVIE_CATEGORY_RANGES, TV_CATEGORY_RANGES and
+/// ANIME_CATEGORY_RANGES, loaded once
+pub fn category_ranges() -> &'static Categories {
+    CATEGORY_RANGES.get_or_init(|| Categories {
+        movie: load_category_ranges("MOVIE_CATEGORY_RANGES", (2000, 2999)),
+        tv: load_category_ranges("TV_CATEGORY_RANGES", (3000, 3999)),
+        anime: load_category_ranges("ANIME_CATEGORY_RANGES", (5070, 5070)),
+    })
+}
+
+impl Categories {
+    /// Ranges counted as `media`
+    pub fn of(&self, media: &Media) -> &[(i64, i64)] {
+        match media {
+            Media::TV => &self.tv,
+            Media::Movie => &self.movie,
+            Media::Anime => &self.anime,
+        }
+    }
+}
+
+fn in_ranges(categories: &[i64], ranges: &[(i64, i64)]) -> bool {
+    categories
+        .iter()
+        .any(|c| ranges.iter().any(|(start, end)| c >= start && c <= end))
+}
+
+fn count_in_ranges(categories: &[i64], ranges: &[(i64, i64)]) -> usize {
+    categories
+        .iter()
+        .filter(|c| ranges.iter().any(|(start, end)| *c >= start && *c <= end))
+        .count()
+}
+
+/// What a result's categories say about its media
+#[derive(Debug, PartialEq)]
+enum CategoryGuess {
+    Tv,
+    Movie,
+    Anime,
+    /// As many TV as movie categories
+    Ambiguous,
+    Unknown,
+}
+
+/// Anime categories win, otherwise the side with more matching categories
+fn classify_categories(categories: &[i64]) -> CategoryGuess {
+    let ranges = category_ranges();
+    if in_ranges(categories, &ranges.anime) {
+        return CategoryGuess::Anime;
+    }
+
+    let tv = count_in_ranges(categories, &ranges.tv);
+    let movie = count_in_ranges(categories, &ranges.movie);
+
+    match (tv, movie) {
+        (0, 0) => CategoryGuess::Unknown,
+        (tv, movie) if tv > movie => CategoryGuess::Tv,
+        (tv, movie) if movie > tv => CategoryGuess::Movie,
+        _ => CategoryGuess::Ambiguous,
+    }
 }
 
 fn is_debug_enabled() -> bool {
@@ -252,20 +964,10 @@ pub async fn dispatch_from_reply(
     index: u16,
     reply_text: String,
     torrents: Vec<TelegramJackettResponse>,
-) -> Result<(Option<Media>, TorrentLocation), String> {
+) -> Result<(Option<Media>, TorrentLocation, bool), String> {
     let real_index = index - 1;
 
-    let jackett = torrents.clone().into_iter().find(|response| {
-        let decoded_response = decode_html_entities(
-            format_torrent(response.clone())
-                .split_whitespace()
-                .collect::<String>()
-        );
-
-        return decoded_response == reply_text.split_whitespace().collect::<String>();
-    });
-
-    match jackett {
+    match find_response(&reply_text, torrents) {
         Some(jackett) => {
             let torrent = jackett.torrents.iter().nth(real_index.into());
 
@@ -298,6 +1000,13 @@ pub async fn dispatch_from_reply(
                                     loc.content.len()
                                 );
                             }
+                            // Indexers sometimes answer with an error page instead of a .torrent
+                            if !loc.is_magnet {
+                                base64::decode(&loc.content)
+                                    .map_err(|e| e.to_string())
+                                    .and_then(|bytes| crate::torrentfile::parse_torrent_metadata(&bytes))
+                                    .map_err(|e| format!("The indexer didn't send a valid .torrent: {}", e))?;
+                            }
                             location = loc;
                         } else {
                             return Err(result.err().unwrap());
@@ -306,13 +1015,31 @@ pub async fn dispatch_from_reply(
                         return Err("Torrent without URI. Please select another".to_string());
                     }
 
-                    if is_tv_show(torrent.clone().categories) {
-                        return Ok((Some(Media::TV), location));
-                    } else if is_movie(torrent.clone().categories) {
-                        return Ok((Some(Media::Movie), location));
-                    } else {
-                        return Ok((None, location));
+                    // /search-tv and /search-movie already settled it
+                    if let Some(media) = jackett.media.clone() {
+                        return Ok((Some(media), location, false));
+                    }
+
+                    let guess = classify_categories(&torrent.categories);
+                    match guess {
+                        CategoryGuess::Anime => return Ok((Some(Media::Anime), location, false)),
+                        CategoryGuess::Tv => return Ok((Some(Media::TV), location, false)),
+                        CategoryGuess::Movie => return Ok((Some(Media::Movie), location, false)),
+                        CategoryGuess::Ambiguous | CategoryGuess::Unknown => {}
                     }
+
+                    // Categories don't settle it, peek at the files of a .torrent
+                    // instead. Without a guess the user is asked for tv or movie
+                    let guessed = if location.is_magnet {
+                        None
+                    } else {
+                        base64::decode(&location.content)
+                            .ok()
+                            .and_then(|bytes| crate::torrentfile::torrent_file_names(&bytes).ok())
+                            .and_then(|names| crate::torrentfile::guess_media_from_files(&names))
+                    };
+                    // A guess over conflicting categories still gets confirmed
+                    Ok((guessed, location, guess == CategoryGuess::Ambiguous))
                 }
                 None => Err("No torrent for the given index".to_string()),
             }
@@ -321,13 +1048,449 @@ pub async fn dispatch_from_reply(
     }
 }
 
+/// Search response whose formatted text is the message replied to
+///
+/// Every format is tried, the chat may have switched since the search.
+fn find_response(reply_text: &str, torrents: Vec<TelegramJackettResponse>) -> Option<TelegramJackettResponse> {
+    let position = response_position(reply_text, &torrents)?;
+    torrents.into_iter().nth(position)
+}
+
+/// Position in `torrents` of the search listed in `reply_text`
+pub fn response_position(reply_text: &str, torrents: &[TelegramJackettResponse]) -> Option<usize> {
+    let reply_text = reply_text.split_whitespace().collect::<String>();
+
+    torrents.iter().position(|response| {
+        SEARCH_FORMATS.iter().any(|format| {
+            let decoded_response = decode_html_entities(
+                format_torrent(response.clone(), *format)
+                    .split_whitespace()
+                    .collect::<String>()
+            );
+
+            decoded_response == reply_text
+        })
+    })
+}
+
+/// Size of the result at `index` and the media its categories point to,
+/// TV and movie when they don't settle it
+pub fn result_size_and_media(
+    index: u16,
+    reply_text: String,
+    torrents: Vec<TelegramJackettResponse>,
+) -> Result<(u64, Vec<Media>), String> {
+    let jackett = find_response(&reply_text, torrents).ok_or("Couldn't find torrent in the list")?;
+    check_index(index.into(), jackett.torrents.len())?;
+    let torrent = &jackett.torrents[usize::from(index) - 1];
+
+    if let Some(media) = jackett.media.clone() {
+        return Ok((torrent.size, vec![media]));
+    }
+
+    let media = match classify_categories(&torrent.categories) {
+        CategoryGuess::Tv => vec![Media::TV],
+        CategoryGuess::Movie => vec![Media::Movie],
+        CategoryGuess::Anime => vec![Media::Anime],
+        CategoryGuess::Ambiguous | CategoryGuess::Unknown => vec![Media::TV, Media::Movie],
+    };
+
+    Ok((torrent.size, media))
+}
+
+/// Category each result of the search in `reply_text` would be added with,
+/// without adding anything
+///
+/// Unknown and ambiguous results are the ones a pick asks tv/movie for,
+/// unless the files of a .torrent settle it.
+pub fn classify_results(reply_text: &str, torrents: Vec<TelegramJackettResponse>) -> Result<String, String> {
+    let jackett = find_response(reply_text, torrents).ok_or("Couldn't find torrent in the list")?;
+
+    let mut report = String::from("🏷️ Categories picks would get:\n");
+    for (i, t) in jackett.torrents.iter().enumerate() {
+        let category = match (&jackett.media, classify_categories(&t.categories)) {
+            (Some(media), _) => media.label(),
+            (None, CategoryGuess::Tv) => Media::TV.label(),
+            (None, CategoryGuess::Movie) => Media::Movie.label(),
+            (None, CategoryGuess::Anime) => Media::Anime.label(),
+            (None, CategoryGuess::Ambiguous) => "⚖️ Ambiguous, asks",
+            (None, CategoryGuess::Unknown) => "❓ Unknown, asks",
+        };
+        report.push_str(&format!("\n{}. {} - {}", i + 1, category, shorten_title(&t.title)));
+    }
+
+    Ok(report)
+}
+
 fn decode_html_entities(input: String) -> String {
     input
         .replace("&nbsp;", " ")
-        .replace("&amp;", "&")
         .replace("&lt;", "<")
         .replace("&gt;", ">")
         .replace("&quot;", "\"")
         .replace("&apos;", "'")
         .replace("&#039;", "'")
+        // Last, so an escaped "&lt;" in a title stays literal
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn torrent(title: &str) -> Torrent {
+        Torrent {
+            seeders: 10,
+            magnet_uri: Some("magnet:?xt=urn:btih:abc".to_string()),
+            title: title.to_string(),
+            categories: vec![5000],
+            size: 1_000_000,
+            torrent_url: None,
+            tracker: Some("Indexer".to_string()),
+            tracker_id: Some("indexer".to_string()),
+            publish_date: None,
+            peers: Some(15),
+        }
+    }
+
+    #[test]
+    fn prowlarr_results_deserialize() {
+        let payload = serde_json::json!({
+            "title": "The Matrix 1999 1080p",
+            "size": 2_000_000_000u64,
+            "seeders": 42,
+            "magnetUrl": "magnet:?xt=urn:btih:abc",
+            "downloadUrl": "http://prowlarr/download/1",
+            "indexer": "SomeIndexer",
+            "indexerId": 7,
+            "categories": [{"id": 2000, "name": "Movies"}, {"id": 2040, "name": "Movies/HD"}]
+        });
+
+        let torrent: Torrent = serde_json::from_value(payload).unwrap();
+
+        assert_eq!(torrent.title, "The Matrix 1999 1080p");
+        assert_eq!(torrent.seeders, 42);
+        assert_eq!(torrent.magnet_uri.as_deref(), Some("magnet:?xt=urn:btih:abc"));
+        assert_eq!(torrent.torrent_url.as_deref(), Some("http://prowlarr/download/1"));
+        assert_eq!(torrent.tracker.as_deref(), Some("SomeIndexer"));
+        assert_eq!(torrent.tracker_id.as_deref(), Some("7"));
+        assert_eq!(torrent.categories, vec![2000, 2040]);
+    }
+
+    #[test]
+    fn missing_result_fields_use_defaults() {
+        let torrent: Torrent = serde_json::from_value(serde_json::json!({ "Title": "Bare" })).unwrap();
+
+        assert_eq!(torrent.title, "Bare");
+        assert_eq!(torrent.seeders, 0);
+        assert!(torrent.categories.is_empty());
+        assert!(torrent.magnet_uri.is_none());
+    }
+
+    #[test]
+    fn search_results_note_failed_indexers() {
+        let response = TelegramJackettResponse {
+            torrents: vec![torrent("The Matrix")],
+            failed_indexers: Some((3, 8)),
+            total_results: 1,
+            media: None,
+            searched_on: 0,
+        };
+
+        assert!(format_telegram_response(response, SearchFormat::Compact).ends_with("(3/8 indexers failed)</pre>"));
+    }
+
+    #[test]
+    fn min_seeders_filter_has_its_own_error() {
+        let mut popular = torrent("Popular");
+        popular.seeders = 50;
+        let min_20 = SearchFilters {
+            min_seeders: Some(20),
+            ..Default::default()
+        };
+
+        let response = rank_results(vec![torrent("Obscure"), popular], min_20.clone()).unwrap();
+        assert_eq!(response.torrents.len(), 1);
+        assert_eq!(response.torrents[0].title, "Popular");
+
+        assert_eq!(
+            rank_results(vec![torrent("Obscure")], min_20.clone()).err().unwrap(),
+            "No results above 20 seeders"
+        );
+        assert_eq!(
+            rank_results(Vec::new(), min_20).err().unwrap(),
+            "No results were returned for your search"
+        );
+    }
+
+    #[test]
+    fn title_filters_keep_the_resolution_and_drop_cams() {
+        let filters = SearchFilters {
+            resolution: Some("1080p"),
+            exclude_cam: true,
+            ..Default::default()
+        };
+        let results = vec![
+            torrent("Dune.2021.1080p.WEB-DL"),
+            torrent("Dune.2021.2160p.UHD"),
+            torrent("Dune.2021.1080p.HDCAM"),
+            torrent("Dune 2021 1080p CAMRip"),
+            torrent("Dune.2021.1080p.HDTS.x264"),
+            torrent("Dune.2021.1080p.TS"),
+            // Only whole tags count, not words that happen to contain them
+            torrent("The.American.2010.1080p.BluRay"),
+            torrent("Camelot.S01.1080p.WEB"),
+            torrent("Scams.2019.1080p.WEBRip"),
+        ];
+
+        let response = rank_results(results, filters.clone()).unwrap();
+        let titles: Vec<&str> = response.torrents.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Dune.2021.1080p.WEB-DL",
+                "The.American.2010.1080p.BluRay",
+                "Camelot.S01.1080p.WEB",
+                "Scams.2019.1080p.WEBRip"
+            ]
+        );
+
+        assert_eq!(
+            rank_results(vec![torrent("Dune.2021.720p.CAM")], filters).err().unwrap(),
+            "No results matching 1080p -cam"
+        );
+    }
+
+    #[test]
+    fn media_searches_keep_to_their_categories() {
+        let filters = SearchFilters {
+            media: Some(Media::TV),
+            ..Default::default()
+        };
+        assert_eq!(filters.categories(), vec![3000]);
+
+        let mut show = torrent("Show");
+        show.categories = vec![3040];
+        let mut film = torrent("Film");
+        film.categories = vec![2040];
+        let mut bare = torrent("Bare");
+        bare.categories = Vec::new();
+
+        let response = rank_results(vec![show, film, bare], filters).unwrap();
+        let titles: Vec<&str> = response.torrents.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, vec!["Show", "Bare"]);
+        assert_eq!(response.media, Some(Media::TV));
+    }
+
+    #[test]
+    fn classify_lists_the_category_of_each_result() {
+        let mut movie = torrent("Film");
+        movie.categories = vec![2040];
+        let mut both = torrent("Both");
+        both.categories = vec![2040, 3040];
+        let response = TelegramJackettResponse {
+            torrents: vec![movie, both, torrent("Other")],
+            failed_indexers: None,
+            total_results: 3,
+            media: None,
+            searched_on: 0,
+        };
+        let reply = decode_html_entities(format_torrent(response.clone(), SearchFormat::Compact));
+
+        let report = classify_results(&reply, vec![response]).unwrap();
+        let lines: Vec<&str> = report.lines().skip(2).collect();
+        assert_eq!(
+            lines,
+            vec!["1. 🎬 Movie - Film", "2. ⚖️ Ambiguous, asks - Both", "3. ❓ Unknown, asks - Other"]
+        );
+    }
+
+    #[test]
+    fn truncated_results_note_the_total() {
+        let response = TelegramJackettResponse {
+            torrents: vec![torrent("The Office"), torrent("The Office UK")],
+            failed_indexers: None,
+            total_results: 35,
+            media: None,
+            searched_on: 0,
+        };
+
+        assert!(format_torrent(response, SearchFormat::Compact).ends_with("\n(showing top 2 of 35)"));
+    }
+
+    #[test]
+    fn categories_are_classified_by_majority() {
+        assert_eq!(classify_categories(&[2000, 2040]), CategoryGuess::Movie);
+        assert_eq!(classify_categories(&[3000]), CategoryGuess::Tv);
+        assert_eq!(classify_categories(&[2000, 3000, 3030]), CategoryGuess::Tv);
+        assert_eq!(classify_categories(&[2000, 3000]), CategoryGuess::Ambiguous);
+        assert_eq!(classify_categories(&[5070, 2000]), CategoryGuess::Anime);
+        assert_eq!(classify_categories(&[8000]), CategoryGuess::Unknown);
+    }
+
+    #[test]
+    fn category_ranges_parse_ranges_and_single_categories() {
+        assert_eq!(
+            parse_category_ranges("2000-2999, 5070").unwrap(),
+            vec![(2000, 2999), (5070, 5070)]
+        );
+        assert!(parse_category_ranges("3000-2000").is_err());
+        assert!(parse_category_ranges("anime").is_err());
+    }
+
+    #[test]
+    fn search_results_escape_html_in_titles() {
+        let response = TelegramJackettResponse {
+            torrents: vec![torrent("Tom & Jerry <3")],
+            failed_indexers: None,
+            total_results: 1,
+            media: None,
+            searched_on: 0,
+        };
+
+        let text = format_telegram_response(response.clone(), SearchFormat::Compact);
+
+        assert!(text.contains("Tom &amp; Jerry &lt;3"));
+        assert!(!text.contains("<3"));
+        // What the user sees in the reply matches the stored list again
+        assert!(decode_html_entities(format_torrent(response, SearchFormat::Compact)).contains("Tom & Jerry <3"));
+    }
+
+    #[test]
+    fn compact_format_aligns_columns_and_cuts_long_titles() {
+        let mut long = torrent("The.Matrix.1999.1080p.BluRay.x264.DTS-HD.MA.5.1-GROUP");
+        long.seeders = 142;
+        let mut bare = torrent("Short");
+        bare.peers = None;
+        bare.tracker = None;
+
+        let text = format_compact(&[long, bare]);
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert!(lines[0].starts_with("1. The.Matrix.1999.1080p.BluRay.x264.DTS-H… "));
+        assert!(lines[0].ends_with(" S:142 P:15 [Indexer]"));
+        assert!(lines[1].starts_with("2. Short "));
+        assert!(lines[1].ends_with("  S:10"));
+        // Seeders end in the same column on every line
+        let seeders_end = lines[0][..lines[0].find("S:142").unwrap()].chars().count() + "S:142".len();
+        assert_eq!(seeders_end, lines[1].chars().count());
+    }
+
+    #[test]
+    fn detailed_format_puts_each_field_on_its_own_line() {
+        let mut result = torrent("The Matrix");
+        result.publish_date = Some("2000-01-01T10:00:00Z".to_string());
+        let response = TelegramJackettResponse {
+            torrents: vec![result],
+            failed_indexers: None,
+            total_results: 1,
+            media: None,
+            searched_on: days_from_civil(2001, 1, 1),
+        };
+
+        let compact = format_torrent(response.clone(), SearchFormat::Compact);
+        assert_eq!(compact.lines().count(), 1);
+        assert!(compact.ends_with(" S:10 P:15 [Indexer]\n"));
+
+        let detailed = format_torrent(response.clone(), SearchFormat::Detailed);
+        let lines: Vec<&str> = detailed.lines().collect();
+        assert_eq!(lines[0], "1. The Matrix");
+        assert!(lines[1].starts_with("   Size: "));
+        assert_eq!(lines[2], "   Seeders: 10");
+        assert_eq!(lines[3], "   Peers: 15");
+        assert_eq!(lines[4], "   Indexer: Indexer");
+        // Counted from the day of the search, not the day of the reply
+        assert_eq!(lines[5], "   Age: 12 months ago");
+
+        // Replies to either format find the search again
+        let reply = decode_html_entities(detailed);
+        assert!(find_response(&reply, vec![response]).is_some());
+    }
+
+    #[test]
+    fn publish_age_counts_days_months_and_years() {
+        let today = days_from_civil(2024, 3, 1);
+
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(publish_age("2024-03-01T08:00:00+00:00", today).as_deref(), Some("today"));
+        assert_eq!(publish_age("2024-02-29", today).as_deref(), Some("1 day ago"));
+        assert_eq!(publish_age("2023-12-01T00:00:00Z", today).as_deref(), Some("3 months ago"));
+        assert_eq!(publish_age("2021-01-01", today).as_deref(), Some("3 years ago"));
+        assert_eq!(publish_age("0001-01-01T00:00:00", today), None);
+        assert_eq!(publish_age("yesterday", today), None);
+    }
+
+    #[test]
+    fn parallel_results_are_deduped_by_info_hash() {
+        let mut less_seeded = torrent("Show S01E01 on another indexer");
+        less_seeded.magnet_uri = Some("magnet:?xt=urn:btih:ABC&dn=Show".to_string());
+        less_seeded.seeders = 3;
+        let mut other = torrent("Other");
+        other.magnet_uri = Some("magnet:?xt=urn:btih:def".to_string());
+        let mut no_magnet = torrent("Link only");
+        no_magnet.magnet_uri = None;
+
+        let results = dedupe_by_info_hash(vec![
+            less_seeded,
+            torrent("Show S01E01"),
+            other,
+            no_magnet.clone(),
+            no_magnet,
+        ]);
+
+        let titles: Vec<&str> = results.iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(titles, vec!["Show S01E01", "Other", "Link only", "Link only"]);
+    }
+
+    #[test]
+    fn torznab_items_become_results() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:torznab="http://torznab.com/schemas/2015/feed">
+  <channel>
+    <title>AggregateSearch</title>
+    <item>
+      <title>The Matrix 1999 1080p &amp; extras</title>
+      <jackettindexer id="1337x">1337x</jackettindexer>
+      <size>2000000000</size>
+      <link>http://jackett/dl/1337x/?jackett_apikey=key&amp;path=abc</link>
+      <pubDate>Sat, 01 Jan 2000 10:00:00 +0000</pubDate>
+      <category>2000</category>
+      <torznab:attr name="category" value="2000" />
+      <torznab:attr name="category" value="2040" />
+      <torznab:attr name="seeders" value="42" />
+      <torznab:attr name="peers" value="50" />
+      <torznab:attr name="magneturl" value="magnet:?xt=urn:btih:abc&amp;dn=The+Matrix" />
+    </item>
+    <item>
+      <title>Bare</title>
+    </item>
+  </channel>
+</rss>"#;
+
+        let results = parse_torznab(xml);
+        assert_eq!(results.len(), 2);
+
+        let matrix = &results[0];
+        assert_eq!(matrix.title, "The Matrix 1999 1080p & extras");
+        assert_eq!(matrix.tracker.as_deref(), Some("1337x"));
+        assert_eq!(matrix.tracker_id.as_deref(), Some("1337x"));
+        assert_eq!(matrix.size, 2_000_000_000);
+        assert_eq!(matrix.categories, vec![2000, 2040]);
+        assert_eq!(matrix.seeders, 42);
+        assert_eq!(matrix.peers, Some(50));
+        assert_eq!(matrix.magnet_uri.as_deref(), Some("magnet:?xt=urn:btih:abc&dn=The+Matrix"));
+        assert_eq!(matrix.torrent_url.as_deref(), Some("http://jackett/dl/1337x/?jackett_apikey=key&path=abc"));
+        assert_eq!(matrix.publish_date.as_deref(), Some("2000-01-01"));
+
+        assert_eq!(results[1].seeders, 0);
+        assert!(results[1].magnet_uri.is_none());
+        assert!(parse_torznab(r#"<error code="201" description="Incorrect parameter" />"#).is_empty());
+    }
+
+    #[test]
+    fn rfc2822_dates_keep_the_day() {
+        assert_eq!(rfc2822_date("Sat, 01 Jan 2000 10:00:00 +0000").as_deref(), Some("2000-01-01"));
+        assert_eq!(rfc2822_date("5 Dec 2023 08:00:00 GMT").as_deref(), Some("2023-12-05"));
+        assert_eq!(rfc2822_date("yesterday"), None);
+    }
 }
@@ -1,27 +1,33 @@
 use std::env;
 use std::process::exit;
 
-use futures::lock::Mutex;
 use futures::StreamExt;
 
+mod background;
 mod imdb;
 mod jackett;
+mod notifier;
+mod prefs;
+mod prowlarr;
+mod qbittorrent;
 mod restructure;
+mod search;
+mod selection;
 mod telegram;
+mod torrent_client;
+mod torrentfile;
 mod transmission;
 
-use telegram::handle_message;
-use telegram_bot::MessageId;
+use telegram::{handle_callback, handle_document, handle_message, status_labels, version_info, warn_without_admins, BotState};
 
 use std::error::Error;
-use std::sync::Arc;
+use std::time::Duration;
 use telegram_bot::types::{MessageKind, UpdateKind};
 use telegram_bot::{AllowedUpdate, Api, UpdatesStream};
 
-use crate::jackett::TelegramJackettResponse;
-
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -45,49 +51,101 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .unwrap();
     }
 
-    let responses: Arc<Mutex<Vec<TelegramJackettResponse>>> = Arc::new(Mutex::new(Vec::new()));
-    let torrent_lists: Arc<Mutex<Vec<(Vec<i64>, String, MessageId)>>> = Arc::new(Mutex::new(Vec::new()));
-    let file_lists:   Arc<Mutex<Vec<(Vec<String>, String, MessageId)>>> = Arc::new(Mutex::new(Vec::new()));
-    let restructure_plans: Arc<Mutex<Vec<(restructure::RestructurePlan, String, MessageId)>>> = Arc::new(Mutex::new(Vec::new()));
+    println!("{}", version_info());
+    // Load status label and category overrides now so config errors show up at startup
+    status_labels();
+    jackett::category_ranges();
+    transmission::warn_overlapping_media_paths();
+    transmission::ensure_media_dirs();
+    warn_without_admins();
+
+    let state = BotState::new();
 
     let telegram_token = env::var("TELEGRAM_BOT_TOKEN").expect("TELEGRAM_BOT_TOKEN not set");
 
     let api = Api::new(telegram_token);
-    let mut stream = UpdatesStream::new(&api);
-    stream.allowed_updates(&[AllowedUpdate::Message]);
-
-    while let Some(update) = stream.next().await {
-        let Ok(update) = update else {
-            continue;
-        };
-
-        let UpdateKind::Message(message) = update.kind else {
-            continue;
-        };
-
-        let MessageKind::Text { ref data, .. } = message.kind else {
-            continue;
-        };
-
-        let text = data.split_whitespace().map(|s| s.to_string()).collect();
-        let cloned_api = api.clone();
-        let mut shared_responses = Arc::clone(&responses);
-        let mut shared_torrent_lists = Arc::clone(&torrent_lists);
-        let mut shared_file_lists = Arc::clone(&file_lists);
-        let mut shared_restructure_plans = Arc::clone(&restructure_plans);
-        let data_cloned = data.clone();
-
-        tokio::spawn(async move {
-            let handle = handle_message(&cloned_api, &message, text, &mut shared_responses, &mut shared_torrent_lists, &mut shared_file_lists, &mut shared_restructure_plans);
-            if (handle.await).is_err() {
-                let error_msg = format!(
-                    "Errors should be handled in handle_message {:?}",
-                    data_cloned.clone()
-                );
-                println!("{}", error_msg);
+    notifier::spawn_notifier(api.clone());
+    notifier::spawn_auto_delete(api.clone());
+    notifier::spawn_pick_sweeper(api.clone(), state.uncategorized_picks.clone());
+    notifier::spawn_watch_poller(api.clone());
+
+    // On stream errors the stream is rebuilt after a growing delay instead of
+    // exiting, so the shared state survives Telegram outages
+    let mut backoff = Duration::from_secs(1);
+    let mut last_update_id: Option<i64> = None;
+
+    loop {
+        let mut stream = UpdatesStream::new(&api);
+        stream.allowed_updates(&[AllowedUpdate::Message, AllowedUpdate::CallbackQuery]);
+
+        while let Some(update) = stream.next().await {
+            let update = match update {
+                Ok(update) => {
+                    backoff = Duration::from_secs(1);
+                    update
+                }
+                Err(err) => {
+                    println!("Error on telegram update stream: {}", err);
+                    break;
+                }
             };
-        });
-    }
 
-    Ok(())
+            // A rebuilt stream can redeliver updates that weren't confirmed yet
+            if last_update_id.is_some_and(|last| update.id <= last) {
+                continue;
+            }
+            last_update_id = Some(update.id);
+
+            let message = match update.kind {
+                UpdateKind::Message(message) => message,
+                UpdateKind::CallbackQuery(query) => {
+                    let cloned_api = api.clone();
+                    let mut shared_state = state.clone();
+
+                    tokio::spawn(async move {
+                        if handle_callback(&cloned_api, query, &mut shared_state).await.is_err() {
+                            println!("Errors should be handled in handle_callback");
+                        }
+                    });
+                    continue;
+                }
+                _ => continue,
+            };
+
+            if let MessageKind::Document { .. } = message.kind {
+                let cloned_api = api.clone();
+
+                tokio::spawn(async move {
+                    if handle_document(&cloned_api, &message).await.is_err() {
+                        println!("Errors should be handled in handle_document");
+                    }
+                });
+                continue;
+            }
+
+            let MessageKind::Text { ref data, .. } = message.kind else {
+                continue;
+            };
+
+            let text = data.split_whitespace().map(|s| s.to_string()).collect();
+            let cloned_api = api.clone();
+            let mut shared_state = state.clone();
+            let data_cloned = data.clone();
+
+            tokio::spawn(async move {
+                let handle = handle_message(&cloned_api, &message, text, &mut shared_state);
+                if (handle.await).is_err() {
+                    let error_msg = format!(
+                        "Errors should be handled in handle_message {:?}",
+                        data_cloned.clone()
+                    );
+                    println!("{}", error_msg);
+                };
+            });
+        }
+
+        println!("Reconnecting to telegram in {}s", backoff.as_secs());
+        tokio::time::sleep(backoff).await;
+        backoff = std::cmp::min(backoff * 2, MAX_BACKOFF);
+    }
 }
@@ -0,0 +1,525 @@
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use telegram_bot::prelude::*;
+use telegram_bot::{Api, ChatId};
+
+use crate::background::background_permit;
+use crate::jackett::TorrentLocation;
+use crate::prefs::{prefs, Prefs};
+use crate::telegram::run_watch;
+use crate::torrent_client::{require_transmission, torrent_backend, TorrentClient};
+use crate::transmission::{get_recently_active, get_torrents, Media, Torrent};
+
+const DEFAULT_NOTIFY_INTERVAL: u64 = 60;
+// How far back Transmission's recently-active set reaches
+const RECENTLY_ACTIVE_WINDOW: Duration = Duration::from_secs(60);
+const DEFAULT_PICK_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+const PICK_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+// Download speed samples kept per torrent for the /status sparkline
+const SPEED_SAMPLES: usize = 10;
+const SPARK_LEVELS: &[char] = &['▁', '▂', '▄', '▆', '█'];
+
+static SPEED_HISTORY: OnceLock<Mutex<HashMap<i64, VecDeque<i64>>>> = OnceLock::new();
+
+fn speed_history() -> &'static Mutex<HashMap<i64, VecDeque<i64>>> {
+    SPEED_HISTORY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// Torrents missing from a recently-active poll had nothing going on, so
+// they get a zero sample
+fn record_speeds(active: &[Torrent], removed: &[i64]) {
+    let mut history = speed_history().lock().unwrap();
+
+    for id in removed {
+        history.remove(id);
+    }
+
+    let mut rates: HashMap<i64, i64> = active.iter().map(|t| (t.id, t.rate_download)).collect();
+    for (id, samples) in history.iter_mut() {
+        samples.push_back(rates.remove(id).unwrap_or(0));
+    }
+    for (id, rate) in rates {
+        history.insert(id, VecDeque::from(vec![rate]));
+    }
+
+    for samples in history.values_mut() {
+        while samples.len() > SPEED_SAMPLES {
+            samples.pop_front();
+        }
+    }
+}
+
+fn sparkline(samples: &VecDeque<i64>) -> String {
+    let max = samples.iter().copied().max().unwrap_or(0);
+
+    samples
+        .iter()
+        .map(|rate| {
+            if max <= 0 {
+                SPARK_LEVELS[0]
+            } else {
+                let level = (*rate).max(0) * (SPARK_LEVELS.len() as i64 - 1) / max;
+                SPARK_LEVELS[level as usize]
+            }
+        })
+        .collect()
+}
+
+/// Recent download speed trend of a torrent, scaled to its own peak
+///
+/// Needs at least two samples from the notifier.
+pub fn speed_sparkline(id: i64) -> Option<String> {
+    let history = speed_history().lock().unwrap();
+    history
+        .get(&id)
+        .filter(|samples| samples.len() > 1)
+        .map(sparkline)
+}
+
+fn notify_chat_ids() -> Vec<i64> {
+    match env::var("NOTIFY_CHAT_IDS") {
+        Ok(val) => val
+            .split(',')
+            .filter_map(|x| x.trim().parse::<i64>().ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn notify_interval() -> Duration {
+    let secs = env::var("NOTIFY_INTERVAL_SECS")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_NOTIFY_INTERVAL);
+
+    Duration::from_secs(secs)
+}
+
+async fn announce(api: &Api, chats: &[i64], text: String) {
+    let muted = prefs().muted_chats;
+
+    for chat in chats.iter().filter(|chat| !muted.contains(chat)) {
+        if let Err(err) = api.send(ChatId::new(*chat).text(text.clone())).await {
+            println!("Failed to notify chat {}: {}", chat, err);
+        }
+    }
+}
+
+/// Poll Transmission, sampling download speeds for `speed_sparkline` and
+/// telling NOTIFY_CHAT_IDS when a download finishes
+///
+/// Polls without NOTIFY_CHAT_IDS too, the /status sparklines need the samples.
+/// Torrents already there at startup aren't announced, anything added later
+/// is, even when it finished between two polls.
+pub fn spawn_notifier(api: Api) {
+    if let Err(err) = require_transmission() {
+        println!("Download notifier disabled: {}", err);
+        return;
+    }
+    let chats = notify_chat_ids();
+
+    tokio::spawn(async move {
+        let interval = notify_interval();
+        // recently-active only covers the last minute, longer intervals would
+        // miss what happened in between
+        let full_polls = interval > RECENTLY_ACTIVE_WINDOW;
+        // Last seen progress, None until a full poll saw what was there at startup
+        let mut progress: Option<HashMap<i64, f64>> = None;
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let full = full_polls || progress.is_none();
+            let poll = {
+                let _permit = background_permit().await;
+                if full {
+                    get_torrents().await.map(|torrents| (torrents, Vec::new()))
+                } else {
+                    get_recently_active().await
+                }
+            };
+
+            let (torrents, mut removed) = match poll {
+                Ok(poll) => poll,
+                Err(err) => {
+                    println!("Notifier failed to poll transmission: {}", err);
+                    continue;
+                }
+            };
+
+            let Some(progress) = progress.as_mut() else {
+                progress = Some(torrents.iter().map(|t| (t.id, t.percent_done)).collect());
+                record_speeds(&torrents, &removed);
+                continue;
+            };
+
+            if full {
+                removed = progress.keys().filter(|id| !torrents.iter().any(|t| t.id == **id)).copied().collect();
+            }
+            record_speeds(&torrents, &removed);
+
+            for name in newly_finished(progress, &torrents, &removed) {
+                if !chats.is_empty() {
+                    announce(&api, &chats, format!("✅ Finished downloading {}", name)).await;
+                }
+            }
+        }
+    });
+}
+
+/// Names of the torrents that finished since `progress` was updated, which
+/// then holds their current progress
+///
+/// Torrents missing from `progress` are new, they count when they show up
+/// already finished.
+fn newly_finished(progress: &mut HashMap<i64, f64>, torrents: &[Torrent], removed: &[i64]) -> Vec<String> {
+    for id in removed {
+        progress.remove(id);
+    }
+
+    torrents
+        .iter()
+        .filter(|torrent| {
+            let previous = progress.insert(torrent.id, torrent.percent_done);
+            torrent.percent_done >= 1.0 && previous.is_none_or(|p| p < 1.0)
+        })
+        .map(|torrent| torrent.name.clone())
+        .collect()
+}
+
+/// Ratio a torrent has to reach before it's removed: the limit it seeds to
+/// (its own or the client's), AUTO_DELETE_RATIO when neither is set, and
+/// never for torrents set to seed forever
+fn auto_delete_limit(torrent: &Torrent, session: Option<f64>, global: Option<f64>) -> Option<f64> {
+    match torrent.seed_ratio_mode {
+        2 => None,
+        _ => torrent.ratio_limit(session).or(global),
+    }
+}
+
+fn auto_delete_ratio() -> Option<f64> {
+    let value = env::var("AUTO_DELETE_RATIO").ok()?;
+    let ratio = value.trim().parse::<f64>().ok().filter(|ratio| *ratio > 0.0);
+    if ratio.is_none() {
+        println!("Ignoring invalid AUTO_DELETE_RATIO value: {}", value);
+    }
+    ratio
+}
+
+/// With AUTO_DELETE_SEEDED set, remove finished torrents past their ratio
+/// limit every notifier interval, telling NOTIFY_CHAT_IDS
+///
+/// Only the torrent is removed, the downloaded data always stays on disk.
+pub fn spawn_auto_delete(api: Api) {
+    if env::var("AUTO_DELETE_SEEDED").is_err() {
+        return;
+    }
+
+    let chats = notify_chat_ids();
+    let global = auto_delete_ratio();
+    let backend = match torrent_backend() {
+        Ok(backend) => backend,
+        Err(err) => {
+            println!("Auto delete disabled: {}", err);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        let interval = notify_interval();
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let _permit = background_permit().await;
+            let torrents = match backend.get_torrents().await {
+                Ok(torrents) => torrents,
+                Err(err) => {
+                    println!("Auto delete failed to list torrents: {}", err);
+                    continue;
+                }
+            };
+            let session = match backend.session_ratio_limit().await {
+                Ok(session) => session,
+                Err(err) => {
+                    println!("Auto delete failed to read the session ratio limit: {}", err);
+                    continue;
+                }
+            };
+
+            for torrent in torrents.iter().filter(|t| t.percent_done >= 1.0) {
+                let limit = match auto_delete_limit(torrent, session, global) {
+                    Some(limit) if torrent.upload_ratio >= limit => limit,
+                    _ => continue,
+                };
+
+                // delete_torrent never removes local data
+                match backend.delete_torrent(vec![torrent.id]).await {
+                    Ok(()) => {
+                        let text = format!(
+                            "🧹 Removed {} after reaching ratio {:.2} (limit {:.2}), files kept",
+                            torrent.name, torrent.upload_ratio, limit
+                        );
+                        println!("{}", text);
+                        announce(&api, &chats, text).await;
+                    }
+                    Err(err) => println!("Auto delete failed to remove {}: {}", torrent.name, err),
+                }
+            }
+        }
+    });
+}
+
+/// How long a pick without a category waits for tv/movie, PICK_CATEGORY_TIMEOUT
+/// (e.g. 30m), 15 minutes by default
+pub fn pick_timeout() -> Duration {
+    match env::var("PICK_CATEGORY_TIMEOUT") {
+        Ok(value) => crate::restructure::parse_duration(&value).unwrap_or_else(|err| {
+            println!("Ignoring PICK_CATEGORY_TIMEOUT: {}", err);
+            DEFAULT_PICK_TIMEOUT
+        }),
+        Err(_) => DEFAULT_PICK_TIMEOUT,
+    }
+}
+
+/// DEFAULT_MEDIA_CATEGORY (tv, movie or anime) unanswered picks are added as
+pub fn default_media_category() -> Option<Media> {
+    let value = env::var("DEFAULT_MEDIA_CATEGORY").ok()?;
+    match value.trim().to_lowercase().as_str() {
+        "tv" => Some(Media::TV),
+        "movie" => Some(Media::Movie),
+        "anime" => Some(Media::Anime),
+        _ => {
+            println!("Ignoring invalid DEFAULT_MEDIA_CATEGORY value: {}", value);
+            None
+        }
+    }
+}
+
+/// Remove the picks waiting for a category for `timeout` or longer by `now`
+/// and return them
+fn take_expired_picks(
+    picks: &mut Vec<(ChatId, TorrentLocation, String, Instant)>,
+    now: Instant,
+    timeout: Duration,
+) -> Vec<(ChatId, TorrentLocation, String, Instant)> {
+    let (expired, waiting) = picks
+        .drain(..)
+        .partition(|(.., picked)| now.saturating_duration_since(*picked) >= timeout);
+    *picks = waiting;
+    expired
+}
+
+/// Picks nobody gave a category within `pick_timeout` are added as
+/// DEFAULT_MEDIA_CATEGORY, or dropped with a note when it isn't set
+pub fn spawn_pick_sweeper(api: Api, picks: Arc<futures::lock::Mutex<Vec<(ChatId, TorrentLocation, String, Instant)>>>) {
+    let timeout = pick_timeout();
+    let default_media = default_media_category();
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(PICK_SWEEP_INTERVAL).await;
+
+            let expired = take_expired_picks(&mut *picks.lock().await, Instant::now(), timeout);
+
+            for (chat, location, requester, _) in expired {
+                let name = crate::torrentfile::location_name(&location).unwrap_or_else(|| "The torrent".to_string());
+                let text = match default_media.clone() {
+                    Some(media) => {
+                        let _permit = background_permit().await;
+                        let added = match torrent_backend() {
+                            Ok(backend) => backend.add_torrent(location, media.clone(), requester).await,
+                            Err(err) => Err(err),
+                        };
+                        match added {
+                            Ok(path) => format!(
+                                "🧲 No category picked for {}, added it as {} to {}",
+                                name,
+                                media.label(),
+                                path
+                            ),
+                            Err(err) => format!(
+                                "❌ No category picked for {}, adding it as {} failed: {}",
+                                name,
+                                media.label(),
+                                err
+                            ),
+                        }
+                    }
+                    None => format!(
+                        "⌛ No category picked for {}, reply tv (index) or movie (index) to the search to add it",
+                        name
+                    ),
+                };
+
+                if let Err(err) = api.send(chat.text(text)).await {
+                    println!("Failed to tell chat {} about an expired pick: {}", chat, err);
+                }
+            }
+        }
+    });
+}
+
+/// WATCH_INTERVAL (e.g. 6h) between runs of the saved watches, unset leaves
+/// them to /watch-now
+fn watch_interval() -> Option<Duration> {
+    let value = env::var("WATCH_INTERVAL").ok()?;
+    crate::restructure::parse_duration(&value)
+        .map_err(|err| println!("Ignoring WATCH_INTERVAL: {}", err))
+        .ok()
+}
+
+/// Run the watches of every chat that didn't pause them each WATCH_INTERVAL,
+/// telling the chat when a watch has a new best result
+pub fn spawn_watch_poller(api: Api) {
+    let Some(interval) = watch_interval() else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+
+            let Prefs { watches, paused_watches, .. } = prefs();
+            for (chat, watches) in watches.into_iter().filter(|(chat, _)| !paused_watches.contains(chat)) {
+                for (index, watch) in watches.into_iter().enumerate() {
+                    let response = {
+                        let _permit = background_permit().await;
+                        run_watch(chat, watch.query.clone()).await
+                    };
+                    let top = match response {
+                        Ok(response) => response.top_title(),
+                        Err(err) => {
+                            println!("Watch {} in chat {} failed: {}", watch.query, chat, err);
+                            continue;
+                        }
+                    };
+
+                    let Some(top) = top.filter(|top| watch.last_top.as_ref() != Some(top)) else {
+                        continue;
+                    };
+                    let text = format!(
+                        "👀 New top result for {}: {}\nRun /watch-now {} to pick from the results",
+                        watch.query,
+                        top,
+                        index + 1
+                    );
+                    if let Err(err) = api.send(ChatId::new(chat).text(text)).await {
+                        println!("Failed to tell chat {} about watch {}: {}", chat, watch.query, err);
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seeded(mode: i64, limit: f64) -> Torrent {
+        serde_json::from_value(serde_json::json!({
+            "id": 1, "name": "Show", "status": 6, "percentDone": 1.0, "downloadDir": "/data/tv",
+            "totalSize": 1000, "downloadedEver": 1000, "uploadedEver": 3000, "uploadRatio": 3.0,
+            "rateDownload": 0, "rateUpload": 0, "eta": -1,
+            "seedRatioLimit": limit, "seedRatioMode": mode, "seedIdleLimit": 30,
+            "peersConnected": 0, "peersSendingToUs": 0, "peersGettingFromUs": 0,
+            "error": 0, "errorString": "", "metadataPercentComplete": 1.0
+        }))
+        .unwrap()
+    }
+
+    fn downloading(id: i64, rate: i64) -> Torrent {
+        let mut torrent = seeded(1, 0.0);
+        torrent.id = id;
+        torrent.rate_download = rate;
+        torrent
+    }
+
+    // The only test touching the shared history, ids are kept apart anyway
+    #[test]
+    fn speed_samples_are_recorded_per_torrent() {
+        record_speeds(&[downloading(9001, 100), downloading(9002, 50)], &[]);
+        record_speeds(&[downloading(9001, 300)], &[]);
+        assert_eq!(speed_sparkline(9001), Some("▂█".to_string()));
+        // Missing from the recently-active poll, so nothing going on
+        assert_eq!(speed_sparkline(9002), Some("█▁".to_string()));
+
+        record_speeds(&[], &[9002]);
+        assert_eq!(speed_sparkline(9002), None);
+
+        for _ in 0..SPEED_SAMPLES + 5 {
+            record_speeds(&[downloading(9001, 100)], &[]);
+        }
+        assert_eq!(speed_sparkline(9001).unwrap().chars().count(), SPEED_SAMPLES);
+
+        // A single sample isn't a trend yet
+        record_speeds(&[downloading(9003, 100)], &[]);
+        assert_eq!(speed_sparkline(9003), None);
+    }
+
+    fn at(id: i64, percent_done: f64) -> Torrent {
+        let mut torrent = downloading(id, 0);
+        torrent.name = format!("Torrent {}", id);
+        torrent.percent_done = percent_done;
+        torrent
+    }
+
+    #[test]
+    fn completions_are_announced_once() {
+        let mut progress: HashMap<i64, f64> = vec![(1, 0.5), (2, 1.0)].into_iter().collect();
+
+        let finished = newly_finished(&mut progress, &[at(1, 1.0), at(2, 1.0), at(3, 1.0), at(4, 0.2)], &[]);
+        // 2 was done at startup, 3 finished before it was ever seen
+        assert_eq!(finished, vec!["Torrent 1", "Torrent 3"]);
+        assert!(newly_finished(&mut progress, &[at(1, 1.0), at(3, 1.0)], &[]).is_empty());
+
+        assert!(newly_finished(&mut progress, &[], &[4]).is_empty());
+        assert!(!progress.contains_key(&4));
+    }
+
+    #[test]
+    fn picks_expire_after_the_timeout() {
+        let now = Instant::now();
+        let pick = |content: &str, age: u64| {
+            let location = TorrentLocation {
+                content: content.to_string(),
+                is_magnet: true,
+            };
+            (ChatId::new(1), location, "alice".to_string(), now - Duration::from_secs(age))
+        };
+        let mut picks = vec![pick("magnet:?old", 900), pick("magnet:?new", 60)];
+
+        let expired = take_expired_picks(&mut picks, now, Duration::from_secs(15 * 60));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].1.content, "magnet:?old");
+        // Still waiting for tv/movie
+        assert_eq!(picks.len(), 1);
+        assert_eq!(picks[0].1.content, "magnet:?new");
+
+        assert!(take_expired_picks(&mut picks, now, Duration::from_secs(15 * 60)).is_empty());
+    }
+
+    #[test]
+    fn sparklines_scale_to_the_peak() {
+        assert_eq!(sparkline(&VecDeque::from(vec![0, 25, 50, 75, 100])), "▁▂▄▆█");
+        assert_eq!(sparkline(&VecDeque::from(vec![0, 0, 0])), "▁▁▁");
+        assert_eq!(sparkline(&VecDeque::from(vec![-1, 10])), "▁█");
+    }
+
+    #[test]
+    fn auto_delete_follows_the_seed_ratio_mode() {
+        assert_eq!(auto_delete_limit(&seeded(1, 1.5), Some(2.5), Some(4.0)), Some(1.5));
+        assert_eq!(auto_delete_limit(&seeded(0, 1.5), Some(2.5), Some(4.0)), Some(2.5));
+        // No session limit, AUTO_DELETE_RATIO takes over
+        assert_eq!(auto_delete_limit(&seeded(0, 1.5), None, Some(4.0)), Some(4.0));
+        assert_eq!(auto_delete_limit(&seeded(1, 0.0), None, Some(4.0)), Some(4.0));
+        assert_eq!(auto_delete_limit(&seeded(0, 0.0), None, None), None);
+        // Seeds forever, whatever the other limits say
+        assert_eq!(auto_delete_limit(&seeded(2, 1.5), Some(2.5), Some(4.0)), None);
+    }
+}
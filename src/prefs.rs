@@ -0,0 +1,78 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::sync::{Mutex, OnceLock};
+
+/// Per-chat preferences that should survive restarts
+#[derive(serde::Serialize, serde::Deserialize, Default, Clone, Debug)]
+pub struct Prefs {
+    /// Chats that don't want completion notifications
+    #[serde(default)]
+    pub muted_chats: BTreeSet<i64>,
+    /// Search result format (compact or detailed) picked with /search-format
+    #[serde(default)]
+    pub search_formats: BTreeMap<i64, String>,
+    /// Queries saved with /watch in each chat
+    #[serde(default)]
+    pub watches: BTreeMap<i64, Vec<Watch>>,
+    /// Chats whose watches are paused with /watch-pause
+    #[serde(default)]
+    pub paused_watches: BTreeSet<i64>,
+}
+
+/// A saved search, run again with /watch-now
+#[derive(serde::Serialize, serde::Deserialize, Default, Clone, Debug)]
+pub struct Watch {
+    pub query: String,
+    /// Unix time of the last run
+    #[serde(default)]
+    pub last_run: Option<u64>,
+    /// Best result of the last run
+    #[serde(default)]
+    pub last_top: Option<String>,
+}
+
+static PREFS: OnceLock<Mutex<Prefs>> = OnceLock::new();
+
+// Without BOT_STATE_FILE the preferences only live in memory
+fn state_file() -> Option<String> {
+    env::var("BOT_STATE_FILE").ok().filter(|path| !path.is_empty())
+}
+
+fn load_prefs() -> Prefs {
+    let Some(path) = state_file() else {
+        return Prefs::default();
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|err| {
+            println!("Ignoring invalid BOT_STATE_FILE {}: {}", path, err);
+            Prefs::default()
+        }),
+        Err(_) => Prefs::default(),
+    }
+}
+
+fn prefs_lock() -> &'static Mutex<Prefs> {
+    PREFS.get_or_init(|| Mutex::new(load_prefs()))
+}
+
+/// Snapshot of the current preferences
+pub fn prefs() -> Prefs {
+    prefs_lock().lock().unwrap().clone()
+}
+
+/// Change the preferences and write them to BOT_STATE_FILE
+pub fn update_prefs<T>(change: impl FnOnce(&mut Prefs) -> T) -> Result<T, String> {
+    let mut prefs = prefs_lock().lock().unwrap();
+    let result = change(&mut prefs);
+
+    if let Some(path) = state_file() {
+        let content = serde_json::to_string_pretty(&*prefs)
+            .map_err(|err| format!("Failed to serialize preferences: {}", err))?;
+        fs::write(&path, content)
+            .map_err(|err| format!("Failed to write {}: {}", path, err))?;
+    }
+
+    Ok(result)
+}
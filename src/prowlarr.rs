@@ -0,0 +1,64 @@
+use hyper::{body::to_bytes, client, Body, Request};
+use std::env;
+
+use url::form_urlencoded;
+
+use crate::jackett::{rank_results, TelegramJackettResponse, Torrent};
+use crate::search::SearchFilters;
+
+fn prowlarr_url() -> String {
+    match env::var("PROWLARR_URL") {
+        Ok(url) => url,
+        Err(_) => "http://localhost:9696".to_string(),
+    }
+}
+
+fn prowlarr_token() -> Result<String, String> {
+    env::var("PROWLARR_TOKEN").map_err(|_| "Set PROWLARR_TOKEN to search with Prowlarr".to_string())
+}
+
+pub async fn request_prowlarr(query_string: String, filters: SearchFilters) -> Result<TelegramJackettResponse, String> {
+    let https = hyper_rustls::HttpsConnector::with_native_roots();
+    let client: client::Client<_> = client::Client::builder().build(https);
+
+    let token = prowlarr_token()?;
+
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    serializer
+        .append_pair("query", query_string.as_str())
+        .append_pair("type", "search");
+    for category in filters.categories() {
+        serializer.append_pair("categories", &category.to_string());
+    }
+    let encoded_path: String = serializer.finish();
+
+    let request = Request::builder()
+        .uri(format!("{}/api/v1/search?{}", prowlarr_url(), encoded_path))
+        .header("X-Api-Key", token)
+        .body(Body::empty())
+        .map_err(|err| format!("Url misconfigured {}", err))?;
+
+    let response = client
+        .request(request)
+        .await
+        .map_err(|err| format!("Prowlarr Response: {}", err))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Prowlarr replied with {}", response.status()));
+    }
+
+    let body = to_bytes(response.into_body())
+        .await
+        .map_err(|err| format!("From Prowlarr to body: {}", err))?;
+
+    // Prowlarr answers with a bare array of results
+    let results: Vec<Torrent> =
+        serde_json::from_slice(&body).map_err(|err| format!("Not JSON {}", err))?;
+
+    rank_results(results, filters)
+}
+
+/// Search by IMDb id with Prowlarr's `{ImdbId:tt...}` query syntax
+pub async fn request_prowlarr_by_imdb(imdb_id: String) -> Result<TelegramJackettResponse, String> {
+    request_prowlarr(format!("{{ImdbId:{}}}", imdb_id), SearchFilters::default()).await
+}
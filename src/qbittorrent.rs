@@ -0,0 +1,434 @@
+use hyper::header::{CONTENT_TYPE, COOKIE, REFERER, SET_COOKIE};
+use hyper::{body::to_bytes, client, Body, Request};
+use serde::Deserialize;
+use std::env;
+
+use url::form_urlencoded;
+
+use crate::jackett::TorrentLocation;
+use crate::transmission::{added_by_label, download_dir, Media, Torrent};
+
+type Client = client::Client<hyper_rustls::HttpsConnector<client::HttpConnector>>;
+
+const MULTIPART_BOUNDARY: &str = "telegram-bot-torrents-boundary";
+// qBittorrent's "no ETA"
+const ETA_INFINITY: i64 = 8_640_000;
+
+fn qbittorrent_url() -> String {
+    env::var("QBITTORRENT_URL").map_or("http://localhost:8080".to_string(), |url| url)
+}
+
+/// QBITTORRENT_CREDENTIALS as `user:password`, unset when qBittorrent
+/// bypasses authentication for the bot's address
+fn qbittorrent_credentials() -> Option<(String, String)> {
+    let credentials = env::var("QBITTORRENT_CREDENTIALS").ok()?;
+    let (user, password) = credentials.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+#[derive(Debug, Deserialize)]
+struct QbTorrent {
+    hash: String,
+    name: String,
+    state: String,
+    progress: f64,
+    save_path: String,
+    #[serde(default)]
+    total_size: i64,
+    #[serde(default)]
+    downloaded: i64,
+    #[serde(default)]
+    uploaded: i64,
+    #[serde(default)]
+    ratio: f64,
+    #[serde(default)]
+    dlspeed: i64,
+    #[serde(default)]
+    upspeed: i64,
+    #[serde(default)]
+    eta: i64,
+    // -2 uses the global limit, -1 means no limit
+    #[serde(default)]
+    ratio_limit: f64,
+    #[serde(default)]
+    seeding_time_limit: i64,
+    #[serde(default)]
+    num_seeds: i64,
+    #[serde(default)]
+    num_leechs: i64,
+    #[serde(default)]
+    tracker: String,
+    // Comma separated
+    #[serde(default)]
+    tags: String,
+}
+
+/// qBittorrent identifies torrents by hash, the bot's lists need numbers
+fn hash_id(hash: &str) -> i64 {
+    hash.get(..15)
+        .and_then(|prefix| i64::from_str_radix(prefix, 16).ok())
+        .unwrap_or_default()
+}
+
+/// Transmission's status codes for a qBittorrent state
+fn transmission_status(state: &str) -> i64 {
+    match state {
+        "checkingDL" | "checkingUP" | "checkingResumeData" | "moving" => 2,
+        "queuedDL" => 3,
+        "downloading" | "stalledDL" | "metaDL" | "forcedDL" | "forcedMetaDL" => 4,
+        "queuedUP" => 5,
+        "uploading" | "stalledUP" | "forcedUP" => 6,
+        _ => 0,
+    }
+}
+
+impl From<QbTorrent> for Torrent {
+    fn from(torrent: QbTorrent) -> Self {
+        let errored = matches!(torrent.state.as_str(), "error" | "missingFiles");
+
+        Torrent {
+            id: hash_id(&torrent.hash),
+            name: torrent.name,
+            status: transmission_status(&torrent.state),
+            percent_done: torrent.progress,
+            download_dir: torrent.save_path,
+            total_size: torrent.total_size,
+            downloaded_ever: torrent.downloaded,
+            uploaded_ever: torrent.uploaded,
+            upload_ratio: torrent.ratio,
+            rate_download: torrent.dlspeed,
+            rate_upload: torrent.upspeed,
+            eta: if torrent.eta >= ETA_INFINITY { -1 } else { torrent.eta },
+            seed_ratio_limit: torrent.ratio_limit.max(0.0),
+            seed_ratio_mode: match torrent.ratio_limit {
+                limit if limit == -2.0 => 0,
+                limit if limit < 0.0 => 2,
+                _ => 1,
+            },
+            seed_idle_limit: torrent.seeding_time_limit,
+            peers_connected: torrent.num_seeds + torrent.num_leechs,
+            peers_sending_to_us: torrent.num_seeds,
+            peers_getting_from_us: torrent.num_leechs,
+            trackers: if torrent.tracker.is_empty() {
+                Vec::new()
+            } else {
+                vec![serde_json::json!({ "announce": torrent.tracker })]
+            },
+            error: if errored { 1 } else { 0 },
+            error_string: if errored { torrent.state.clone() } else { String::new() },
+            metadata_percent_complete: if torrent.state.contains("meta") { 0.0 } else { 1.0 },
+            labels: torrent
+                .tags
+                .split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect(),
+        }
+    }
+}
+
+fn new_client() -> Client {
+    let https = hyper_rustls::HttpsConnector::with_native_roots();
+    client::Client::builder().build(https)
+}
+
+/// SID cookie of a fresh session, none without QBITTORRENT_CREDENTIALS
+async fn login(client: &Client) -> Result<Option<String>, String> {
+    let Some((user, password)) = qbittorrent_credentials() else {
+        return Ok(None);
+    };
+
+    let body = form_urlencoded::Serializer::new(String::new())
+        .append_pair("username", &user)
+        .append_pair("password", &password)
+        .finish();
+
+    let request = Request::builder()
+        .method("POST")
+        .uri(format!("{}/api/v2/auth/login", qbittorrent_url()))
+        .header(REFERER, qbittorrent_url())
+        .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(body))
+        .map_err(|err| format!("Url misconfigured {}", err))?;
+
+    let response = client
+        .request(request)
+        .await
+        .map_err(|err| format!("qBittorrent login: {}", err))?;
+
+    let cookie = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find_map(|value| value.split(';').next().filter(|cookie| cookie.starts_with("SID=")))
+        .map(|cookie| cookie.to_string());
+
+    cookie
+        .map(Some)
+        .ok_or_else(|| "qBittorrent login failed, check QBITTORRENT_CREDENTIALS".to_string())
+}
+
+async fn post(
+    client: &Client,
+    cookie: &Option<String>,
+    path: &str,
+    content_type: &str,
+    body: Body,
+) -> Result<hyper::body::Bytes, String> {
+    let mut builder = Request::builder()
+        .method("POST")
+        .uri(format!("{}/api/v2/{}", qbittorrent_url(), path))
+        .header(REFERER, qbittorrent_url())
+        .header(CONTENT_TYPE, content_type);
+    if let Some(cookie) = cookie {
+        builder = builder.header(COOKIE, cookie.as_str());
+    }
+    let request = builder.body(body).map_err(|err| format!("Url misconfigured {}", err))?;
+
+    let response = client
+        .request(request)
+        .await
+        .map_err(|err| format!("qBittorrent Response: {}", err))?;
+
+    if response.status() == 403 {
+        return Err("qBittorrent refused the request, check QBITTORRENT_CREDENTIALS".to_string());
+    }
+    if !response.status().is_success() {
+        return Err(format!("qBittorrent replied with {}", response.status()));
+    }
+
+    to_bytes(response.into_body())
+        .await
+        .map_err(|err| format!("From qBittorrent to body: {}", err))
+}
+
+async fn post_form(client: &Client, cookie: &Option<String>, path: &str, fields: &[(&str, &str)]) -> Result<(), String> {
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    for (name, value) in fields {
+        serializer.append_pair(name, value);
+    }
+
+    post(client, cookie, path, "application/x-www-form-urlencoded", Body::from(serializer.finish())).await?;
+    Ok(())
+}
+
+/// /torrents/add only takes files as multipart/form-data
+fn multipart_body(fields: &[(&str, &str)], torrent_file: Option<&[u8]>) -> Vec<u8> {
+    let mut body = Vec::new();
+
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                MULTIPART_BOUNDARY, name, value
+            )
+            .as_bytes(),
+        );
+    }
+
+    if let Some(content) = torrent_file {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"torrents\"; filename=\"upload.torrent\"\r\nContent-Type: application/x-bittorrent\r\n\r\n",
+                MULTIPART_BOUNDARY
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(content);
+        body.extend_from_slice(b"\r\n");
+    }
+
+    body.extend_from_slice(format!("--{}--\r\n", MULTIPART_BOUNDARY).as_bytes());
+    body
+}
+
+/// Add the torrent to the folder of its media and return that folder,
+/// `requester` is stored as a tag
+pub async fn add_torrent(location: TorrentLocation, media: Media, requester: String) -> Result<String, String> {
+    let path = download_dir(&location, &media).await?;
+
+    let tag = added_by_label(&requester);
+    let mut fields = vec![("savepath", path.as_str()), ("tags", tag.as_str())];
+    let torrent_file = if location.is_magnet {
+        fields.push(("urls", location.content.as_str()));
+        None
+    } else {
+        Some(base64::decode(&location.content).map_err(|err| format!("Invalid torrent file: {}", err))?)
+    };
+
+    let client = new_client();
+    let cookie = login(&client).await?;
+    let body = multipart_body(&fields, torrent_file.as_deref());
+    let content_type = format!("multipart/form-data; boundary={}", MULTIPART_BOUNDARY);
+    let reply = post(&client, &cookie, "torrents/add", &content_type, Body::from(body)).await?;
+
+    if String::from_utf8_lossy(&reply).trim() == "Fails." {
+        return Err("qBittorrent couldn't add the torrent".to_string());
+    }
+
+    Ok(path)
+}
+
+async fn torrents_info(client: &Client, cookie: &Option<String>) -> Result<Vec<QbTorrent>, String> {
+    let body = post(client, cookie, "torrents/info", "application/x-www-form-urlencoded", Body::empty()).await?;
+    serde_json::from_slice(&body).map_err(|err| format!("Failed to parse qBittorrent torrents: {}", err))
+}
+
+pub async fn get_torrents() -> Result<Vec<Torrent>, String> {
+    let client = new_client();
+    let cookie = login(&client).await?;
+
+    Ok(torrents_info(&client, &cookie).await?.into_iter().map(Torrent::from).collect())
+}
+
+/// Remove the torrents with these ids, keeping their data
+pub async fn delete_torrent(ids: Vec<i64>) -> Result<(), String> {
+    let client = new_client();
+    let cookie = login(&client).await?;
+
+    let hashes: Vec<String> = torrents_info(&client, &cookie)
+        .await?
+        .into_iter()
+        .map(|torrent| torrent.hash)
+        .filter(|hash| ids.contains(&hash_id(hash)))
+        .collect();
+    if hashes.is_empty() {
+        return Err("Torrent not found, it may have been removed".to_string());
+    }
+
+    let hashes = hashes.join("|");
+    post_form(&client, &cookie, "torrents/delete", &[("hashes", hashes.as_str()), ("deleteFiles", "false")]).await
+}
+
+#[derive(Deserialize)]
+struct QbPreferences {
+    #[serde(default)]
+    max_ratio_enabled: bool,
+    #[serde(default)]
+    max_ratio: f64,
+}
+
+/// Global share ratio limit, None when it's off
+pub async fn session_ratio_limit() -> Result<Option<f64>, String> {
+    let client = new_client();
+    let cookie = login(&client).await?;
+
+    let body = post(&client, &cookie, "app/preferences", "application/x-www-form-urlencoded", Body::empty()).await?;
+    let preferences: QbPreferences = serde_json::from_slice(&body)
+        .map_err(|err| format!("Failed to parse qBittorrent preferences: {}", err))?;
+
+    Ok(Some(preferences.max_ratio).filter(|limit| preferences.max_ratio_enabled && *limit > 0.0))
+}
+
+pub async fn stop_seeding_all() -> Result<(), String> {
+    let client = new_client();
+    let cookie = login(&client).await?;
+
+    // qBittorrent 5 renamed pause to stop
+    match post_form(&client, &cookie, "torrents/stop", &[("hashes", "all")]).await {
+        Err(err) if err.contains("404") => post_form(&client, &cookie, "torrents/pause", &[("hashes", "all")]).await,
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qb_torrent(extra: serde_json::Value) -> QbTorrent {
+        let mut value = serde_json::json!({
+            "hash": "8f2a1c0e9b7d6a5f4e3d2c1b0a9f8e7d6c5b4a39",
+            "name": "Some.Show.S01E01.1080p",
+            "state": "uploading",
+            "progress": 1.0,
+            "save_path": "/data/tv",
+        });
+        for (key, field) in extra.as_object().unwrap() {
+            value[key] = field.clone();
+        }
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn hashes_become_stable_positive_ids() {
+        let id = hash_id("8f2a1c0e9b7d6a5f4e3d2c1b0a9f8e7d6c5b4a39");
+
+        assert_eq!(id, 0x8f2a1c0e9b7d6a5);
+        assert_eq!(id, hash_id("8F2A1C0E9B7D6A5FFFFFFFFFFFFFFFFFFFFFFFFF"));
+        assert_eq!(hash_id("8f2a1c"), 0);
+        assert_eq!(hash_id("not-a-hash-at-all-really"), 0);
+    }
+
+    #[test]
+    fn qbittorrent_states_map_to_transmission_statuses() {
+        assert_eq!(transmission_status("checkingResumeData"), 2);
+        assert_eq!(transmission_status("moving"), 2);
+        assert_eq!(transmission_status("queuedDL"), 3);
+        assert_eq!(transmission_status("stalledDL"), 4);
+        assert_eq!(transmission_status("forcedMetaDL"), 4);
+        assert_eq!(transmission_status("queuedUP"), 5);
+        assert_eq!(transmission_status("stalledUP"), 6);
+        assert_eq!(transmission_status("pausedUP"), 0);
+        assert_eq!(transmission_status("error"), 0);
+    }
+
+    #[test]
+    fn infinite_eta_and_unset_ratio_limits_are_cleared() {
+        let torrent = Torrent::from(qb_torrent(serde_json::json!({ "eta": ETA_INFINITY, "ratio_limit": -2.0 })));
+        assert_eq!(torrent.eta, -1);
+        assert_eq!(torrent.seed_ratio_limit, 0.0);
+        assert_eq!(torrent.ratio_limit(Some(2.0)), Some(2.0));
+
+        let torrent = Torrent::from(qb_torrent(serde_json::json!({ "eta": 90, "ratio_limit": -1.0 })));
+        assert_eq!(torrent.eta, 90);
+        assert_eq!(torrent.seed_ratio_limit, 0.0);
+        assert_eq!(torrent.ratio_limit(Some(2.0)), None);
+
+        let torrent = Torrent::from(qb_torrent(serde_json::json!({ "ratio_limit": 1.5 })));
+        assert_eq!(torrent.seed_ratio_limit, 1.5);
+        assert_eq!(torrent.ratio_limit(Some(2.0)), Some(1.5));
+    }
+
+    #[test]
+    fn tags_become_labels() {
+        let tags = format!("{}, kids,,", added_by_label("alice"));
+        let torrent = Torrent::from(qb_torrent(serde_json::json!({ "tags": tags })));
+
+        assert_eq!(torrent.labels, vec![added_by_label("alice"), "kids".to_string()]);
+        assert_eq!(torrent.added_by(), Some("alice"));
+        assert!(Torrent::from(qb_torrent(serde_json::json!({}))).labels.is_empty());
+    }
+
+    #[test]
+    fn errored_and_metadata_states_are_flagged() {
+        let torrent = Torrent::from(qb_torrent(serde_json::json!({ "state": "missingFiles" })));
+        assert_eq!(torrent.error, 1);
+        assert_eq!(torrent.error_string, "missingFiles");
+
+        let torrent = Torrent::from(qb_torrent(serde_json::json!({ "state": "metaDL", "progress": 0.0 })));
+        assert_eq!(torrent.error, 0);
+        assert_eq!(torrent.metadata_percent_complete, 0.0);
+        assert_eq!(torrent.status, 4);
+    }
+
+    #[test]
+    fn multipart_body_has_fields_file_and_closing_boundary() {
+        let body = multipart_body(&[("savepath", "/data/tv"), ("tags", "kids")], Some(b"d4:infoe"));
+        let body = String::from_utf8(body).unwrap();
+
+        assert!(body.starts_with(&format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"savepath\"\r\n\r\n/data/tv\r\n",
+            MULTIPART_BOUNDARY
+        )));
+        assert!(body.contains("name=\"tags\"\r\n\r\nkids\r\n"));
+        assert!(body.contains(
+            "name=\"torrents\"; filename=\"upload.torrent\"\r\nContent-Type: application/x-bittorrent\r\n\r\nd4:infoe\r\n"
+        ));
+        assert!(body.ends_with(&format!("--{}--\r\n", MULTIPART_BOUNDARY)));
+
+        let body = String::from_utf8(multipart_body(&[("urls", "magnet:?xt=urn:btih:abc")], None)).unwrap();
+        assert!(!body.contains("filename="));
+        assert_eq!(body.matches(MULTIPART_BOUNDARY).count(), 2);
+    }
+}
@@ -1,37 +1,48 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use tokio::process::Command;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
+use crate::selection::parse_index_selection;
 use crate::transmission::Media;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GuessitMetadata {
     pub title: String,
     pub year: Option<i32>,
-    pub season: Option<u32>,
+    pub season: Option<serde_json::Value>,   // Array for multi-season packs
     pub episode: Option<serde_json::Value>,  // Can be single number or array
     #[serde(default)]
     pub extension: String,
 }
 
-impl GuessitMetadata {
-    pub fn episodes(&self) -> Vec<u32> {
-        match &self.episode {
-            Some(serde_json::Value::Number(n)) => {
-                if let Some(ep) = n.as_u64() {
-                    vec![ep as u32]
-                } else {
-                    Vec::new()
-                }
-            }
-            Some(serde_json::Value::Array(arr)) => {
-                arr.iter()
-                    .filter_map(|v| v.as_u64().map(|n| n as u32))
-                    .collect()
+/// Guessit returns seasons and episodes either as a single number or an array
+fn numbers(value: &Option<serde_json::Value>) -> Vec<u32> {
+    match value {
+        Some(serde_json::Value::Number(n)) => {
+            if let Some(number) = n.as_u64() {
+                vec![number as u32]
+            } else {
+                Vec::new()
             }
-            _ => Vec::new(),
         }
+        Some(serde_json::Value::Array(arr)) => {
+            arr.iter()
+                .filter_map(|v| v.as_u64().map(|n| n as u32))
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+impl GuessitMetadata {
+    pub fn seasons(&self) -> Vec<u32> {
+        numbers(&self.season)
+    }
+
+    pub fn episodes(&self) -> Vec<u32> {
+        numbers(&self.episode)
     }
 }
 
@@ -43,21 +54,173 @@ pub struct MoveOperation {
     pub is_subtitle: bool,
 }
 
+/// Media roots a plan moves files into
+#[derive(Debug, Clone)]
+pub enum PlanTarget {
+    /// Every file is the same media type, built under the given root
+    Single(Media, String),
+    /// Mixed folders, each file goes to TV when guessit finds a season or
+    /// episode and to movies otherwise
+    Auto { tv_path: String, movie_path: String },
+}
+
+impl PlanTarget {
+    /// Media type and root for a file with `metadata`
+    fn route(&self, metadata: &GuessitMetadata) -> (Media, &str) {
+        match self {
+            PlanTarget::Single(media, base_path) => (media.clone(), base_path),
+            PlanTarget::Auto { tv_path, movie_path } => {
+                if metadata.seasons().is_empty() && metadata.episodes().is_empty() {
+                    (Media::Movie, movie_path)
+                } else {
+                    (Media::TV, tv_path)
+                }
+            }
+        }
+    }
+
+    fn media(&self) -> Option<Media> {
+        match self {
+            PlanTarget::Single(media, _) => Some(media.clone()),
+            PlanTarget::Auto { .. } => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct RestructurePlan {
-    pub media_type: Media,
+    // None for auto plans mixing TV and movies
+    pub media_type: Option<Media>,
     pub operations: Vec<MoveOperation>,
     pub unparseable_files: Vec<String>,
+    // Files renamed with a -N suffix because another file in the plan had the same target
+    pub duplicate_targets: Vec<String>,
+    // Files guessit put in several seasons at once, left to be moved by hand
+    pub multi_season_files: Vec<String>,
+    // Directory the files were scanned from, source folders emptied by the
+    // moves are looked for below it
+    pub scan_path: String,
+    // What to tidy in the source folders once the files are moved
+    pub cleanup: Cleanup,
+}
+
+/// Post-move tidying asked for with --cleanup and --cleanup-junk
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Cleanup {
+    // Remove the emptied source folders instead of only reporting them
+    pub empty_dirs: bool,
+    // Delete RESTRUCTURE_CLEANUP_EXTENSIONS files left next to the moved ones
+    pub junk: bool,
 }
 
-const VIDEO_EXTENSIONS: &[&str] = &[
-    ".mkv", ".mp4", ".avi", ".mov", ".wmv", ".flv", ".webm", ".m4v",
+pub const VIDEO_EXTENSIONS: &[&str] = &[
+    ".mkv", ".mp4", ".avi", ".mov", ".wmv", ".flv", ".webm", ".m4v", ".ts",
 ];
 
 const SUBTITLE_EXTENSIONS: &[&str] = &[".srt", ".sub", ".ass", ".ssa", ".vtt"];
 
+const DEFAULT_EXCLUDED_WORDS: &[&str] = &["sample", "trailer"];
+const DEFAULT_EXCLUDED_DIRS: &[&str] = &["extras", "featurettes"];
+// Samples and trailers stay below this, an episode or movie doesn't
+const EXCLUDED_WORD_MAX_SIZE: u64 = 300 * 1000 * 1000;
+
+/// Release junk kept out of restructure plans: files with one of `words` in
+/// their name and everything under one of `dirs`
+#[derive(Debug, Clone)]
+pub struct Exclusions {
+    pub words: Vec<String>,
+    pub dirs: Vec<String>,
+}
+
+fn env_list(name: &str, default: &[&str]) -> Vec<String> {
+    match std::env::var(name) {
+        Ok(val) => val
+            .split(',')
+            .map(|x| x.trim().to_lowercase())
+            .filter(|x| !x.is_empty())
+            .collect(),
+        Err(_) => default.iter().map(|x| x.to_string()).collect(),
+    }
+}
+
+/// Extensions listed in RESTRUCTURE_CLEANUP_EXTENSIONS, with their leading dot
+///
+/// Video and subtitle extensions are dropped so a typo can never delete media.
+pub fn junk_extensions() -> Vec<String> {
+    env_list("RESTRUCTURE_CLEANUP_EXTENSIONS", &[])
+        .into_iter()
+        .map(|ext| format!(".{}", ext.trim_start_matches('.')))
+        .filter(|ext| !VIDEO_EXTENSIONS.contains(&ext.as_str()) && !SUBTITLE_EXTENSIONS.contains(&ext.as_str()))
+        .collect()
+}
+
+impl Exclusions {
+    /// RESTRUCTURE_EXCLUDE_WORDS and RESTRUCTURE_EXCLUDE_DIRS, an empty value disables them
+    pub fn from_env() -> Self {
+        Exclusions {
+            words: env_list("RESTRUCTURE_EXCLUDE_WORDS", DEFAULT_EXCLUDED_WORDS),
+            dirs: env_list("RESTRUCTURE_EXCLUDE_DIRS", DEFAULT_EXCLUDED_DIRS),
+        }
+    }
+
+    fn excludes_dir(&self, name: &str) -> bool {
+        self.dirs.contains(&name.to_lowercase())
+    }
+
+    // Whole words only, so "Sampler.mkv" is still a real file. A name with an
+    // episode or year marker only counts when the file is small too, so
+    // "Trailer Park Boys S01E01" stays an episode
+    fn excludes_file(&self, name: &str, size: u64) -> bool {
+        let stem = Path::new(name)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(name)
+            .to_lowercase();
+
+        let words: Vec<&str> = stem.split(|c: char| !c.is_alphanumeric()).collect();
+        if !words.iter().any(|word| self.words.iter().any(|excluded| excluded == word)) {
+            return false;
+        }
+
+        let has_year = words
+            .iter()
+            .any(|word| word.len() == 4 && word.parse::<u32>().is_ok_and(|year| (1900..2100).contains(&year)));
+        let has_marker = has_year || episode_marker(&stem).is_some();
+
+        !has_marker || size < EXCLUDED_WORD_MAX_SIZE
+    }
+}
+
+/// Parse durations like "30m", "12h", "7d" or "2w"
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim().to_lowercase();
+    let invalid = || format!("Invalid duration: {} (use e.g. 12h, 7d, 2w)", value);
+
+    let unit_start = value.char_indices().last().map(|(i, _)| i).ok_or_else(invalid)?;
+    let (number, unit) = value.split_at(unit_start);
+    let number = number.parse::<u64>().map_err(|_| invalid())?;
+
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+
+    number.checked_mul(seconds).map(Duration::from_secs).ok_or_else(invalid)
+}
+
 /// Recursively scan directory for video files
-fn scan_files_recursive(dir: &str, extensions: &[&str]) -> Result<Vec<String>, String> {
+///
+/// With `modified_since` set, files last modified before it are skipped.
+fn scan_files_recursive(
+    dir: &str,
+    extensions: &[&str],
+    modified_since: Option<SystemTime>,
+    exclusions: &Exclusions,
+) -> Result<Vec<String>, String> {
     let mut files = Vec::new();
     let path = Path::new(dir);
 
@@ -69,7 +232,13 @@ fn scan_files_recursive(dir: &str, extensions: &[&str]) -> Result<Vec<String>, S
         return Err(format!("Path is not a directory: {}", dir));
     }
 
-    fn walk_dir(path: &Path, extensions: &[&str], files: &mut Vec<String>) -> Result<(), String> {
+    fn walk_dir(
+        path: &Path,
+        extensions: &[&str],
+        modified_since: Option<SystemTime>,
+        exclusions: &Exclusions,
+        files: &mut Vec<String>,
+    ) -> Result<(), String> {
         let entries = std::fs::read_dir(path)
             .map_err(|e| format!("Failed to read directory {}: {}", path.display(), e))?;
 
@@ -77,18 +246,31 @@ fn scan_files_recursive(dir: &str, extensions: &[&str]) -> Result<Vec<String>, S
             let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
             let entry_path = entry.path();
 
+            let name = entry_path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
             // Skip hidden files
-            if let Some(file_name) = entry_path.file_name() {
-                if let Some(name_str) = file_name.to_str() {
-                    if name_str.starts_with('.') {
-                        continue;
-                    }
-                }
+            if name.starts_with('.') {
+                continue;
             }
 
             if entry_path.is_dir() {
-                walk_dir(&entry_path, extensions, files)?;
+                if !exclusions.excludes_dir(name) {
+                    walk_dir(&entry_path, extensions, modified_since, exclusions, files)?;
+                }
             } else if entry_path.is_file() {
+                let metadata = entry.metadata();
+                let size = metadata.as_ref().map(|m| m.len()).unwrap_or(0);
+                if exclusions.excludes_file(name, size) {
+                    continue;
+                }
+
+                if let Some(since) = modified_since {
+                    let modified = metadata.and_then(|m| m.modified());
+                    if matches!(modified, Ok(modified) if modified < since) {
+                        continue;
+                    }
+                }
+
                 if let Some(ext) = entry_path.extension() {
                     if let Some(ext_str) = ext.to_str() {
                         let ext_with_dot = format!(".{}", ext_str);
@@ -103,7 +285,7 @@ fn scan_files_recursive(dir: &str, extensions: &[&str]) -> Result<Vec<String>, S
         Ok(())
     }
 
-    walk_dir(path, extensions, &mut files)?;
+    walk_dir(path, extensions, modified_since, exclusions, &mut files)?;
     files.sort();
     Ok(files)
 }
@@ -140,29 +322,53 @@ async fn call_guessit(file_path: &str) -> Result<GuessitMetadata, String> {
     let mut metadata: GuessitMetadata = serde_json::from_str(&json_str)
         .map_err(|e| format!("Failed to parse guessit output: {}", e))?;
 
-    // Extract extension from file path
-    metadata.extension = Path::new(file_path)
+    metadata.extension = file_extension(file_path);
+
+    Ok(metadata)
+}
+
+/// Extension of a file path with its dot, `.mkv` when there is none
+fn file_extension(file_path: &str) -> String {
+    Path::new(file_path)
         .extension()
         .and_then(|ext| ext.to_str())
         .map(|s| format!(".{}", s))
-        .unwrap_or_else(|| ".mkv".to_string());
+        .unwrap_or_else(|| ".mkv".to_string())
+}
 
-    Ok(metadata)
+/// Zero-width and bidi control characters that render invisibly but break
+/// lookups on network shares
+fn is_invisible_char(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2069}' | '\u{FEFF}'
+    )
 }
 
 /// Sanitize filename by removing invalid characters
+///
+/// Besides the characters Windows/SMB reject, control characters and
+/// invisible Unicode are dropped and trailing dots/spaces are trimmed.
 fn sanitize_filename(name: &str) -> String {
-    name.chars()
+    let sanitized: String = name
+        .chars()
+        .filter(|c| !c.is_control() && !is_invisible_char(*c))
         .map(|c| match c {
             '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
             _ => c,
         })
-        .collect()
+        .collect();
+
+    sanitized.trim_end_matches(|c| c == '.' || c == ' ').to_string()
 }
 
 /// Generate TV show path
 fn generate_tv_path(base: &str, metadata: &GuessitMetadata) -> Result<String, String> {
-    let season = metadata.season.ok_or("TV show missing season number")?;
+    let season = match metadata.seasons()[..] {
+        [season] => season,
+        [] => return Err("TV show missing season number".to_string()),
+        _ => return Err("File spans multiple seasons".to_string()),
+    };
     let episodes = metadata.episodes();
 
     if episodes.is_empty() {
@@ -218,10 +424,14 @@ fn generate_movie_path(base: &str, metadata: &GuessitMetadata) -> Result<String,
 }
 
 /// Resolve file collisions by appending -1, -2, etc.
-fn resolve_collision(target_path: &str) -> String {
+///
+/// A path is taken if it exists on disk or is already in `assigned`, the
+/// targets handed out earlier in the same plan.
+fn resolve_collision(target_path: &str, assigned: &HashSet<String>) -> String {
     let path = Path::new(target_path);
+    let is_taken = |p: &Path| p.exists() || assigned.contains(&*p.to_string_lossy());
 
-    if !path.exists() {
+    if !is_taken(path) {
         return target_path.to_string();
     }
 
@@ -237,7 +447,7 @@ fn resolve_collision(target_path: &str) -> String {
         };
 
         let new_path = parent.join(new_name);
-        if !new_path.exists() {
+        if !is_taken(&new_path) {
             return new_path.to_string_lossy().to_string();
         }
     }
@@ -246,8 +456,99 @@ fn resolve_collision(target_path: &str) -> String {
     target_path.to_string()
 }
 
+/// Subtitle files per directory, so a folder is only read once per plan
+type SubtitleCache = HashMap<PathBuf, Vec<PathBuf>>;
+
+fn subtitles_in_dir<'a>(dir: &Path, cache: &'a mut SubtitleCache) -> &'a [PathBuf] {
+    cache.entry(dir.to_path_buf()).or_insert_with(|| {
+        let mut subtitles = Vec::new();
+
+        if let Ok(entries) = std::fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+
+                if !path.is_file() {
+                    continue;
+                }
+
+                if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+                    let ext_with_dot = format!(".{}", ext);
+                    if SUBTITLE_EXTENSIONS.contains(&ext_with_dot.as_str()) {
+                        subtitles.push(path);
+                    }
+                }
+            }
+        }
+
+        subtitles
+    })
+}
+
+// Subtitle name tags that look like language codes but aren't
+const NON_LANGUAGE_TAGS: &[&str] = &["sdh", "cc", "hi", "forced", "sub", "dub"];
+
+// ISO 639-2 codes of common subtitle languages, bibliographic and
+// terminology forms, with their ISO 639-1 code
+const ISO_639_2: &[(&str, &str)] = &[
+    ("ara", "ar"), ("chi", "zh"), ("zho", "zh"), ("cze", "cs"), ("ces", "cs"), ("dan", "da"),
+    ("dut", "nl"), ("nld", "nl"), ("eng", "en"), ("fin", "fi"), ("fre", "fr"), ("fra", "fr"),
+    ("ger", "de"), ("deu", "de"), ("gre", "el"), ("ell", "el"), ("heb", "he"), ("hin", "hi"),
+    ("hun", "hu"), ("ind", "id"), ("ita", "it"), ("jpn", "ja"), ("kor", "ko"), ("nor", "no"),
+    ("pol", "pl"), ("por", "pt"), ("rum", "ro"), ("ron", "ro"), ("rus", "ru"), ("spa", "es"),
+    ("swe", "sv"), ("tha", "th"), ("tur", "tr"), ("ukr", "uk"), ("vie", "vi"),
+];
+
+/// Lowercased language tag with an ISO 639-2 base turned into its ISO 639-1
+/// code, so "eng" and "en" compare equal and "por-BR" becomes "pt-br"
+fn language_code(tag: &str) -> String {
+    let tag = tag.to_lowercase();
+    let (base, region) = match tag.find(['-', '_']) {
+        Some(split) => tag.split_at(split),
+        None => (tag.as_str(), ""),
+    };
+
+    let base = ISO_639_2
+        .iter()
+        .find(|(long, _)| *long == base)
+        .map_or(base, |(_, short)| *short);
+    format!("{}{}", base, region.replace('_', "-"))
+}
+
+/// Language tag of a subtitle named after its video, e.g. "en" for
+/// "show.s01e01.eng.srt" or "pt-br" for "show.s01e01.pt-BR.forced.srt"
+fn subtitle_language(file_name: &str, video_stem: &str) -> Option<String> {
+    let tags = Path::new(file_name).file_stem()?.to_str()?.strip_prefix(video_stem)?;
+
+    tags.split('.')
+        .find(|tag| {
+            let base = tag.split(['-', '_']).next().unwrap_or_default();
+            (2..=3).contains(&base.len())
+                && base.chars().all(|c| c.is_ascii_alphabetic())
+                && !NON_LANGUAGE_TAGS.contains(&tag.to_lowercase().as_str())
+        })
+        .map(language_code)
+}
+
+/// Whether SUBTITLE_LANGUAGES (already lowercased) lets this subtitle move
+/// with its video. Untagged subtitles always do, "pt" also covers "pt-br"
+/// and "en" also covers "eng"
+fn keeps_subtitle(file_name: &str, video_stem: &str, languages: &[String]) -> bool {
+    if languages.is_empty() {
+        return true;
+    }
+
+    match subtitle_language(file_name, video_stem) {
+        Some(language) => languages.iter().map(|wanted| language_code(wanted)).any(|wanted| {
+            language == wanted || language.split('-').next() == Some(wanted.as_str())
+        }),
+        None => true,
+    }
+}
+
 /// Find matching subtitle files for a video file
-fn find_matching_subtitles(video_path: &str) -> Vec<String> {
+///
+/// Language-tagged ones outside SUBTITLE_LANGUAGES are left where they are.
+fn find_matching_subtitles(video_path: &str, cache: &mut SubtitleCache) -> Vec<String> {
     let video = Path::new(video_path);
     let parent = match video.parent() {
         Some(p) => p,
@@ -259,170 +560,551 @@ fn find_matching_subtitles(video_path: &str) -> Vec<String> {
         None => return Vec::new(),
     };
 
-    let mut subtitles = Vec::new();
+    let languages = env_list("SUBTITLE_LANGUAGES", &[]);
+    let mut subtitles: Vec<String> = subtitles_in_dir(parent, cache)
+        .iter()
+        .filter(|path| {
+            // Match exact name or name with language code
+            // e.g., "show.s01e01.srt" or "show.s01e01.en.srt"
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|file_name| {
+                    file_name.starts_with(video_stem) && keeps_subtitle(file_name, video_stem, &languages)
+                })
+        })
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+
+    subtitles.sort();
+    subtitles
+}
+
+/// Target path a file with this name would get, without touching the filesystem
+pub async fn preview_target_path(media: Media, filename: &str, base_path: &str) -> Result<String, String> {
+    let metadata = call_guessit(filename)
+        .await
+        .map_err(|e| format!("Unparseable name: {}", e))?;
+
+    match media {
+        Media::TV | Media::Anime => generate_tv_path(base_path, &metadata),
+        Media::Movie => generate_movie_path(base_path, &metadata),
+    }
+    .map_err(|e| format!("Unparseable name: {}", e))
+}
+
+/// Show name of a release title, sanitized like the show folders of a plan
+pub async fn guess_show_name(title: &str) -> Result<String, String> {
+    let metadata = call_guessit(title)
+        .await
+        .map_err(|e| format!("Unparseable name: {}", e))?;
+
+    let show = sanitize_filename(&metadata.title);
+    if show.trim().is_empty() {
+        return Err("Unparseable name: no title".to_string());
+    }
+
+    Ok(show)
+}
+
+fn file_display_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path)
+        .to_string()
+}
+
+/// Hardlink `files` (and their subtitles) into the library layout under
+/// `library_path`, copying when a hardlink isn't possible
+///
+/// The originals are left untouched so they keep seeding. Targets that
+/// already exist are treated as harvested before and skipped.
+pub async fn harvest_files(media: Media, files: &[String], library_path: &str) -> Result<String, String> {
+    let mut linked = 0;
+    let mut copied = 0;
+    let mut already_harvested = 0;
+    let mut unparseable = Vec::new();
+    let mut errors = Vec::new();
+    let mut subtitle_cache = SubtitleCache::new();
+
+    for file_path in files {
+        let target = match call_guessit(file_path).await {
+            Ok(metadata) => match media {
+                Media::TV | Media::Anime => generate_tv_path(library_path, &metadata),
+                Media::Movie => generate_movie_path(library_path, &metadata),
+            },
+            Err(e) => Err(e),
+        };
+
+        let target = match target {
+            Ok(target) => target,
+            Err(_) => {
+                unparseable.push(file_display_name(file_path));
+                continue;
+            }
+        };
+
+        let target_dir = Path::new(&target)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .to_path_buf();
+        let mut pairs = vec![(file_path.clone(), PathBuf::from(&target))];
+        for sub_path in find_matching_subtitles(file_path, &mut subtitle_cache) {
+            let sub_target = target_dir.join(file_display_name(&sub_path));
+            pairs.push((sub_path, sub_target));
+        }
 
-    if let Ok(entries) = std::fs::read_dir(parent) {
-        for entry in entries.flatten() {
-            let path = entry.path();
+        for (source, target) in pairs {
+            let name = file_display_name(&source);
 
-            if !path.is_file() {
+            if target.exists() {
+                already_harvested += 1;
                 continue;
             }
 
-            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                let ext_with_dot = format!(".{}", ext);
-                if !SUBTITLE_EXTENSIONS.contains(&ext_with_dot.as_str()) {
-                    continue;
-                }
+            if let Err(e) = std::fs::create_dir_all(&target_dir) {
+                errors.push(format!("{}: Failed to create directory - {}", name, e));
+                continue;
             }
 
-            if let Some(file_name) = path.file_name().and_then(|n| n.to_str()) {
-                // Match exact name or name with language code
-                // e.g., "show.s01e01.srt" or "show.s01e01.en.srt"
-                if file_name.starts_with(video_stem) {
-                    subtitles.push(path.to_string_lossy().to_string());
-                }
+            // Hardlinks only work within one filesystem, copy otherwise
+            match std::fs::hard_link(&source, &target) {
+                Ok(_) => linked += 1,
+                Err(_) => match std::fs::copy(&source, &target) {
+                    Ok(_) => copied += 1,
+                    Err(e) => errors.push(format!("{}: Failed to copy - {}", name, e)),
+                },
             }
         }
     }
 
-    subtitles.sort();
-    subtitles
+    let mut result = format!(
+        "🌾 Harvest complete!\n• {} linked, {} copied\n• {} already in the library",
+        linked, copied, already_harvested
+    );
+
+    if !unparseable.is_empty() {
+        result.push_str(&format!("\n• {} unparseable:\n", unparseable.len()));
+        for name in unparseable.iter().take(10) {
+            result.push_str(&format!("  - {}\n", name));
+        }
+    }
+
+    if !errors.is_empty() {
+        result.push_str(&format!("\n• {} errors:\n", errors.len()));
+        for error in errors.iter().take(10) {
+            result.push_str(&format!("  - {}\n", error));
+        }
+    }
+
+    if linked + copied == 0 && !errors.is_empty() {
+        Err(result)
+    } else {
+        Ok(result)
+    }
+}
+
+/// Video files of a torrent, which is either a single file or a directory
+pub fn torrent_video_files(path: &str) -> Result<Vec<String>, String> {
+    if Path::new(path).is_dir() {
+        scan_files_recursive(path, VIDEO_EXTENSIONS, None, &Exclusions::from_env())
+    } else if VIDEO_EXTENSIONS.iter().any(|ext| path.to_lowercase().ends_with(ext)) {
+        Ok(vec![path.to_string()])
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+const GUESSIT_BATCH_SIZE: usize = 10;
+
+/// Run guessit on a batch of files concurrently
+async fn guess_batch(files: &[String]) -> Result<Vec<(String, Result<GuessitMetadata, String>)>, String> {
+    let tasks: Vec<_> = files
+        .iter()
+        .cloned()
+        .map(|file_path| {
+            tokio::spawn(async move {
+                let result = call_guessit(&file_path).await;
+                (file_path, result)
+            })
+        })
+        .collect();
+
+    let mut results = Vec::new();
+    for task in tasks {
+        results.push(task.await.map_err(|e| format!("Task failed: {}", e))?);
+    }
+
+    Ok(results)
+}
+
+/// Where an `S01E02` style marker starts in a file name, with its season and
+/// episodes (`S01E02E03` and `S01E02-E03` give both episodes)
+pub fn episode_marker(name: &str) -> Option<(usize, u32, Vec<u32>)> {
+    let bytes = name.as_bytes();
+
+    for start in 0..bytes.len() {
+        if !bytes[start].eq_ignore_ascii_case(&b's') {
+            continue;
+        }
+        // Only at the start of a word
+        if start > 0 && bytes[start - 1].is_ascii_alphanumeric() {
+            continue;
+        }
+
+        let mut i = start + 1;
+        let Some(season) = read_number(bytes, &mut i) else {
+            continue;
+        };
+
+        let mut episodes = Vec::new();
+        loop {
+            let mut next = i;
+            if bytes.get(next) == Some(&b'-') {
+                next += 1;
+            }
+            if !bytes.get(next).is_some_and(|b| b.eq_ignore_ascii_case(&b'e')) {
+                break;
+            }
+            next += 1;
+            match read_number(bytes, &mut next) {
+                Some(episode) => episodes.push(episode),
+                None => break,
+            }
+            i = next;
+        }
+
+        // The marker has to end the word too, "S01E02x264" is not one
+        if !episodes.is_empty() && !bytes.get(i).is_some_and(|b| b.is_ascii_alphanumeric()) {
+            return Some((start, season, episodes));
+        }
+    }
+
+    None
+}
+
+/// Up to three digits at `i`, moving `i` past them
+fn read_number(bytes: &[u8], i: &mut usize) -> Option<u32> {
+    let start = *i;
+    while *i < bytes.len() && *i - start < 3 && bytes[*i].is_ascii_digit() {
+        *i += 1;
+    }
+
+    std::str::from_utf8(&bytes[start..*i]).ok()?.parse().ok()
+}
+
+/// Season packs with fewer files than this are guessed file by file
+const PACK_MIN_FILES: usize = 2;
+
+// Show (alphanumerics of the name before the marker, lowercased), season
+// and episodes of a file's `S01E02` marker
+type PackMarker = (String, u32, Vec<u32>);
+
+/// Markers of the files and the index of the file whose guess each file
+/// reuses: its own, or the first file of its season pack
+fn pack_sources(files: &[String]) -> (Vec<Option<PackMarker>>, Vec<usize>) {
+    let markers: Vec<_> = files
+        .iter()
+        .map(|file| {
+            let name = Path::new(file).file_name().and_then(|n| n.to_str()).unwrap_or(file);
+            episode_marker(name).map(|(start, season, episodes)| {
+                let show: String = name[..start]
+                    .chars()
+                    .filter(|c| c.is_alphanumeric())
+                    .collect::<String>()
+                    .to_lowercase();
+                (show, season, episodes)
+            })
+        })
+        .collect();
+
+    let mut packs: HashMap<(Option<&Path>, &str, u32), Vec<usize>> = HashMap::new();
+    for (index, marker) in markers.iter().enumerate() {
+        if let Some((show, season, _)) = marker {
+            if !show.is_empty() {
+                packs
+                    .entry((Path::new(&files[index]).parent(), show.as_str(), *season))
+                    .or_default()
+                    .push(index);
+            }
+        }
+    }
+
+    let mut source: Vec<usize> = (0..files.len()).collect();
+    for members in packs.values().filter(|members| members.len() >= PACK_MIN_FILES) {
+        for &index in members {
+            source[index] = members[0];
+        }
+    }
+
+    (markers, source)
+}
+
+/// Files whose pack source failed to guess, pointed back at themselves so
+/// one bad name doesn't take the whole pack down with it
+fn unshare_failed_guesses(
+    files: &[String],
+    source: &mut [usize],
+    guesses: &HashMap<String, Result<GuessitMetadata, String>>,
+) -> Vec<String> {
+    let mut retry = Vec::new();
+    for index in 0..files.len() {
+        let failed = guesses.get(&files[source[index]]).is_none_or(|guess| guess.is_err());
+        if source[index] != index && failed {
+            source[index] = index;
+            retry.push(files[index].clone());
+        }
+    }
+    retry
+}
+
+/// Each file with its guess, pack members get their own season, episodes
+/// and extension on top of the shared guess
+fn pack_results(
+    files: &[String],
+    markers: &[Option<PackMarker>],
+    source: &[usize],
+    guesses: &HashMap<String, Result<GuessitMetadata, String>>,
+) -> Vec<(String, Result<GuessitMetadata, String>)> {
+    files
+        .iter()
+        .enumerate()
+        .map(|(index, file_path)| {
+            let result = guesses
+                .get(&files[source[index]])
+                .cloned()
+                .unwrap_or_else(|| Err("guessit returned nothing".to_string()));
+
+            let result = match (&markers[index], source[index] == index) {
+                (Some((_, season, episodes)), false) => result.map(|mut metadata| {
+                    metadata.season = Some(serde_json::json!(season));
+                    metadata.episode = Some(match episodes[..] {
+                        [episode] => serde_json::json!(episode),
+                        _ => serde_json::json!(episodes),
+                    });
+                    metadata.extension = file_extension(file_path);
+                    metadata
+                }),
+                _ => result,
+            };
+
+            (file_path.clone(), result)
+        })
+        .collect()
+}
+
+/// Run guessit on a list of files, GUESSIT_BATCH_SIZE at a time
+///
+/// Files in the same folder that share a show name and season (a season
+/// pack) only cost one guessit run: the title and year come from the first
+/// file and every file keeps the episode from its own `S01E02` marker.
+/// When that first guess fails, the rest of the pack is guessed file by file.
+async fn guess_files(files: &[String]) -> Result<Vec<(String, Result<GuessitMetadata, String>)>, String> {
+    let started = std::time::Instant::now();
+
+    let (markers, mut source) = pack_sources(files);
+
+    let mut to_guess: Vec<String> = (0..files.len())
+        .filter(|&index| source[index] == index)
+        .map(|index| files[index].clone())
+        .collect();
+
+    let mut guesses = HashMap::new();
+    for chunk in to_guess.chunks(GUESSIT_BATCH_SIZE) {
+        guesses.extend(guess_batch(chunk).await?);
+    }
+
+    let retry = unshare_failed_guesses(files, &mut source, &guesses);
+    for chunk in retry.chunks(GUESSIT_BATCH_SIZE) {
+        guesses.extend(guess_batch(chunk).await?);
+    }
+    to_guess.extend(retry);
+
+    let results = pack_results(files, &markers, &source, &guesses);
+
+    println!(
+        "guessit: {} runs for {} files in {:.1}s",
+        to_guess.len(),
+        files.len(),
+        started.elapsed().as_secs_f32()
+    );
+
+    Ok(results)
+}
+
+/// Count how many videos guessit can parse, and the size of the ones it can't,
+/// without building a plan
+pub async fn tally_unparseable(media: Media, scan_path: &str) -> Result<(usize, usize, u64), String> {
+    let video_files = scan_files_recursive(scan_path, VIDEO_EXTENSIONS, None, &Exclusions::from_env())?;
+
+    let mut parseable = 0;
+    let mut unparseable = 0;
+    let mut unparseable_size = 0;
+
+    for (file_path, result) in guess_files(&video_files).await? {
+        // The base doesn't matter, only whether a path can be built
+        let target = result.and_then(|metadata| match media {
+            Media::TV | Media::Anime => generate_tv_path("", &metadata),
+            Media::Movie => generate_movie_path("", &metadata),
+        });
+
+        if target.is_ok() {
+            parseable += 1;
+        } else {
+            unparseable += 1;
+            unparseable_size += std::fs::metadata(&file_path).map(|m| m.len()).unwrap_or(0);
+        }
+    }
+
+    Ok((parseable, unparseable, unparseable_size))
 }
 
 /// Generate complete restructure plan
+///
+/// Files are scanned from `scan_path` and their targets are built under the
+/// root `target` picks for each file. `modified_since` limits the plan to
+/// recently modified files.
 pub async fn generate_restructure_plan(
-    media: Media,
-    base_path: &str,
+    target: PlanTarget,
+    scan_path: &str,
+    modified_since: Option<SystemTime>,
 ) -> Result<RestructurePlan, String> {
     // Scan for video files
-    let video_files = scan_files_recursive(base_path, VIDEO_EXTENSIONS)?;
+    let video_files = scan_files_recursive(scan_path, VIDEO_EXTENSIONS, modified_since, &Exclusions::from_env())?;
 
     if video_files.is_empty() {
         return Ok(RestructurePlan {
-            media_type: media,
+            media_type: target.media(),
             operations: Vec::new(),
             unparseable_files: Vec::new(),
+            duplicate_targets: Vec::new(),
+            multi_season_files: Vec::new(),
+            scan_path: scan_path.to_string(),
+            cleanup: Cleanup::default(),
         });
     }
 
     let mut operations = Vec::new();
     let mut unparseable_files = Vec::new();
+    let mut duplicate_targets = Vec::new();
+    let mut multi_season_files = Vec::new();
+    let mut assigned_targets = HashSet::new();
+    let mut subtitle_cache = SubtitleCache::new();
+
+    for (file_path, result) in guess_files(&video_files).await? {
+        match result {
+            Ok(metadata) => {
+                let (media, base_path) = target.route(&metadata);
+                if media != Media::Movie && metadata.seasons().len() > 1 {
+                    multi_season_files.push(file_path);
+                    continue;
+                }
 
-    // Process files in batches of 10 concurrently
-    let batch_size = 10;
-    for chunk in video_files.chunks(batch_size) {
-        let mut tasks = Vec::new();
-
-        for file_path in chunk {
-            let file_path = file_path.clone();
-            let base_path = base_path.to_string();
-            let media = media.clone();
-
-            tasks.push(tokio::spawn(async move {
-                (file_path.clone(), call_guessit(&file_path).await, base_path, media)
-            }));
-        }
-
-        // Wait for batch to complete
-        for task in tasks {
-            let (file_path, result, base_path, media) = task
-                .await
-                .map_err(|e| format!("Task failed: {}", e))?;
-
-            match result {
-                Ok(metadata) => {
-                    // Generate target path
-                    let target_path = match media {
-                        Media::TV => generate_tv_path(&base_path, &metadata),
-                        Media::Movie => generate_movie_path(&base_path, &metadata),
-                    };
-
-                    let target_path = match target_path {
-                        Ok(p) => p,
-                        Err(_) => {
-                            unparseable_files.push(file_path.clone());
-                            continue;
-                        }
-                    };
-
-                    // Skip if source == target (already organized)
-                    let source_canonical = Path::new(&file_path)
-                        .canonicalize()
-                        .unwrap_or_else(|_| PathBuf::from(&file_path));
-                    let target_canonical = Path::new(&target_path)
-                        .canonicalize()
-                        .unwrap_or_else(|_| PathBuf::from(&target_path));
+                // Generate target path
+                let target_path = match media {
+                    Media::TV | Media::Anime => generate_tv_path(base_path, &metadata),
+                    Media::Movie => generate_movie_path(base_path, &metadata),
+                };
 
-                    if source_canonical == target_canonical {
+                let target_path = match target_path {
+                    Ok(p) => p,
+                    Err(_) => {
+                        unparseable_files.push(file_path.clone());
                         continue;
                     }
+                };
+
+                // Skip if source == target (already organized)
+                let source_canonical = Path::new(&file_path)
+                    .canonicalize()
+                    .unwrap_or_else(|_| PathBuf::from(&file_path));
+                let target_canonical = Path::new(&target_path)
+                    .canonicalize()
+                    .unwrap_or_else(|_| PathBuf::from(&target_path));
+
+                if source_canonical == target_canonical {
+                    continue;
+                }
+
+                // Resolve collisions
+                let final_target = resolve_collision(&target_path, &assigned_targets);
 
-                    // Resolve collisions
-                    let final_target = resolve_collision(&target_path);
+                // Get display name
+                let display_name = Path::new(&file_path)
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or(&file_path)
+                    .to_string();
 
-                    // Get display name
-                    let display_name = Path::new(&file_path)
+                if assigned_targets.contains(&target_path) {
+                    duplicate_targets.push(display_name.clone());
+                }
+                assigned_targets.insert(final_target.clone());
+
+                // Add video file operation
+                operations.push(MoveOperation {
+                    source_path: file_path.clone(),
+                    target_path: final_target.clone(),
+                    display_name,
+                    is_subtitle: false,
+                });
+
+                // Find and add subtitle operations
+                let subtitles = find_matching_subtitles(&file_path, &mut subtitle_cache);
+                for sub_path in subtitles {
+                    let sub_name = Path::new(&sub_path)
                         .file_name()
                         .and_then(|n| n.to_str())
-                        .unwrap_or(&file_path)
-                        .to_string();
+                        .map(|s| s.to_string())
+                        .unwrap_or_else(|| sub_path.clone());
+
+                    // Generate subtitle target path (same directory as video)
+                    let target_dir = Path::new(&final_target)
+                        .parent()
+                        .unwrap_or_else(|| Path::new(""));
+                    let sub_target = target_dir.join(&sub_name);
+                    let sub_target = resolve_collision(&sub_target.to_string_lossy(), &assigned_targets);
+                    assigned_targets.insert(sub_target.clone());
 
-                    // Add video file operation
                     operations.push(MoveOperation {
-                        source_path: file_path.clone(),
-                        target_path: final_target.clone(),
-                        display_name,
-                        is_subtitle: false,
+                        source_path: sub_path,
+                        target_path: sub_target,
+                        display_name: sub_name,
+                        is_subtitle: true,
                     });
-
-                    // Find and add subtitle operations
-                    let subtitles = find_matching_subtitles(&file_path);
-                    for sub_path in subtitles {
-                        let sub_name = Path::new(&sub_path)
-                            .file_name()
-                            .and_then(|n| n.to_str())
-                            .map(|s| s.to_string())
-                            .unwrap_or_else(|| sub_path.clone());
-
-                        // Generate subtitle target path (same directory as video)
-                        let target_dir = Path::new(&final_target)
-                            .parent()
-                            .unwrap_or_else(|| Path::new(""));
-                        let sub_target = target_dir.join(&sub_name);
-                        let sub_target = resolve_collision(&sub_target.to_string_lossy());
-
-                        operations.push(MoveOperation {
-                            source_path: sub_path,
-                            target_path: sub_target,
-                            display_name: sub_name,
-                            is_subtitle: true,
-                        });
-                    }
-                }
-                Err(_) => {
-                    unparseable_files.push(file_path);
                 }
             }
+            Err(_) => {
+                unparseable_files.push(file_path);
+            }
         }
     }
 
     Ok(RestructurePlan {
-        media_type: media,
+        media_type: target.media(),
         operations,
         unparseable_files,
+        duplicate_targets,
+        multi_season_files,
+        scan_path: scan_path.to_string(),
+        cleanup: Cleanup::default(),
     })
 }
 
 /// Format the restructure plan for display
 pub fn format_restructure_plan(plan: &RestructurePlan) -> String {
-    if plan.operations.is_empty() && plan.unparseable_files.is_empty() {
+    if plan.operations.is_empty()
+        && plan.unparseable_files.is_empty()
+        && plan.multi_season_files.is_empty()
+    {
         return "✅ Nothing to restructure".to_string();
     }
 
     let emoji = match plan.media_type {
-        Media::TV => "📺",
-        Media::Movie => "🎬",
+        Some(Media::TV) => "📺",
+        Some(Media::Movie) => "🎬",
+        Some(Media::Anime) => "🍥",
+        None => "🔀",
     };
 
     let mut output = format!("{} Restructure Plan:\n\n", emoji);
@@ -475,6 +1157,24 @@ pub fn format_restructure_plan(plan: &RestructurePlan) -> String {
         }
     }
 
+    if !plan.duplicate_targets.is_empty() {
+        output.push_str("\n⚠️ Same target as another file (renamed with a -N suffix):\n");
+        for name in plan.duplicate_targets.iter().take(20) {
+            output.push_str(&format!("  • {}\n", name));
+        }
+    }
+
+    if !plan.multi_season_files.is_empty() {
+        output.push_str("\n⚠️ Multi-season files (move these by hand):\n");
+        for file in plan.multi_season_files.iter().take(20) {
+            let display = Path::new(file)
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or(file);
+            output.push_str(&format!("  • {}\n", display));
+        }
+    }
+
     // Add unparseable files warning
     if !plan.unparseable_files.is_empty() {
         output.push_str("\n⚠️ Unparseable files (will be skipped):\n");
@@ -494,7 +1194,7 @@ pub fn format_restructure_plan(plan: &RestructurePlan) -> String {
 
     output.push_str("\nReply with:\n");
     output.push_str("• \"all\" - Execute all operations\n");
-    output.push_str("• \"apply 1 2 5\" - Execute specific operations\n");
+    output.push_str("• \"apply 1 2 5\" or \"apply 1-4\" - Execute specific operations\n");
     output.push_str("• \"cancel\" - Cancel restructure\n");
 
     output
@@ -511,82 +1211,107 @@ pub fn parse_restructure_reply(
         return Err("Restructure cancelled".to_string());
     }
 
-    if reply == "apply all" || reply == "apply" || reply == "all" {
+    if reply == "apply" {
         return Ok(plan.operations.clone());
     }
 
-    if reply.starts_with("apply ") {
-        let indices_str = reply.strip_prefix("apply ").unwrap().trim();
-        let mut indices: Vec<usize> = Vec::new();
-
-        for part in indices_str.split_whitespace() {
-            match part.parse::<usize>() {
-                Ok(idx) => indices.push(idx),
-                Err(_) => return Err(format!("Invalid number: {}", part)),
-            }
-        }
-
-        // Remove duplicates
-        indices.sort_unstable();
-        indices.dedup();
-
-        // Group operations by video file and collect selected ones
-        let mut selected_ops = Vec::new();
-        let mut current_index = 0;
-        let mut i = 0;
+    let selection = match reply.strip_prefix("apply ") {
+        Some(selection) => selection,
+        None if reply == "all" => "all",
+        None => return Err("Invalid reply. Use 'apply all', 'apply 1 2 5', 'apply 1-4' or 'cancel'".to_string()),
+    };
 
-        while i < plan.operations.len() {
-            let op = &plan.operations[i];
+    let video_count = plan.operations.iter().filter(|op| !op.is_subtitle).count();
+    let indices = parse_index_selection(selection, video_count)?;
 
-            if !op.is_subtitle {
-                current_index += 1;
+    // Each video is followed by its subtitles, they're selected together
+    let mut selected_ops = Vec::new();
+    let mut current_index = 0;
+    let mut selected = false;
+    for op in &plan.operations {
+        if !op.is_subtitle {
+            current_index += 1;
+            selected = indices.contains(&current_index);
+        }
+        if selected {
+            selected_ops.push(op.clone());
+        }
+    }
 
-                if indices.contains(&current_index) {
-                    // Add the video file
-                    selected_ops.push(op.clone());
+    Ok(selected_ops)
+}
 
-                    // Add all associated subtitle files
-                    let mut j = i + 1;
-                    while j < plan.operations.len() && plan.operations[j].is_subtitle {
-                        selected_ops.push(plan.operations[j].clone());
-                        j += 1;
-                    }
-                    i = j;
-                } else {
-                    // Skip this video and its subtitles
-                    let mut j = i + 1;
-                    while j < plan.operations.len() && plan.operations[j].is_subtitle {
-                        j += 1;
-                    }
-                    i = j;
-                }
-            } else {
-                i += 1;
+/// Folders below `root` that held `moved_sources`, deepest first
+fn source_dirs(moved_sources: &[&str], root: &Path) -> Vec<PathBuf> {
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    for source in moved_sources {
+        let mut dir = Path::new(source).parent();
+        while let Some(current) = dir {
+            if current == root || !current.starts_with(root) {
+                break;
+            }
+            if !candidates.iter().any(|c| c == current) {
+                candidates.push(current.to_path_buf());
             }
+            dir = current.parent();
         }
+    }
 
-        if selected_ops.is_empty() {
-            return Err("No valid operations selected".to_string());
-        }
+    candidates.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    candidates
+}
 
-        // Validate all indices were in range
-        let max_index = current_index;
-        for idx in &indices {
-            if *idx == 0 || *idx > max_index {
-                return Err(format!("Index {} out of range (1-{})", idx, max_index));
+/// Files with one of `extensions` directly inside the source folders of
+/// `moved_sources`, the scan root itself is never looked at
+fn junk_files(moved_sources: &[&str], root: &Path, extensions: &[String]) -> Vec<PathBuf> {
+    let mut junk = Vec::new();
+    for dir in source_dirs(moved_sources, root) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            let name = path.to_string_lossy().to_lowercase();
+            if path.is_file() && extensions.iter().any(|ext| name.ends_with(ext.as_str())) {
+                junk.push(path);
             }
         }
+    }
 
-        Ok(selected_ops)
-    } else {
-        Err("Invalid reply. Use 'apply all', 'apply 1 2 5', or 'cancel'".to_string())
+    junk.sort();
+    junk
+}
+
+/// Source folders below `root` that hold nothing but other such folders
+/// once `moved_sources` are gone, deepest first
+fn empty_source_dirs(moved_sources: &[&str], root: &Path) -> Vec<PathBuf> {
+    let mut empty: Vec<PathBuf> = Vec::new();
+    for dir in source_dirs(moved_sources, root) {
+        let only_empty_dirs = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .map(|entry| entry.map(|e| e.path()))
+                .all(|path| path.is_ok_and(|path| empty.contains(&path))),
+            Err(_) => false,
+        };
+
+        if only_empty_dirs {
+            empty.push(dir);
+        }
     }
+
+    empty
 }
 
 /// Execute the move operations
-pub async fn execute_moves(operations: &[MoveOperation]) -> Result<String, String> {
+///
+/// Source folders below `source_root` left empty are reported, and removed
+/// when `cleanup.empty_dirs` is set. With `cleanup.junk` the files matching
+/// RESTRUCTURE_CLEANUP_EXTENSIONS in those folders are deleted first, so
+/// release cruft doesn't keep them from being removed. Folders with anything
+/// else left in them are never removed.
+pub async fn execute_moves(operations: &[MoveOperation], source_root: &str, cleanup: Cleanup) -> Result<String, String> {
     let mut success_count = 0;
     let mut errors = Vec::new();
+    let mut moved_sources = Vec::new();
 
     for op in operations {
         let source = Path::new(&op.source_path);
@@ -604,6 +1329,7 @@ pub async fn execute_moves(operations: &[MoveOperation]) -> Result<String, Strin
         match std::fs::rename(source, target) {
             Ok(_) => {
                 success_count += 1;
+                moved_sources.push(op.source_path.as_str());
             }
             Err(e) => {
                 // If cross-filesystem error, try copy + delete
@@ -617,6 +1343,7 @@ pub async fn execute_moves(operations: &[MoveOperation]) -> Result<String, Strin
                                 ));
                             } else {
                                 success_count += 1;
+                                moved_sources.push(op.source_path.as_str());
                             }
                         }
                         Err(copy_err) => {
@@ -644,9 +1371,483 @@ pub async fn execute_moves(operations: &[MoveOperation]) -> Result<String, Strin
         }
     }
 
+    let extensions = junk_extensions();
+    let junk = junk_files(&moved_sources, Path::new(source_root), &extensions);
+    if !junk.is_empty() {
+        if cleanup.junk {
+            let removed: Vec<&PathBuf> = junk
+                .iter()
+                .filter(|file| std::fs::remove_file(file).is_ok())
+                .collect();
+            result.push_str(&format!("\n• {} junk files deleted:\n", removed.len()));
+            for file in removed.iter().take(10) {
+                result.push_str(&format!("  - {}\n", file.display()));
+            }
+        } else {
+            result.push_str(&format!(
+                "\n• {} junk files left in the source folders (restructure with --cleanup-junk to delete them)\n",
+                junk.len()
+            ));
+        }
+    }
+
+    let empty_dirs = empty_source_dirs(&moved_sources, Path::new(source_root));
+    if !empty_dirs.is_empty() {
+        if cleanup.empty_dirs {
+            // remove_dir refuses non-empty folders, so nothing new is ever lost
+            let removed: Vec<&PathBuf> = empty_dirs
+                .iter()
+                .filter(|dir| std::fs::remove_dir(dir).is_ok())
+                .collect();
+            result.push_str(&format!("\n• {} empty source folders removed:\n", removed.len()));
+            for dir in removed.iter().take(10) {
+                result.push_str(&format!("  - {}\n", dir.display()));
+            }
+        } else {
+            result.push_str(&format!(
+                "\n• {} source folders are now empty (restructure with --cleanup to remove them):\n",
+                empty_dirs.len()
+            ));
+            for dir in empty_dirs.iter().take(10) {
+                result.push_str(&format!("  - {}\n", dir.display()));
+            }
+        }
+    }
+
     if success_count == 0 {
         Err(result)
     } else {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(title: &str, year: Option<i32>, season: Option<u32>, episode: serde_json::Value) -> GuessitMetadata {
+        GuessitMetadata {
+            title: title.to_string(),
+            year,
+            season: season.map(|season| serde_json::json!(season)),
+            episode: if episode.is_null() { None } else { Some(episode) },
+            extension: ".mkv".to_string(),
+        }
+    }
+
+    fn op(source: &str, is_subtitle: bool) -> MoveOperation {
+        MoveOperation {
+            source_path: source.to_string(),
+            target_path: format!("/target/{}", source),
+            display_name: source.to_string(),
+            is_subtitle,
+        }
+    }
+
+    fn plan() -> RestructurePlan {
+        RestructurePlan {
+            media_type: Some(Media::TV),
+            operations: vec![
+                op("one.mkv", false),
+                op("one.en.srt", true),
+                op("two.mkv", false),
+                op("three.mkv", false),
+                op("three.en.srt", true),
+                op("three.es.srt", true),
+            ],
+            unparseable_files: Vec::new(),
+            duplicate_targets: Vec::new(),
+            multi_season_files: Vec::new(),
+            scan_path: "/source".to_string(),
+            cleanup: Cleanup::default(),
+        }
+    }
+
+    fn sources(operations: &[MoveOperation]) -> Vec<&str> {
+        operations.iter().map(|op| op.source_path.as_str()).collect()
+    }
+
+    #[test]
+    fn tv_path_single_episode() {
+        let meta = metadata("The Office", None, Some(2), serde_json::json!(3));
+        assert_eq!(
+            generate_tv_path("/tv", &meta).unwrap(),
+            "/tv/The Office/Season 02/The Office - S02E03.mkv"
+        );
+    }
+
+    #[test]
+    fn tv_path_multi_episode_is_sorted_and_joined() {
+        let meta = metadata("Lost", None, Some(1), serde_json::json!([2, 1]));
+        assert_eq!(
+            generate_tv_path("/tv", &meta).unwrap(),
+            "/tv/Lost/Season 01/Lost - S01E01-E02.mkv"
+        );
+    }
+
+    #[test]
+    fn tv_path_requires_season_and_episode() {
+        let no_season = metadata("Lost", None, None, serde_json::json!(1));
+        let no_episode = metadata("Lost", None, Some(1), serde_json::Value::Null);
+
+        assert!(generate_tv_path("/tv", &no_season).is_err());
+        assert!(generate_tv_path("/tv", &no_episode).is_err());
+    }
+
+    #[test]
+    fn guessit_single_season_json() {
+        let meta: GuessitMetadata =
+            serde_json::from_str(r#"{"title": "Lost", "season": 2, "episode": 5, "type": "episode"}"#).unwrap();
+
+        assert_eq!(meta.seasons(), vec![2]);
+        assert_eq!(meta.episodes(), vec![5]);
+    }
+
+    #[test]
+    fn guessit_multi_season_json_needs_manual_handling() {
+        let mut meta: GuessitMetadata =
+            serde_json::from_str(r#"{"title": "Lost", "season": [1, 2, 3], "type": "episode"}"#).unwrap();
+        meta.extension = ".mkv".to_string();
+
+        assert_eq!(meta.seasons(), vec![1, 2, 3]);
+        assert!(generate_tv_path("/tv", &meta).is_err());
+    }
+
+    #[test]
+    fn movie_path_with_and_without_year() {
+        let with_year = metadata("The Matrix", Some(1999), None, serde_json::Value::Null);
+        let without_year = metadata("The Matrix", None, None, serde_json::Value::Null);
+
+        assert_eq!(
+            generate_movie_path("/movies", &with_year).unwrap(),
+            "/movies/The Matrix (1999)/The Matrix (1999).mkv"
+        );
+        assert_eq!(
+            generate_movie_path("/movies", &without_year).unwrap(),
+            "/movies/The Matrix/The Matrix.mkv"
+        );
+    }
+
+    #[test]
+    fn auto_target_routes_episodes_to_tv_and_the_rest_to_movies() {
+        let target = PlanTarget::Auto {
+            tv_path: "/tv".to_string(),
+            movie_path: "/movies".to_string(),
+        };
+        let episode = metadata("The Office", None, Some(2), serde_json::json!(3));
+        let season_pack = metadata("The Office", None, Some(2), serde_json::Value::Null);
+        let movie = metadata("The Matrix", Some(1999), None, serde_json::Value::Null);
+
+        assert_eq!(target.route(&episode), (Media::TV, "/tv"));
+        assert_eq!(target.route(&season_pack), (Media::TV, "/tv"));
+        assert_eq!(target.route(&movie), (Media::Movie, "/movies"));
+    }
+
+    #[test]
+    fn sanitize_replaces_reserved_characters() {
+        assert_eq!(sanitize_filename("What If...? A/B: C"), "What If...- A-B- C");
+    }
+
+    #[test]
+    fn sanitize_strips_control_characters_and_trailing_dots() {
+        assert_eq!(sanitize_filename("Mr. Robot\u{0007} S.H.I.E.L.D. "), "Mr. Robot S.H.I.E.L.D");
+        assert_eq!(sanitize_filename("Zero\u{200B}Width\u{202E}"), "ZeroWidth");
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("12h").unwrap(), Duration::from_secs(12 * 3600));
+        assert_eq!(parse_duration("7D").unwrap(), Duration::from_secs(7 * 86400));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("7y").is_err());
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("99999999999999999w").is_err());
+        assert_eq!(parse_duration("18446744073709551615s").unwrap(), Duration::from_secs(u64::MAX));
+    }
+
+    #[test]
+    fn resolve_collision_appends_counter() {
+        let dir = std::env::temp_dir().join(format!("restructure-collision-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let target = dir.join("Movie.mkv");
+        let target = target.to_string_lossy().to_string();
+        assert_eq!(resolve_collision(&target, &HashSet::new()), target);
+
+        std::fs::write(&target, b"").unwrap();
+        std::fs::write(dir.join("Movie-1.mkv"), b"").unwrap();
+        assert_eq!(
+            resolve_collision(&target, &HashSet::new()),
+            dir.join("Movie-2.mkv").to_string_lossy().to_string()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_collision_avoids_targets_assigned_in_the_same_plan() {
+        // Two rips of the same episode map to the same new path
+        let target = "/nonexistent/Show/Season 01/Show - S01E01.mkv".to_string();
+        let mut assigned = HashSet::new();
+
+        let first = resolve_collision(&target, &assigned);
+        assigned.insert(first.clone());
+        let second = resolve_collision(&target, &assigned);
+
+        assert_eq!(first, target);
+        assert_eq!(second, "/nonexistent/Show/Season 01/Show - S01E01-1.mkv");
+    }
+
+    #[test]
+    fn subtitles_are_matched_from_one_directory_read() {
+        let dir = std::env::temp_dir().join(format!("restructure-subtitles-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["show.s01e01.en.srt", "show.s01e01.srt", "show.s01e02.srt", "notes.txt"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let mut cache = SubtitleCache::new();
+        let first = find_matching_subtitles(&dir.join("show.s01e01.mkv").to_string_lossy(), &mut cache);
+        let second = find_matching_subtitles(&dir.join("show.s01e02.mkv").to_string_lossy(), &mut cache);
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(first.len(), 2);
+        assert_eq!(second, vec![dir.join("show.s01e02.srt").to_string_lossy().to_string()]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn subtitle_languages_come_from_the_name_suffix() {
+        assert_eq!(subtitle_language("show.s01e01.en.srt", "show.s01e01").as_deref(), Some("en"));
+        assert_eq!(subtitle_language("show.s01e01.eng.srt", "show.s01e01").as_deref(), Some("en"));
+        assert_eq!(subtitle_language("show.s01e01.sdh.en.srt", "show.s01e01").as_deref(), Some("en"));
+        assert_eq!(subtitle_language("show.s01e01.por_BR.srt", "show.s01e01").as_deref(), Some("pt-br"));
+        assert_eq!(subtitle_language("show.s01e01.HI.srt", "show.s01e01"), None);
+        assert_eq!(subtitle_language("show.s01e01.cc.srt", "show.s01e01"), None);
+        assert_eq!(subtitle_language("show.s01e01.pt-BR.forced.srt", "show.s01e01").as_deref(), Some("pt-br"));
+        assert_eq!(subtitle_language("show.s01e01.forced.es.srt", "show.s01e01").as_deref(), Some("es"));
+        assert_eq!(subtitle_language("show.s01e01.srt", "show.s01e01"), None);
+        assert_eq!(subtitle_language("show.s01e01.1080p.srt", "show.s01e01"), None);
+    }
+
+    #[test]
+    fn subtitle_language_preference_keeps_untagged_ones() {
+        let languages = vec!["en".to_string(), "pt".to_string()];
+
+        assert!(keeps_subtitle("movie.en.srt", "movie", &languages));
+        assert!(keeps_subtitle("movie.pt-BR.srt", "movie", &languages));
+        assert!(keeps_subtitle("movie.srt", "movie", &languages));
+        assert!(!keeps_subtitle("movie.es.srt", "movie", &languages));
+        assert!(keeps_subtitle("movie.es.srt", "movie", &[]));
+        // ISO 639-2 tags and hearing-impaired markers
+        assert!(keeps_subtitle("movie.eng.srt", "movie", &languages));
+        assert!(keeps_subtitle("movie.en.sdh.srt", "movie", &languages));
+        assert!(!keeps_subtitle("movie.spa.srt", "movie", &languages));
+        assert!(keeps_subtitle("movie.en.srt", "movie", &["eng".to_string()]));
+    }
+
+    #[test]
+    fn scan_skips_samples_and_extras() {
+        let dir = std::env::temp_dir().join(format!("restructure-samples-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("Extras")).unwrap();
+        for name in ["Movie.2020.1080p.mkv", "movie-sample.mkv", "Trailer.mp4", "Extras/Interview.mkv"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let exclusions = Exclusions {
+            words: vec!["sample".to_string(), "trailer".to_string()],
+            dirs: vec!["extras".to_string()],
+        };
+        let files = scan_files_recursive(&dir.to_string_lossy(), VIDEO_EXTENSIONS, None, &exclusions).unwrap();
+
+        assert_eq!(files, vec![dir.join("Movie.2020.1080p.mkv").to_string_lossy().to_string()]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn exclusions_match_whole_words() {
+        let exclusions = Exclusions {
+            words: vec!["sample".to_string()],
+            dirs: Vec::new(),
+        };
+
+        assert!(exclusions.excludes_file("Show.S01E01.SAMPLE.mkv", 30_000_000));
+        assert!(!exclusions.excludes_file("The.Sampler.2019.mkv", 30_000_000));
+    }
+
+    #[test]
+    fn excluded_words_in_titles_need_a_small_file() {
+        let exclusions = Exclusions {
+            words: vec!["trailer".to_string()],
+            dirs: Vec::new(),
+        };
+
+        assert!(!exclusions.excludes_file("Trailer.Park.Boys.S01E01.720p.mkv", 400_000_000));
+        assert!(!exclusions.excludes_file("Trailer Park Boys The Movie (2006).mkv", 1_500_000_000));
+        assert!(exclusions.excludes_file("Movie.2020.Trailer.mkv", 80_000_000));
+        // Without a marker the word is enough, whatever the size
+        assert!(exclusions.excludes_file("Trailer.mp4", 500_000_000));
+    }
+
+    #[test]
+    fn empty_source_dirs_only_lists_folders_with_nothing_left() {
+        let root = std::env::temp_dir().join(format!("restructure-cleanup-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("Release/Subs")).unwrap();
+        std::fs::create_dir_all(root.join("Other")).unwrap();
+        std::fs::write(root.join("Other/keep.nfo"), b"").unwrap();
+
+        let moved = [
+            root.join("Release/movie.mkv").to_string_lossy().to_string(),
+            root.join("Release/Subs/movie.srt").to_string_lossy().to_string(),
+            root.join("Other/movie2.mkv").to_string_lossy().to_string(),
+        ];
+        let moved: Vec<&str> = moved.iter().map(|m| m.as_str()).collect();
+
+        assert_eq!(
+            empty_source_dirs(&moved, &root),
+            vec![root.join("Release/Subs"), root.join("Release")]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn junk_files_stay_inside_the_moved_sources_folders() {
+        let root = std::env::temp_dir().join(format!("restructure-junk-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("Release")).unwrap();
+        std::fs::create_dir_all(root.join("Other")).unwrap();
+        for file in ["Release/release.nfo", "Release/Cover.JPG", "Release/movie.en.srt", "Other/other.nfo", "root.nfo"] {
+            std::fs::write(root.join(file), b"").unwrap();
+        }
+
+        let moved = [root.join("Release/movie.mkv").to_string_lossy().to_string()];
+        let moved: Vec<&str> = moved.iter().map(|m| m.as_str()).collect();
+        let extensions = vec![".nfo".to_string(), ".jpg".to_string()];
+
+        assert_eq!(
+            junk_files(&moved, &root, &extensions),
+            vec![root.join("Release/Cover.JPG"), root.join("Release/release.nfo")]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn reply_apply_selects_videos_with_their_subtitles() {
+        let selected = parse_restructure_reply("apply 1 3", &plan()).unwrap();
+        assert_eq!(
+            sources(&selected),
+            vec!["one.mkv", "one.en.srt", "three.mkv", "three.en.srt", "three.es.srt"]
+        );
+    }
+
+    #[test]
+    fn reply_apply_accepts_ranges() {
+        let selected = parse_restructure_reply("apply 2-3", &plan()).unwrap();
+        assert_eq!(
+            sources(&selected),
+            vec!["two.mkv", "three.mkv", "three.en.srt", "three.es.srt"]
+        );
+    }
+
+    #[test]
+    fn reply_all_selects_everything() {
+        assert_eq!(parse_restructure_reply("all", &plan()).unwrap().len(), 6);
+        assert_eq!(parse_restructure_reply("apply all", &plan()).unwrap().len(), 6);
+    }
+
+    #[test]
+    fn reply_rejects_out_of_range_and_garbage() {
+        assert!(parse_restructure_reply("apply 1 4", &plan()).is_err());
+        assert!(parse_restructure_reply("apply 2-4", &plan()).is_err());
+        assert!(parse_restructure_reply("apply one", &plan()).is_err());
+        assert!(parse_restructure_reply("whatever", &plan()).is_err());
+    }
+
+    #[test]
+    fn episode_marker_finds_season_and_episodes() {
+        assert_eq!(episode_marker("Show.Name.S01E02.1080p.mkv"), Some((10, 1, vec![2])));
+        assert_eq!(episode_marker("show s02e10 720p.mkv"), Some((5, 2, vec![10])));
+        assert_eq!(episode_marker("Show.S01E02E03.mkv"), Some((5, 1, vec![2, 3])));
+        assert_eq!(episode_marker("Show.S01E02-E03.mkv"), Some((5, 1, vec![2, 3])));
+    }
+
+    #[test]
+    fn episode_marker_needs_a_whole_word() {
+        assert_eq!(episode_marker("Movie.2010.1080p.mkv"), None);
+        assert_eq!(episode_marker("Glass01E02.mkv"), None);
+        assert_eq!(episode_marker("Show.S01E02x264.mkv"), None);
+        assert_eq!(episode_marker("Show.S01.Complete.mkv"), None);
+    }
+
+    fn guess(title: &str) -> GuessitMetadata {
+        GuessitMetadata {
+            title: title.to_string(),
+            year: None,
+            season: None,
+            episode: None,
+            extension: ".mkv".to_string(),
+        }
+    }
+
+    fn files(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn season_packs_share_the_first_guess() {
+        let files = files(&[
+            "/tv/Show/Show.S01E01.mkv",
+            "/tv/Show/Show.S01E02E03.mkv",
+            "/tv/Show/Show.S02E01.mkv",
+            "/tv/Other/Show.S01E04.mkv",
+            "/tv/Other/Movie.2010.mkv",
+        ]);
+
+        let (markers, source) = pack_sources(&files);
+        // The lone S02 file and the same show in another folder aren't a pack
+        assert_eq!(source, vec![0, 0, 2, 3, 4]);
+        assert_eq!(markers[1], Some(("show".to_string(), 1, vec![2, 3])));
+        assert_eq!(markers[4], None);
+
+        let mut guesses = HashMap::new();
+        for index in [0, 2, 3, 4].iter() {
+            guesses.insert(files[*index].clone(), Ok(guess("Show")));
+        }
+        let results = pack_results(&files, &markers, &source, &guesses);
+        let member = results[1].1.as_ref().unwrap();
+        assert_eq!(member.title, "Show");
+        assert_eq!(member.seasons(), vec![1]);
+        assert_eq!(member.episode, Some(serde_json::json!([2, 3])));
+    }
+
+    #[test]
+    fn a_failed_first_guess_only_fails_its_own_file() {
+        let files = files(&["/tv/Show/Show.S01E01.mkv", "/tv/Show/Show.S01E02.mkv", "/tv/Show/Show.S01E03.mkv"]);
+        let (markers, mut source) = pack_sources(&files);
+        assert_eq!(source, vec![0, 0, 0]);
+
+        let mut guesses = HashMap::new();
+        guesses.insert(files[0].clone(), Err("guessit failed".to_string()));
+
+        let retry = unshare_failed_guesses(&files, &mut source, &guesses);
+        assert_eq!(retry, files[1..].to_vec());
+        assert_eq!(source, vec![0, 1, 2]);
+
+        for file in retry {
+            guesses.insert(file, Ok(guess("Show")));
+        }
+        let results = pack_results(&files, &markers, &source, &guesses);
+        assert!(results[0].1.is_err());
+        assert_eq!(results[1].1.as_ref().unwrap().title, "Show");
+        assert_eq!(results[2].1.as_ref().unwrap().title, "Show");
+    }
+}
@@ -0,0 +1,127 @@
+use std::convert::TryFrom;
+use std::env;
+
+use crate::jackett::{category_ranges, request_jackett, request_jackett_by_imdb, TelegramJackettResponse};
+use crate::prowlarr::{request_prowlarr, request_prowlarr_by_imdb};
+use crate::transmission::Media;
+
+/// Optional narrowing of a /search
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchFilters {
+    /// Only this Torznab category (e.g. 2045)
+    pub category: Option<u32>,
+    /// Overrides JACKETT_MIN_SEEDERS
+    pub min_seeders: Option<i64>,
+    /// Only titles mentioning this resolution (e.g. 1080p)
+    pub resolution: Option<&'static str>,
+    /// Drop cam and telesync rips, titles with one of `CAM_TAGS`
+    pub exclude_cam: bool,
+    /// Only the categories of this media, for /search-tv and /search-movie
+    pub media: Option<Media>,
+}
+
+/// Resolutions a /search can be narrowed to
+pub const RESOLUTIONS: &[&str] = &["480p", "720p", "1080p", "2160p"];
+
+// Release tags of theater recordings, matched as whole words of the title
+const CAM_TAGS: &[&str] = &["cam", "camrip", "hdcam", "ts", "tsrip", "hdts", "telesync", "tc", "hdtc", "telecine"];
+
+impl SearchFilters {
+    /// Categories to ask the indexers for: the --cat one, or the first
+    /// category of each range of the media (e.g. 2000 for all of 2000-2999)
+    pub fn categories(&self) -> Vec<u32> {
+        if let Some(category) = self.category {
+            return vec![category];
+        }
+
+        match &self.media {
+            Some(media) => category_ranges()
+                .of(media)
+                .iter()
+                .filter_map(|(start, _)| u32::try_from(*start).ok())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Whether a result title passes the resolution and cam filters, ignoring case
+    pub fn matches_title(&self, title: &str) -> bool {
+        let title = title.to_lowercase();
+        self.resolution.is_none_or(|resolution| title.contains(resolution))
+            && !(self.exclude_cam && title.split(|c: char| !c.is_alphanumeric()).any(|word| CAM_TAGS.contains(&word)))
+    }
+
+    /// The title filters as the user typed them, for "no results" errors
+    pub fn title_filters(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self.resolution.into_iter().collect();
+        if self.exclude_cam {
+            names.push("-cam");
+        }
+        names
+    }
+}
+
+/// Indexer aggregator the torrent searches go through
+pub(crate) trait SearchProvider {
+    async fn search(&self, query: String, filters: SearchFilters) -> Result<TelegramJackettResponse, String>;
+
+    /// Search by IMDb id (e.g. tt0133093)
+    async fn search_imdb(&self, imdb_id: String) -> Result<TelegramJackettResponse, String>;
+}
+
+pub struct Jackett;
+
+impl SearchProvider for Jackett {
+    async fn search(&self, query: String, filters: SearchFilters) -> Result<TelegramJackettResponse, String> {
+        request_jackett(query, filters).await
+    }
+
+    async fn search_imdb(&self, imdb_id: String) -> Result<TelegramJackettResponse, String> {
+        request_jackett_by_imdb(imdb_id).await
+    }
+}
+
+pub struct Prowlarr;
+
+impl SearchProvider for Prowlarr {
+    async fn search(&self, query: String, filters: SearchFilters) -> Result<TelegramJackettResponse, String> {
+        request_prowlarr(query, filters).await
+    }
+
+    async fn search_imdb(&self, imdb_id: String) -> Result<TelegramJackettResponse, String> {
+        request_prowlarr_by_imdb(imdb_id).await
+    }
+}
+
+/// Backend picked by SEARCH_BACKEND
+pub enum SearchBackend {
+    Jackett(Jackett),
+    Prowlarr(Prowlarr),
+}
+
+impl SearchProvider for SearchBackend {
+    async fn search(&self, query: String, filters: SearchFilters) -> Result<TelegramJackettResponse, String> {
+        match self {
+            SearchBackend::Jackett(jackett) => jackett.search(query, filters).await,
+            SearchBackend::Prowlarr(prowlarr) => prowlarr.search(query, filters).await,
+        }
+    }
+
+    async fn search_imdb(&self, imdb_id: String) -> Result<TelegramJackettResponse, String> {
+        match self {
+            SearchBackend::Jackett(jackett) => jackett.search_imdb(imdb_id).await,
+            SearchBackend::Prowlarr(prowlarr) => prowlarr.search_imdb(imdb_id).await,
+        }
+    }
+}
+
+/// SEARCH_BACKEND is `jackett` (default) or `prowlarr`
+pub fn search_backend() -> Result<SearchBackend, String> {
+    let backend = env::var("SEARCH_BACKEND").unwrap_or_default();
+
+    match backend.trim().to_lowercase().as_str() {
+        "" | "jackett" => Ok(SearchBackend::Jackett(Jackett)),
+        "prowlarr" => Ok(SearchBackend::Prowlarr(Prowlarr)),
+        other => Err(format!("Unknown SEARCH_BACKEND {}, use jackett or prowlarr", other)),
+    }
+}
@@ -0,0 +1,121 @@
+/// 1-based indices picked by a reply like `1 3 5`, `1-4` or `all`
+///
+/// Numbers and ranges can be separated by spaces or commas. Every index is
+/// checked against `max` before any is returned, and the result is sorted
+/// without duplicates.
+pub fn parse_index_selection(text: &str, max: usize) -> Result<Vec<usize>, String> {
+    if text.trim().eq_ignore_ascii_case("all") {
+        return Ok((1..=max).collect());
+    }
+
+    let mut indices = Vec::new();
+
+    for part in text.split(|c: char| c.is_whitespace() || c == ',').filter(|p| !p.is_empty()) {
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (parse_index(start)?, parse_index(end)?),
+            None => {
+                let index = parse_index(part)?;
+                (index, index)
+            }
+        };
+
+        if start > end {
+            return Err(format!("Invalid range: {}", part));
+        }
+        if start == 0 || end > max {
+            return Err(format!("Index {} out of range (1-{})", part, max));
+        }
+
+        indices.extend(start..=end);
+    }
+
+    if indices.is_empty() {
+        return Err("Reply with a number, several (1 3 5), a range (1-4) or all".to_string());
+    }
+
+    indices.sort_unstable();
+    indices.dedup();
+
+    Ok(indices)
+}
+
+/// Same range check for replies that take a single index
+pub fn check_index(index: usize, max: usize) -> Result<(), String> {
+    if index == 0 || index > max {
+        return Err(format!("Index {} out of range (1-{})", index, max));
+    }
+
+    Ok(())
+}
+
+fn parse_index(value: &str) -> Result<usize, String> {
+    value
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid number: {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_numbers() {
+        assert_eq!(parse_index_selection("2", 5).unwrap(), vec![2]);
+        assert_eq!(parse_index_selection(" 5 ", 5).unwrap(), vec![5]);
+    }
+
+    #[test]
+    fn several_numbers_with_spaces_or_commas() {
+        assert_eq!(parse_index_selection("1 3 5", 5).unwrap(), vec![1, 3, 5]);
+        assert_eq!(parse_index_selection("1,3, 5", 5).unwrap(), vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn ranges_are_inclusive() {
+        assert_eq!(parse_index_selection("1-4", 5).unwrap(), vec![1, 2, 3, 4]);
+        assert_eq!(parse_index_selection("2-2", 5).unwrap(), vec![2]);
+        assert_eq!(parse_index_selection("1-2 4-5", 5).unwrap(), vec![1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn results_are_sorted_and_deduplicated() {
+        assert_eq!(parse_index_selection("3 1 2-3 1", 5).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn all_selects_every_index() {
+        assert_eq!(parse_index_selection("all", 3).unwrap(), vec![1, 2, 3]);
+        assert_eq!(parse_index_selection("ALL", 2).unwrap(), vec![1, 2]);
+        assert!(parse_index_selection("all", 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn out_of_range_fails_the_whole_selection() {
+        assert_eq!(
+            parse_index_selection("1 6", 5).unwrap_err(),
+            "Index 6 out of range (1-5)"
+        );
+        assert_eq!(
+            parse_index_selection("0", 5).unwrap_err(),
+            "Index 0 out of range (1-5)"
+        );
+        assert!(parse_index_selection("4-6", 5).is_err());
+    }
+
+    #[test]
+    fn single_index_check_matches_selection_errors() {
+        assert!(check_index(1, 5).is_ok());
+        assert_eq!(check_index(6, 5).unwrap_err(), parse_index_selection("6", 5).unwrap_err());
+        assert_eq!(check_index(0, 5).unwrap_err(), "Index 0 out of range (1-5)");
+    }
+
+    #[test]
+    fn garbage_and_backwards_ranges_are_rejected() {
+        assert_eq!(parse_index_selection("one", 5).unwrap_err(), "Invalid number: one");
+        assert_eq!(parse_index_selection("1-x", 5).unwrap_err(), "Invalid number: x");
+        assert_eq!(parse_index_selection("4-2", 5).unwrap_err(), "Invalid range: 4-2");
+        assert!(parse_index_selection("-3", 5).is_err());
+        assert!(parse_index_selection("", 5).is_err());
+    }
+}
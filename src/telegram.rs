@@ -1,49 +1,479 @@
 use std::env;
 
+use futures::future::{abortable, AbortHandle};
 use futures::lock::Mutex;
 use telegram_bot::prelude::*;
-use telegram_bot::{Api, ChatId, Message, MessageId, ParseMode};
+use telegram_bot::{
+    Api, CallbackQuery, ChatId, Document, InlineKeyboardButton, InlineKeyboardMarkup, Message, MessageId,
+    MessageKind, MessageOrChannelPost, ParseMode, UserId,
+};
 
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
-use crate::imdb::get_imdb_info;
+use crate::imdb::{get_imdb_info, imdb_id};
 use crate::jackett::{
-    dispatch_from_reply, format_telegram_response, request_jackett, TelegramJackettResponse,
+    classify_results, dispatch_from_reply, format_telegram_response, get_torrent_location_from_url,
+    response_position, result_size_and_media, search_format, SearchFormat, TelegramJackettResponse,
     TorrentLocation,
 };
+use crate::search::{search_backend, SearchFilters, SearchProvider, RESOLUTIONS};
+use crate::selection::{check_index, parse_index_selection};
 use crate::transmission::{
-    add_torrent, delete_torrent, get_media_type_from_path, get_storage_info, get_torrents,
-    stop_seeding_all, Media, Torrent,
+    add_torrent_for_inspect, get_media_type_from_path, get_storage_info, media_path,
+    get_torrent_details, get_torrent_files_detailed, readd_torrent, reannounce_torrent, set_default_download_dir, set_seed_ratio, space_report, set_peer_limit, start_inspected, start_torrent, stop_torrent, verify_all, hold_for_inspect, Media, Torrent, TorrentDetails, TorrentFiles,
 };
+use crate::torrent_client::{require_transmission, torrent_backend, TorrentClient};
 
 const HELP: &str = "
 /torrent-tv (Magnet Link)
 /torrent-movie (Magnet Link)
+Add --check to only preview the name and size without adding
 /search (Movie or TV Show e.g. The Matrix or Simpsons s01e01)
-/imdb (Imdb link). Requires omdb token set https://www.omdbapi.com/
-/status - Get status of active downloads
-/delete-torrent - List all downloads (reply with number to delete torrent)
-/delete-tv - List TV shows files (reply with number to delete file)
-/delete-movie - List movie files (reply with number to delete file)
-/restructure <tv|movie> - Scan and reorganize media files
+Add --cat (category id) to only search one indexer category, e.g. --cat 2045 for UHD movies
+Add seeders>=(number) to hide results with fewer seeders, e.g. seeders>=5
+Add 480p, 720p, 1080p or 2160p to only keep titles with that resolution, and -cam to hide cam rips. They are left out of the search text
+/search-tv and /search-movie (query) - Same, only in the TV or movie categories, picks need no tv/movie
+/imdb (Imdb link). Searches by IMDb id, falling back to the title (requires omdb token set https://www.omdbapi.com/)
+/status - Get status of active downloads, 15 at a time (send next for more)
+Each /status also shows what downloaded since the previous one in the chat
+/status grouped - Status split into TV, movie and unknown sections
+/delete-torrent - List all downloads (press one or reply with numbers or a range like 1-4 to delete torrents)
+/torrent-files - List all downloads (reply with number to see its files)
+/details - List all downloads (reply with number to see its files, trackers and errors)
+/where - List all downloads (reply with number, or send /where N, to see the path of its data)
+/peers - List all downloads (reply with number and limit, e.g. 2 50, to cap its peers)
+/set-ratio - List all downloads (reply with number and ratio, e.g. 2 1.5, to set its seed ratio limit). Also works as /set-ratio 2 1.5 in reply to any torrent list
+/retry-failed - List torrents that failed to start (reply with number to re-add)
+/stalled - List downloads receiving nothing, flagging those without peers (reply with number to re-add)
+/reannounce - List all downloads (reply with number to ask its trackers for peers now)
+/ratio-report - Overall ratio and torrents by ratio, marking those past their limit (reply with number to stop)
+/delete-tv - List TV shows files (reply with numbers or a range like 1-4 to delete files)
+/delete-movie - List movie files (reply with numbers or a range to delete files)
+/delete-anime - List anime files (reply with numbers or a range to delete files)
+File lists show 15 files at a time, reply next or prev to the list to change page
+Lists and plans take replies for 10 minutes (PENDING_LIST_TTL_SECS), send the command again after that
+Files still used by a torrent are marked ⚠️, add force to the reply to delete them anyway
+Reply all confirm to delete every file in the list
+/restructure <tv|movie|anime> [directory] [--since 7d] [--cleanup] [--cleanup-junk] - Scan and reorganize media files, --cleanup removes emptied source folders, --cleanup-junk deletes RESTRUCTURE_CLEANUP_EXTENSIONS files left next to the moved ones
+/restructure auto <directory> - Same for a mixed folder, each file goes to TV or movies on its own
+/unparseable <tv|movie|anime> - Count the files /restructure can't parse and their size
+/harvest <tv|movie|anime> - Hardlink completed downloads into the library, keeping them seeding
+/preview-name <tv|movie|anime> <filename> - Show where /restructure would move a file with that name
+/abort - Cancel the torrent add still in progress in this chat
+/cancel - Reply to a search, list or plan to dismiss it
+Send a .txt file with one magnet link per line to add them all, caption it tv, movie or anime or start a line with one
 /stop-seed - Stop seeding for all downloads
+/verify-all - Recheck the data of every torrent, asks for /verify-all confirm first (admins)
+/stop - List running torrents (reply with number to stop one, keeping it)
+/start - List stopped torrents (reply with number to start one)
 /storage - Get available storage information
+/set-default-dir <path> - Set Transmission's own default download folder (admins)
+/notify <on|off> - Turn download completion messages for this chat on or off
+/watch (search) - Save a search for this chat, filters included
+/watches - List the saved searches with when they last ran and their top result
+/watch-now (number) - Run a saved search now (reply to pick a result as with /search)
+/unwatch (number) - Remove a saved search
+/watch-pause and /watch-resume - Pause or resume this chat's watches (WATCH_INTERVAL runs) without removing them
+/search-format <compact|detailed> - One line per search result, or size, seeders, peers, indexer and age on their own lines
+/version - Show the bot version and build info
+/errors - Show the last errors in this chat
+/pending - Show the lists, plans and searches waiting for a reply, with their message ids and ages
+/s and /st are short for /search and /status
+Deleting, restructuring, /stop-seed, /verify-all, /set-default-dir and /pending, and replies that stop, re-add or answer an inspect, are limited to TELEGRAM_ADMIN_USERS, everyone may run them while it's unset
 
 Reply the magnet links with:
-Position of the torrent
+Position of the torrent (or press its button)
 If jackett doesn't provide a category, it's possible to force with:
 tv (position)
 movie (position)
+anime (position)
+Unanswered picks are added as DEFAULT_MEDIA_CATEGORY or dropped after PICK_CATEGORY_TIMEOUT
+See the category every result would be added with by replying:
+classify
+Check the free space for a result before picking it with:
+space (position)
+Add a result paused and see its files first (reply yes to start it, no to remove it) with:
+inspect (position) or inspect tv (position) / inspect movie (position)
 ";
 
+/// Shared state handed to every spawned message handler
+#[derive(Clone)]
+pub struct BotState {
+    pub responses: Arc<Mutex<Vec<TelegramJackettResponse>>>,
+    pub torrent_lists: Arc<Mutex<Vec<(Vec<i64>, TorrentListAction, String, MessageId, Instant)>>>,
+    // (all paths, page shown, text, message) so numbers stay absolute across pages
+    pub file_lists: Arc<Mutex<Vec<(Vec<String>, usize, String, MessageId, Instant)>>>,
+    pub restructure_plans: Arc<Mutex<Vec<(crate::restructure::RestructurePlan, String, MessageId, Instant)>>>,
+    // (chat, add id, handle) for torrent adds that haven't finished yet
+    pub in_flight_adds: Arc<Mutex<Vec<(ChatId, u64, AbortHandle)>>>,
+    // Picks with an uncertain category waiting for the user to confirm it
+    pub confirmations: Arc<Mutex<Vec<(TorrentLocation, Media, MessageId)>>>,
+    // (chat, torrent, requester, when) of picks without a category, added as
+    // DEFAULT_MEDIA_CATEGORY or dropped by the sweeper after PICK_CATEGORY_TIMEOUT
+    pub uncategorized_picks: Arc<Mutex<Vec<(ChatId, TorrentLocation, String, Instant)>>>,
+    // Where "next" continues the /status list of each chat
+    pub status_pages: Arc<Mutex<Vec<(ChatId, usize)>>>,
+    // (chat, last /status, the /status before it) so pages show what
+    // downloaded in between
+    pub status_snapshots: Arc<Mutex<Vec<(ChatId, StatusSnapshot, Option<StatusSnapshot>)>>>,
+    // Applies over RESTRUCTURE_CONFIRM_THRESHOLD waiting for "confirm":
+    // (operations, scan path, cleanup, message)
+    pub pending_moves: Arc<Mutex<Vec<(Vec<crate::restructure::MoveOperation>, String, crate::restructure::Cleanup, MessageId)>>>,
+    // (chat, when, command, error) of the last failed commands, for /errors
+    pub chat_errors: Arc<Mutex<Vec<(ChatId, Instant, String, String)>>>,
+}
+
+impl BotState {
+    pub fn new() -> Self {
+        BotState {
+            responses: Arc::new(Mutex::new(Vec::new())),
+            torrent_lists: Arc::new(Mutex::new(Vec::new())),
+            file_lists: Arc::new(Mutex::new(Vec::new())),
+            restructure_plans: Arc::new(Mutex::new(Vec::new())),
+            in_flight_adds: Arc::new(Mutex::new(Vec::new())),
+            confirmations: Arc::new(Mutex::new(Vec::new())),
+            uncategorized_picks: Arc::new(Mutex::new(Vec::new())),
+            status_pages: Arc::new(Mutex::new(Vec::new())),
+            status_snapshots: Arc::new(Mutex::new(Vec::new())),
+            pending_moves: Arc::new(Mutex::new(Vec::new())),
+            chat_errors: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+impl Default for BotState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a numbered reply to a torrent list does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TorrentListAction {
+    Delete,
+    Files,
+    Details,
+    Where,
+    Retry,
+    Stop,
+    Start,
+    Peers,
+    Ratio,
+    Reannounce,
+    /// A single torrent added paused by `inspect`
+    Inspect,
+}
+
+impl TorrentListAction {
+    /// Replies that go straight to Transmission instead of the `TorrentClient`
+    fn transmission_only(&self) -> bool {
+        !matches!(self, TorrentListAction::Delete | TorrentListAction::Where)
+    }
+
+    /// Replies that remove a torrent, even if only to add it again, or stop it
+    fn admin_only(&self) -> bool {
+        matches!(
+            self,
+            TorrentListAction::Delete | TorrentListAction::Retry | TorrentListAction::Inspect | TorrentListAction::Stop
+        )
+    }
+
+    fn prompt(&self) -> &'static str {
+        match self {
+            TorrentListAction::Delete => "Reply with the numbers to delete, e.g. 2, 1 3 5 or 1-4 (torrent):",
+            TorrentListAction::Files => "Reply with the number to list its files:",
+            TorrentListAction::Details => "Reply with the number to see its files and trackers:",
+            TorrentListAction::Where => "Reply with the number to see where its data is:",
+            TorrentListAction::Retry => "Reply with the number to remove and re-add (torrent):",
+            TorrentListAction::Stop => "Reply with the number to stop (torrent):",
+            TorrentListAction::Start => "Reply with the number to start (torrent):",
+            TorrentListAction::Peers => "Reply with the number and a peer limit, e.g. 2 50 (torrent):",
+            TorrentListAction::Ratio => "Reply with the number and a seed ratio, e.g. 2 1.5 (torrent):",
+            TorrentListAction::Reannounce => "Reply with the number to reannounce to its trackers (torrent):",
+            TorrentListAction::Inspect => "Reply 'yes' to start it or 'no' to remove it",
+        }
+    }
+}
+
+static NEXT_ADD_ID: AtomicU64 = AtomicU64::new(0);
+
 fn allowed_groups() -> Vec<ChatId> {
-    return match env::var("TELEGRAM_ALLOWED_GROUPS") {
+    match env::var("TELEGRAM_ALLOWED_GROUPS") {
+        Ok(val) => parse_chat_ids(&val),
+        Err(_) => Vec::new(),
+    }
+}
+
+// Invalid entries (typos, a trailing comma) are skipped with a warning
+// instead of taking the bot down
+fn parse_chat_ids(value: &str) -> Vec<ChatId> {
+    value
+        .split(',')
+        .filter_map(|x| match x.trim().parse::<i64>() {
+            Ok(id) => Some(ChatId::new(id)),
+            Err(_) => {
+                println!("Ignoring invalid TELEGRAM_ALLOWED_GROUPS entry: {:?}", x);
+                None
+            }
+        })
+        .collect()
+}
+
+// What an open bot (no TELEGRAM_ALLOWED_GROUPS) still answers, nothing
+// that adds, stops or deletes
+const READ_ONLY_COMMANDS: &[&str] = &[
+    "/chat-id", "/help", "/search", "/search-tv", "/search-movie", "/status", "next", "/storage", "/version",
+    "/watches",
+];
+
+fn is_read_only(message: &Message, command: &str) -> bool {
+    // Replies pick search results, delete list entries and apply plans
+    message.reply_to_message.is_none() && READ_ONLY_COMMANDS.contains(&command)
+}
+
+fn admin_users() -> Vec<UserId> {
+    match env::var("TELEGRAM_ADMIN_USERS") {
         Ok(val) => val
             .split(',')
-            .map(|x| ChatId::new(x.parse::<i64>().unwrap()))
-            .collect::<Vec<ChatId>>(),
+            .filter_map(|x| x.trim().parse::<i64>().ok())
+            .map(UserId::new)
+            .collect(),
         Err(_) => Vec::new(),
+    }
+}
+
+/// Whether the sender may run admin commands, everyone when TELEGRAM_ADMIN_USERS is unset
+fn is_admin(message: &Message) -> bool {
+    let admins = admin_users();
+    admins.is_empty() || admins.contains(&message.from.id)
+}
+
+const ADMINS_ONLY: &str = "⛔ Admins only";
+
+/// Log at startup when the admin commands are open to every member of an
+/// allowed chat
+pub fn warn_without_admins() {
+    if admin_users().is_empty() {
+        println!(
+            "Warning: TELEGRAM_ADMIN_USERS is not set, anyone in TELEGRAM_ALLOWED_GROUPS can delete, restructure and run {}",
+            ADMIN_COMMANDS.join(", ")
+        );
+    }
+}
+
+// Commands that delete, move or change everything at once. Replies that
+// delete or move files, or remove, re-add or stop a torrent, are checked
+// too, whoever asked for the list
+const ADMIN_COMMANDS: &[&str] = &[
+    "/delete-torrent",
+    "/delete-tv",
+    "/delete-movie",
+    "/delete-anime",
+    "/restructure",
+    "/stop-seed",
+    "/verify-all",
+    "/set-default-dir",
+    "/pending",
+];
+
+pub fn version_info() -> String {
+    format!(
+        "🤖 telegram-bot-torrents {}\nCommit: {}\nBuilt: {}",
+        env!("CARGO_PKG_VERSION"),
+        env!("GIT_COMMIT"),
+        env!("BUILD_TIMESTAMP")
+    )
+}
+
+fn dispatch_notify(text: Vec<String>, chat_id: ChatId) -> Result<String, String> {
+    let chat: i64 = chat_id.into();
+
+    let muted = match text.get(1).map(|arg| arg.as_str()) {
+        Some("on") => false,
+        Some("off") => true,
+        _ => {
+            let muted = crate::prefs::prefs().muted_chats.contains(&chat);
+            return Ok(format!(
+                "🔔 Notifications are {} for this chat. Use /notify on or /notify off",
+                if muted { "off" } else { "on" }
+            ));
+        }
+    };
+
+    crate::prefs::update_prefs(|prefs| {
+        if muted {
+            prefs.muted_chats.insert(chat);
+        } else {
+            prefs.muted_chats.remove(&chat);
+        }
+    })?;
+
+    if muted {
+        Ok("🔕 Notifications turned off for this chat".to_string())
+    } else {
+        Ok("🔔 Notifications turned on for this chat".to_string())
+    }
+}
+
+fn dispatch_search_format(text: Vec<String>, chat_id: ChatId) -> Result<String, String> {
+    let chat: i64 = chat_id.into();
+
+    let format = match text.get(1) {
+        Some(arg) => SearchFormat::parse(arg).ok_or("Use /search-format compact or /search-format detailed")?,
+        None => {
+            return Ok(format!(
+                "🔎 Search results are {} in this chat. Use /search-format compact or /search-format detailed",
+                search_format(chat).name()
+            ));
+        }
+    };
+
+    crate::prefs::update_prefs(|prefs| {
+        prefs.search_formats.insert(chat, format.name().to_string());
+    })?;
+
+    Ok(format!("🔎 Search results will be {} in this chat", format.name()))
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+/// Save a /search query for this chat, filters included
+fn dispatch_watch(text: Vec<String>, chat_id: ChatId) -> Result<String, String> {
+    let query = text[1..].join(" ");
+    let (words, _) = split_search_filters(text)?;
+    if words.len() <= 1 {
+        return Err("Pass the search to save after the command (/watch Severance s02)".to_string());
+    }
+
+    let chat: i64 = chat_id.into();
+
+    let position = crate::prefs::update_prefs(|prefs| {
+        let watches = prefs.watches.entry(chat).or_default();
+        if let Some(index) = watches.iter().position(|watch| watch.query == query) {
+            return Err(format!("Already watching {} (#{})", query, index + 1));
+        }
+        watches.push(crate::prefs::Watch {
+            query: query.clone(),
+            ..Default::default()
+        });
+        Ok(watches.len())
+    })??;
+
+    Ok(format!("👀 Watching {} (#{}), run it with /watch-now {}", query, position, position))
+}
+
+fn dispatch_watches(chat_id: ChatId) -> Result<String, String> {
+    let chat: i64 = chat_id.into();
+    let mut prefs = crate::prefs::prefs();
+    let watches = prefs.watches.remove(&chat).unwrap_or_default();
+    if watches.is_empty() {
+        return Ok("No watches in this chat, save one with /watch (search)".to_string());
+    }
+
+    let now = unix_now();
+    let mut list = if prefs.paused_watches.contains(&chat) {
+        String::from("⏸️ Watches are paused, /watch-resume turns them back on (/watch-now still runs one):\n")
+    } else {
+        String::from("👀 Watches (run one with /watch-now N):\n")
     };
+    for (index, watch) in watches.iter().enumerate() {
+        let last_run = match watch.last_run {
+            Some(when) => format!("ran {} ago", format_age(Duration::from_secs(now.saturating_sub(when)))),
+            None => "never run".to_string(),
+        };
+        list.push_str(&format!("\n{}. {} - {}", index + 1, watch.query, last_run));
+        if let Some(ref top) = watch.last_top {
+            list.push_str(&format!("\n   Top: {}", top));
+        }
+    }
+
+    Ok(list)
+}
+
+/// Remove a saved watch and list the ones left
+fn dispatch_unwatch(text: Vec<String>, chat_id: ChatId) -> Result<String, String> {
+    let chat: i64 = chat_id.into();
+    let index = text
+        .get(1)
+        .and_then(|value| value.parse::<usize>().ok())
+        .ok_or("Pass the number of the watch (/unwatch 1), /watches lists them")?;
+
+    let removed = crate::prefs::update_prefs(|prefs| {
+        let watches = prefs.watches.get_mut(&chat).ok_or("No watches in this chat")?;
+        check_index(index, watches.len())?;
+        let removed = watches.remove(index - 1);
+        if watches.is_empty() {
+            prefs.watches.remove(&chat);
+        }
+        Ok::<_, String>(removed)
+    })??;
+
+    Ok(format!("🗑️ Stopped watching {}\n\n{}", removed.query, dispatch_watches(chat_id)?))
+}
+
+/// Pause or resume the watches of this chat, keeping them saved
+fn dispatch_watch_pause(chat_id: ChatId, paused: bool) -> Result<String, String> {
+    let chat: i64 = chat_id.into();
+
+    crate::prefs::update_prefs(|prefs| {
+        if paused {
+            prefs.paused_watches.insert(chat);
+        } else {
+            prefs.paused_watches.remove(&chat);
+        }
+    })?;
+
+    let status = if paused { "⏸️ Watches paused" } else { "▶️ Watches resumed" };
+    Ok(format!("{}\n\n{}", status, dispatch_watches(chat_id)?))
+}
+
+/// Run a saved watch now, remembering when and its best result
+async fn dispatch_watch_now(text: Vec<String>, chat_id: ChatId) -> Result<TelegramJackettResponse, String> {
+    let chat: i64 = chat_id.into();
+    let index = text
+        .get(1)
+        .ok_or("Pass the number of the watch (/watch-now 1), /watches lists them")?
+        .parse::<usize>()
+        .map_err(|_| "Pass the number of the watch (/watch-now 1), /watches lists them".to_string())?;
+
+    let watches = crate::prefs::prefs().watches.remove(&chat).unwrap_or_default();
+    check_index(index, watches.len())?;
+
+    run_watch(chat, watches[index - 1].query.clone()).await
+}
+
+/// Search a saved watch of `chat`, remembering when it ran and its best result
+pub async fn run_watch(chat: i64, query: String) -> Result<TelegramJackettResponse, String> {
+    let mut search = vec!["/search".to_string()];
+    search.extend(query.split_whitespace().map(|word| word.to_string()));
+    let response = dispatch_search(search, None).await?;
+
+    // Match by query, the list may have changed while searching
+    crate::prefs::update_prefs(|prefs| {
+        let watches = prefs.watches.entry(chat).or_default();
+        if let Some(watch) = watches.iter_mut().find(|watch| watch.query == query) {
+            watch.last_run = Some(unix_now());
+            watch.last_top = response.top_title();
+        }
+    })?;
+
+    Ok(response)
+}
+
+/// @username of the sender, or the user id when they have none
+fn requester_name(message: &Message) -> String {
+    match message.from.username {
+        Some(ref username) => format!("@{}", username),
+        None => message.from.id.to_string(),
+    }
 }
 
 async fn dispatch_chat_id(message: Message) -> Result<String, String> {
@@ -53,182 +483,1439 @@ async fn dispatch_chat_id(message: Message) -> Result<String, String> {
     Ok(reply)
 }
 
-async fn dispatch_tv(text: Vec<String>) -> Result<String, String> {
+/// Run `task` so /abort in the chat can cancel it, None when it was
+async fn run_abortable<T>(
+    chat_id: ChatId,
+    in_flight_adds: &Arc<Mutex<Vec<(ChatId, u64, AbortHandle)>>>,
+    task: impl std::future::Future<Output = T>,
+) -> Option<T> {
+    let add_id = NEXT_ADD_ID.fetch_add(1, Ordering::Relaxed);
+    let (task, handle) = abortable(task);
+    in_flight_adds.lock().await.push((chat_id, add_id, handle));
+
+    let result = task.await;
+    in_flight_adds.lock().await.retain(|(_, id, _)| *id != add_id);
+
+    result.ok()
+}
+
+/// Run `add_torrent` so that `/abort` from the same chat can cancel it
+///
+/// Replies with the torrent name and size, when known, and the folder it went to.
+async fn add_torrent_abortable(
+    chat_id: ChatId,
+    in_flight_adds: &Arc<Mutex<Vec<(ChatId, u64, AbortHandle)>>>,
+    location: TorrentLocation,
+    media: Media,
+    requester: &str,
+) -> Result<String, String> {
+    let (name, size) = crate::torrentfile::location_metadata(&location);
+    let backend = torrent_backend()?;
+    let add = backend.add_torrent(location, media, requester.to_string());
+
+    let path = run_abortable(chat_id, in_flight_adds, add)
+        .await
+        .ok_or("Add cancelled")??;
+
+    let size = size
+        .map(|size| format!(" ({}B)", size_format::SizeFormatterSI::new(size)))
+        .unwrap_or_default();
+    Ok(match name {
+        Some(name) => format!("🧲 Added '{}'{} to {}", name, size, path),
+        None => format!("🧲 Added torrent{} to {}", size, path),
+    })
+}
+
+/// Forget the search, list or plan a /cancel replies to, so later number
+/// replies can't pick from it
+async fn dispatch_cancel(
+    message: &Message,
+    responses: &Arc<Mutex<Vec<TelegramJackettResponse>>>,
+    torrent_lists: &Arc<Mutex<Vec<(Vec<i64>, TorrentListAction, String, MessageId, Instant)>>>,
+    file_lists: &Arc<Mutex<Vec<(Vec<String>, usize, String, MessageId, Instant)>>>,
+    restructure_plans: &Arc<Mutex<Vec<(crate::restructure::RestructurePlan, String, MessageId, Instant)>>>,
+    pending_moves: &Arc<Mutex<Vec<(Vec<crate::restructure::MoveOperation>, String, crate::restructure::Cleanup, MessageId)>>>,
+    confirmations: &Arc<Mutex<Vec<(TorrentLocation, Media, MessageId)>>>,
+) -> Result<String, String> {
+    let reply = message
+        .reply_to_message
+        .as_deref()
+        .ok_or("Reply /cancel to the search, list or plan to dismiss")?;
+    let reply_id = match reply {
+        MessageOrChannelPost::Message(m) => m.id,
+        MessageOrChannelPost::ChannelPost(cp) => cp.id,
+    };
+
+    let cancelled = "✅ Cancelled".to_string();
+
+    let mut lists = torrent_lists.lock().await;
+    if let Some(position) = lists.iter().position(|(.., id, _)| *id == reply_id) {
+        lists.remove(position);
+        return Ok(cancelled);
+    }
+    drop(lists);
+
+    let mut lists = file_lists.lock().await;
+    if let Some(position) = lists.iter().position(|(.., id, _)| *id == reply_id) {
+        lists.remove(position);
+        return Ok(cancelled);
+    }
+    drop(lists);
+
+    let mut plans = restructure_plans.lock().await;
+    if let Some(position) = plans.iter().position(|(.., id, _)| *id == reply_id) {
+        plans.remove(position);
+        return Ok(cancelled);
+    }
+    drop(plans);
+
+    let mut moves = pending_moves.lock().await;
+    if let Some(position) = moves.iter().position(|(.., id)| *id == reply_id) {
+        moves.remove(position);
+        return Ok(cancelled);
+    }
+    drop(moves);
+
+    let mut pending = confirmations.lock().await;
+    if let Some(position) = pending.iter().position(|(.., id)| *id == reply_id) {
+        pending.remove(position);
+        return Ok(cancelled);
+    }
+    drop(pending);
+
+    // Searches are only known by their text
+    if let Some(reply_text) = reply.text() {
+        let mut searches = responses.lock().await;
+        if let Some(position) = response_position(&reply_text, &searches) {
+            searches.remove(position);
+            return Ok(cancelled);
+        }
+    }
+
+    Err("That message isn't a search, list or plan waiting for a reply".to_string())
+}
+
+// Entries /pending shows per section, newest first
+const PENDING_SHOWN: usize = 3;
+
+/// One /pending section: its count, then the newest entries (stores keep the
+/// newest last)
+fn pending_section(title: &str, entries: Vec<String>) -> String {
+    let mut text = format!("{}: {}\n", title, entries.len());
+    for entry in entries.iter().rev().take(PENDING_SHOWN) {
+        text.push_str(&format!("• {}\n", entry));
+    }
+    if entries.len() > PENDING_SHOWN {
+        text.push_str(&format!("…and {} more\n", entries.len() - PENDING_SHOWN));
+    }
+    text
+}
+
+/// Lists, plans and searches waiting for a reply, across all chats, by
+/// message id and age. Only counts and the newest few are shown, never file
+/// paths
+async fn dispatch_pending(
+    responses: &Arc<Mutex<Vec<TelegramJackettResponse>>>,
+    torrent_lists: &Arc<Mutex<Vec<(Vec<i64>, TorrentListAction, String, MessageId, Instant)>>>,
+    file_lists: &Arc<Mutex<Vec<(Vec<String>, usize, String, MessageId, Instant)>>>,
+    restructure_plans: &Arc<Mutex<Vec<(crate::restructure::RestructurePlan, String, MessageId, Instant)>>>,
+    pending_moves: &Arc<Mutex<Vec<(Vec<crate::restructure::MoveOperation>, String, crate::restructure::Cleanup, MessageId)>>>,
+    confirmations: &Arc<Mutex<Vec<(TorrentLocation, Media, MessageId)>>>,
+) -> Result<String, String> {
+    let ttl = pending_list_ttl();
+    let age = |sent: &Instant| {
+        let expired = if sent.elapsed() > ttl { ", expired" } else { "" };
+        format!("{} old{}", format_age(sent.elapsed()), expired)
+    };
+
+    let entries = torrent_lists
+        .lock()
+        .await
+        .iter()
+        .map(|(ids, action, _, id, sent)| {
+            let action = format!("{:?}", action).to_lowercase();
+            format!("#{} {}, {} torrents, {}", id, action, ids.len(), age(sent))
+        })
+        .collect();
+    let mut text = pending_section("🗂️ Torrent lists", entries);
+
+    let entries = file_lists
+        .lock()
+        .await
+        .iter()
+        .map(|(paths, page, _, id, sent)| format!("#{} {} files, page {}, {}", id, paths.len(), page + 1, age(sent)))
+        .collect();
+    text.push_str(&pending_section("📂 File lists", entries));
+
+    let entries = restructure_plans
+        .lock()
+        .await
+        .iter()
+        .map(|(plan, _, id, sent)| format!("#{} {} moves, {}", id, plan.operations.len(), age(sent)))
+        .collect();
+    text.push_str(&pending_section("🔄 Restructure plans", entries));
+
+    let entries = pending_moves
+        .lock()
+        .await
+        .iter()
+        .map(|(operations, .., id)| format!("#{} {} moves", id, operations.len()))
+        .collect();
+    text.push_str(&pending_section("⚠️ Applies waiting for confirm", entries));
+
+    let entries = confirmations
+        .lock()
+        .await
+        .iter()
+        .map(|(_, media, id)| format!("#{} {}", id, media.label()))
+        .collect();
+    text.push_str(&pending_section("❓ Category confirmations", entries));
+
+    // Searches are matched by their text, they have no message id
+    text.push_str(&format!("🔍 Search responses: {}", responses.lock().await.len()));
+
+    Ok(text)
+}
+
+async fn dispatch_abort(
+    chat_id: ChatId,
+    in_flight_adds: &Arc<Mutex<Vec<(ChatId, u64, AbortHandle)>>>,
+) -> Result<String, String> {
+    let mut adds = in_flight_adds.lock().await;
+    let position = adds.iter().rposition(|(chat, _, _)| *chat == chat_id);
+
+    match position {
+        Some(position) => {
+            let (_, _, handle) = adds.remove(position);
+            handle.abort();
+            // The cancelled add replies on its own message
+            Ok(String::new())
+        }
+        None => Err("No torrent add in progress".to_string()),
+    }
+}
+
+/// Split the `--check` flag out of a /torrent-* command
+fn take_check_flag(text: Vec<String>) -> (Vec<String>, bool) {
+    let check = text.iter().any(|t| t == "--check");
+    (text.into_iter().filter(|t| t != "--check").collect(), check)
+}
+
+/// Resolve a magnet or .torrent URL and report what it contains without adding it
+async fn dispatch_check(content: String) -> Result<String, String> {
+    use size_format::SizeFormatterSI;
+
+    let location = if content.starts_with("magnet:") {
+        TorrentLocation {
+            is_magnet: true,
+            content,
+        }
+    } else if content.starts_with("http://") || content.starts_with("https://") {
+        get_torrent_location_from_url(content).await?
+    } else {
+        return Err("Send a magnet link or a .torrent URL to check".to_string());
+    };
+
+    let (name, size) = if location.is_magnet {
+        crate::torrentfile::parse_magnet_metadata(&location.content)?
+    } else {
+        let bytes = base64::decode(&location.content)
+            .map_err(|e| format!("Failed to decode torrent file: {}", e))?;
+        let (name, size) = crate::torrentfile::parse_torrent_metadata(&bytes)?;
+        (Some(name), Some(size))
+    };
+
+    Ok(format!(
+        "🔎 Torrent check (not added)\nName: {}\nSize: {}",
+        name.unwrap_or_else(|| "unknown".to_string()),
+        size.map(|size| format!("{}B", SizeFormatterSI::new(size)))
+            .unwrap_or_else(|| "unknown".to_string())
+    ))
+}
+
+async fn dispatch_tv(
+    text: Vec<String>,
+    chat_id: ChatId,
+    in_flight_adds: &Arc<Mutex<Vec<(ChatId, u64, AbortHandle)>>>,
+    requester: &str,
+) -> Result<String, String> {
+    let (text, check) = take_check_flag(text);
     if text.len() <= 1 {
         return Err("Send the magnet-url after command (/torrent-tv magnet_url)".to_string());
     }
 
+    if check {
+        return dispatch_check(text[1].clone()).await;
+    }
+
     let location = TorrentLocation {
         is_magnet: true,
         content: text[1].clone(),
     };
-    add_torrent(location, Media::TV).await?;
-
-    Ok("🧲 Added torrent".to_string())
+    add_torrent_abortable(chat_id, in_flight_adds, location, Media::TV, requester).await
 }
 
-async fn dispatch_movie(text: Vec<String>) -> Result<String, String> {
+async fn dispatch_movie(
+    text: Vec<String>,
+    chat_id: ChatId,
+    in_flight_adds: &Arc<Mutex<Vec<(ChatId, u64, AbortHandle)>>>,
+    requester: &str,
+) -> Result<String, String> {
+    let (text, check) = take_check_flag(text);
     if text.len() <= 1 {
         return Err("Send the magnet-url after command (/torrent-movie magnet_url)".to_string());
     }
 
+    if check {
+        return dispatch_check(text[1].clone()).await;
+    }
+
     let location = TorrentLocation {
         is_magnet: true,
         content: text[1].clone(),
     };
-    add_torrent(location, Media::Movie).await?;
-
-    Ok("🧲 Added torrent".to_string())
+    add_torrent_abortable(chat_id, in_flight_adds, location, Media::Movie, requester).await
 }
 
 async fn dispatch_from_imdb_url(imdb_url: String) -> Result<TelegramJackettResponse, String> {
+    let backend = search_backend()?;
+
+    // Try the IMDb id first, indexers without IMDb support need the title search
+    if let Ok(id) = imdb_id(imdb_url.clone()) {
+        if let Ok(result) = backend.search_imdb(id).await {
+            return Ok(result);
+        }
+    }
+
     let title = get_imdb_info(imdb_url.clone()).await?;
-    let result = request_jackett(title).await?;
+    let result = backend.search(title, SearchFilters::default()).await?;
 
     Ok(result)
 }
 
-async fn dispatch_search(text: Vec<String>) -> Result<TelegramJackettResponse, String> {
+/// Split `--cat <id>`, `seeders>=<n>`, resolutions like `1080p` and `-cam`
+/// out of a /search command
+fn split_search_filters(text: Vec<String>) -> Result<(Vec<String>, SearchFilters), String> {
+    let mut filters = SearchFilters::default();
+    let mut rest = Vec::new();
+    let mut words = text.into_iter();
+
+    while let Some(word) = words.next() {
+        if word == "--cat" {
+            let value = words.next().ok_or("Pass a category id after --cat (e.g. --cat 2045)")?;
+            let category = value
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid category: {}, use a numeric Torznab id like 2045", value))?;
+            filters.category = Some(category);
+        } else if let Some(value) = word.strip_prefix("seeders>=") {
+            let min = value
+                .parse::<i64>()
+                .map_err(|_| format!("Invalid seeders: {}, use e.g. seeders>=5", value))?;
+            filters.min_seeders = Some(min);
+        } else if let Some(resolution) = RESOLUTIONS.iter().find(|r| r.eq_ignore_ascii_case(&word)) {
+            filters.resolution = Some(*resolution);
+        } else if word.eq_ignore_ascii_case("-cam") {
+            filters.exclude_cam = true;
+        } else {
+            rest.push(word);
+        }
+    }
+
+    Ok((rest, filters))
+}
+
+/// /search, or /search-tv and /search-movie with `media` set to keep to its categories
+async fn dispatch_search(text: Vec<String>, media: Option<Media>) -> Result<TelegramJackettResponse, String> {
+    let (text, mut filters) = split_search_filters(text)?;
     if text.len() <= 1 {
-        return Err("Pass the movie/TV after command (/search Matrix 1999)".to_string());
+        return Err(format!("Pass the movie/TV after command ({} Matrix 1999)", text[0]));
     }
+    filters.media = media;
 
     let search_text = text[1..].join(" ");
-    let result = request_jackett(search_text).await?;
+    let result = search_backend()?.search(search_text, filters).await?;
 
     Ok(result)
 }
 
+/// Free space where a search result would be downloaded, against its size
+fn dispatch_space(index: u16, reply_text: String, torrents: Vec<TelegramJackettResponse>) -> Result<String, String> {
+    let (size, media) = result_size_and_media(index, reply_text, torrents)?;
+
+    let reports = media
+        .iter()
+        .map(|media| space_report(media, size))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(reports.join("\n"))
+}
+
+const INSPECT_METADATA_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Add a search result without downloading it and list its files once the
+/// metadata is in, /abort cancels the add and the wait
+async fn dispatch_inspect(
+    index: u16,
+    reply_text: String,
+    torrents: Vec<TelegramJackettResponse>,
+    media: Option<Media>,
+    chat_id: ChatId,
+    in_flight_adds: &Arc<Mutex<Vec<(ChatId, u64, AbortHandle)>>>,
+    requester: &str,
+) -> Result<(String, Option<PendingList>), String> {
+    require_transmission()?;
+
+    let (torrent_media, location, ambiguous) = dispatch_from_reply(index, reply_text, torrents).await?;
+    // Nothing is asked later, an uncertain category has to be given up front
+    let media = match (media, torrent_media) {
+        (Some(media), _) => media,
+        (None, Some(media)) if !ambiguous => media,
+        (None, _) => {
+            let reason = if ambiguous {
+                "Torrent has both TV and movie categories"
+            } else {
+                "No category for given torrent"
+            };
+            return Err(format!("{}.\nReply with inspect tv (index) or inspect movie (index)", reason));
+        }
+    };
+
+    let add = add_torrent_for_inspect(location, media, requester.to_string());
+    let id = run_abortable(chat_id, in_flight_adds, add).await.ok_or("Add cancelled")??;
+
+    let files = match run_abortable(chat_id, in_flight_adds, hold_for_inspect(id, INSPECT_METADATA_TIMEOUT)).await {
+        Some(files) => files,
+        None => {
+            // A magnet may still be running, nobody asked for its data
+            torrent_backend()?.delete_torrent(vec![id]).await?;
+            return Err("Add cancelled".to_string());
+        }
+    };
+
+    let mut text = match files {
+        Ok(details) => format_torrent_files(&details),
+        Err(err) => format!("⏸️ Added paused, but {}\n", err),
+    };
+    text.push_str(&format!("\n{}", TorrentListAction::Inspect.prompt()));
+
+    Ok((text, Some(PendingList::Torrent(vec![id], TorrentListAction::Inspect))))
+}
+
+/// "yes" starts an inspected torrent, "no" removes it (it has no data yet)
+async fn dispatch_inspect_reply(answer: &str, torrent_ids: Vec<i64>) -> Result<String, String> {
+    let id = *torrent_ids.first().ok_or("Torrent not found, it may have been removed")?;
+
+    match answer.to_lowercase().as_str() {
+        "yes" | "ok" | "start" => {
+            let name = start_inspected(id).await?;
+            Ok(format!("▶️ Started {}", name))
+        }
+        "no" | "cancel" | "delete" => {
+            torrent_backend()?.delete_torrent(vec![id]).await?;
+            Ok("🗑️ Removed the inspected torrent".to_string())
+        }
+        _ => Err(TorrentListAction::Inspect.prompt().to_string()),
+    }
+}
+
 async fn pick_choices(
     index: u16,
     reply_text: String,
     torrents: Vec<TelegramJackettResponse>,
     mut media: Option<Media>,
-) -> Result<String, String> {
-    let (torrent_media, location) = dispatch_from_reply(index, reply_text, torrents).await?;
+    message: &Message,
+    in_flight_adds: &Arc<Mutex<Vec<(ChatId, u64, AbortHandle)>>>,
+    uncategorized_picks: &Arc<Mutex<Vec<(ChatId, TorrentLocation, String, Instant)>>>,
+) -> Result<(String, Option<PendingList>), String> {
+    let chat_id = message.chat.id();
+    let requester = requester_name(message);
+    let (torrent_media, location, ambiguous) = dispatch_from_reply(index, reply_text, torrents).await?;
 
     if media.is_none() && torrent_media.is_none() {
-        return Err(
-            "No category for given torrent.\nReply with tv (index) or movie (index) to force it"
-                .to_string(),
+        let reason = if ambiguous {
+            "Torrent has both TV and movie categories"
+        } else {
+            "No category for given torrent"
+        };
+        let timeout = format_age(crate::notifier::pick_timeout());
+        let fallback = match crate::notifier::default_media_category() {
+            Some(media) => format!("it's added as {} in {}", media.label(), timeout),
+            None => format!("the pick expires in {}", timeout),
+        };
+
+        let mut picks = uncategorized_picks.lock().await;
+        picks.retain(|(chat, pending, ..)| !(*chat == chat_id && pending.content == location.content));
+        picks.push((chat_id, location, requester, Instant::now()));
+        // Keep only last 100 picks to avoid memory issues
+        if picks.len() > 100 {
+            picks.remove(0);
+        }
+
+        return Err(format!(
+            "{}.\nReply with tv (index) or movie (index) to force it, otherwise {}",
+            reason, fallback
+        ));
+    }
+
+    // Answered, the sweeper has nothing left to do for this torrent
+    uncategorized_picks
+        .lock()
+        .await
+        .retain(|(chat, pending, ..)| !(*chat == chat_id && pending.content == location.content));
+
+    if media.is_none() && ambiguous {
+        let detected = torrent_media.unwrap();
+        let (label, other) = match detected {
+            Media::TV => ("TV", "movie"),
+            Media::Movie => ("Movie", "tv"),
+            Media::Anime => ("Anime", "tv"),
+        };
+        let text = format!(
+            "🤔 Detected as {} — reply '{}' to change, 'ok' to add it as {} or 'cancel'",
+            label, other, label
         );
+        return Ok((text, Some(PendingList::Confirmation(location, detected))));
     }
 
     if media.is_none() {
         media = torrent_media;
     }
 
-    add_torrent(location, media.unwrap()).await?;
+    let added = add_torrent_abortable(chat_id, in_flight_adds, location, media.unwrap(), &requester).await?;
 
-    Ok("🧲 Added torrent".to_string())
+    Ok((added, None))
 }
 
-async fn dispatch_status() -> Result<String, String> {
-    use size_format::SizeFormatterSI;
-    
-    let torrents = get_torrents().await?;
+// Transmission status codes with their config names, -1 stands for anything else
+const STATUS_NAMES: &[(i64, &str)] = &[
+    (0, "stopped"),
+    (1, "check_wait"),
+    (2, "checking"),
+    (3, "download_wait"),
+    (4, "downloading"),
+    (5, "seed_wait"),
+    (6, "seeding"),
+    (-1, "unknown"),
+];
+
+static STATUS_LABELS: OnceLock<HashMap<i64, String>> = OnceLock::new();
+
+fn default_status_labels() -> HashMap<i64, String> {
+    [
+        (0, "⏸️"),  // Stopped
+        (1, "⏳"),   // Queued to verify
+        (2, "🔍"),   // Verifying
+        (3, "⏳"),   // Queued to download
+        (4, "⬇️"),   // Downloading
+        (5, "⏳"),   // Queued to seed
+        (6, "⬆️"),   // Seeding
+        (-1, "❓"),
+    ]
+    .iter()
+    .map(|(status, label)| (*status, label.to_string()))
+    .collect()
+}
 
-    if torrents.is_empty() {
-        return Ok("📊 No active downloads".to_string());
-    }
+/// Overrides from STATUS_LABELS, either inline JSON or a path to a JSON file.
+/// Keys are status names (e.g. "downloading") or Transmission status codes.
+fn load_status_labels() -> Result<HashMap<i64, String>, String> {
+    let mut labels = default_status_labels();
 
-    let mut status = String::from("📊 Active Downloads:\n\n");
+    let config = match env::var("STATUS_LABELS") {
+        Ok(config) => config,
+        Err(_) => return Ok(labels),
+    };
 
-    for torrent in &torrents {
-        let percent = (torrent.percent_done * 100.0) as i64;
-        let status_emoji = match torrent.status {
-            0 => "⏸️",  // Stopped
-            1 => "⏳",   // Queued to verify
-            2 => "🔍",   // Verifying
-            3 => "⏳",   // Queued to download
-            4 => "⬇️",   // Downloading
-            5 => "⏳",   // Queued to seed
-            6 => "⬆️",   // Seeding
-            _ => "❓",
-        };
+    let json = if config.trim_start().starts_with('{') {
+        config
+    } else {
+        std::fs::read_to_string(&config)
+            .map_err(|e| format!("Failed to read STATUS_LABELS file {}: {}", config, e))?
+    };
 
-        let size_str = SizeFormatterSI::new(torrent.total_size as u64).to_string();
-        
-        status.push_str(&format!(
-            "{} {} ({}%)\n  Size: {}, Downloaded: {}, Uploaded: {}\n",
-            status_emoji,
-            torrent.name,
-            percent,
-            size_str,
-            SizeFormatterSI::new(torrent.downloaded_ever as u64).to_string(),
-            SizeFormatterSI::new(torrent.uploaded_ever as u64).to_string()
-        ));
+    let overrides: HashMap<String, String> = serde_json::from_str(&json)
+        .map_err(|e| format!("STATUS_LABELS is not a JSON object of strings: {}", e))?;
+
+    for (key, label) in overrides {
+        let status = STATUS_NAMES
+            .iter()
+            .find(|(_, name)| *name == key.to_lowercase())
+            .map(|(status, _)| *status)
+            .or_else(|| key.parse::<i64>().ok())
+            .ok_or_else(|| format!("Unknown status in STATUS_LABELS: {}", key))?;
+        labels.insert(status, label);
     }
 
-    Ok(status)
+    Ok(labels)
 }
 
-fn format_torrent_list(torrents: &[Torrent], filter: Option<Media>) -> (String, Vec<i64>) {
-    let mut list = String::new();
-    let mut ids = Vec::new();
+/// Status labels used by /status, loaded once and falling back to the defaults
+pub fn status_labels() -> &'static HashMap<i64, String> {
+    STATUS_LABELS.get_or_init(|| {
+        load_status_labels().unwrap_or_else(|e| {
+            println!("{}. Using the default status labels", e);
+            default_status_labels()
+        })
+    })
+}
 
-    let tv_path = env::var("TRANSMISSION_TV_PATH").unwrap_or_default();
-    let movie_path = env::var("TRANSMISSION_MOVIE_PATH").unwrap_or_default();
+const DEFAULT_COMMAND_ALIASES: &[(&str, &str)] = &[("/s", "/search"), ("/st", "/status")];
 
-    let mut number = 1;
-    for torrent in torrents {
-        let media_type = get_media_type_from_path(&torrent.download_dir, &tv_path, &movie_path);
+static COMMAND_ALIASES: OnceLock<HashMap<String, String>> = OnceLock::new();
 
-        if let Some(filter_media) = &filter {
-            if media_type.as_ref() != Some(filter_media) {
-                continue;
+/// Built-in aliases plus COMMAND_ALIASES ("s=search,dt=delete-torrent"),
+/// the leading slash is optional on both sides
+fn command_aliases() -> &'static HashMap<String, String> {
+    COMMAND_ALIASES.get_or_init(|| {
+        let command = |name: &str| format!("/{}", name.trim().trim_start_matches('/'));
+
+        let mut aliases: HashMap<String, String> = DEFAULT_COMMAND_ALIASES
+            .iter()
+            .map(|(alias, target)| (alias.to_string(), target.to_string()))
+            .collect();
+
+        for pair in env::var("COMMAND_ALIASES").unwrap_or_default().split(',') {
+            match pair.split_once('=') {
+                Some((alias, target)) if !alias.trim().is_empty() && !target.trim().is_empty() => {
+                    aliases.insert(command(alias), command(target));
+                }
+                _ if pair.trim().is_empty() => {}
+                _ => println!("Ignoring invalid COMMAND_ALIASES entry {}", pair),
             }
         }
 
-        let media_label = match media_type {
-            Some(Media::TV) => "📺 TV",
-            Some(Media::Movie) => "🎬 Movie",
-            None => "📁 Unknown",
-        };
+        aliases
+    })
+}
 
-        let percent = (torrent.percent_done * 100.0) as i64;
+/// Canonical command for the first word of a message, without the @botname
+/// Telegram appends in groups and with aliases resolved
+fn resolve_command(word: &str) -> String {
+    if !word.starts_with('/') {
+        return word.to_string();
+    }
 
-        list.push_str(&format!(
-            "{}. {} - {} ({}%)\n",
-            number, media_label, torrent.name, percent
-        ));
-        ids.push(torrent.id);
-        number += 1;
+    let command = word.split('@').next().unwrap_or(word);
+    command_aliases()
+        .get(command)
+        .cloned()
+        .unwrap_or_else(|| command.to_string())
+}
+
+fn status_label(status: i64) -> &'static str {
+    let labels = status_labels();
+    labels
+        .get(&status)
+        .or_else(|| labels.get(&-1))
+        .map(|label| label.as_str())
+        .unwrap_or("❓")
+}
+
+/// Seconds left as "2h 14m", "—" when unknown
+fn format_eta(seconds: i64) -> String {
+    if seconds < 0 {
+        return "—".to_string();
     }
 
-    if list.is_empty() {
-        list = "No downloads found".to_string();
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
     } else {
-        list.insert_str(0, "Reply with the number to delete (torrent):\n\n");
+        format!("{}s", seconds)
     }
-
-    (list, ids)
 }
 
-async fn dispatch_delete_list(filter: Option<Media>) -> Result<(String, Vec<i64>), String> {
-    let torrents = get_torrents().await?;
-    Ok(format_torrent_list(&torrents, filter))
+/// Downloaded bytes of every torrent at one /status
+#[derive(Clone)]
+pub struct StatusSnapshot {
+    taken: Instant,
+    downloaded: HashMap<i64, i64>,
+}
+
+impl StatusSnapshot {
+    fn new(torrents: &[Torrent]) -> Self {
+        StatusSnapshot {
+            taken: Instant::now(),
+            downloaded: torrents.iter().map(|t| (t.id, t.downloaded_ever)).collect(),
+        }
+    }
+
+    /// "+1.2 GB in 5m (4.0 MB/s avg)" for what the torrent downloaded since
+    fn delta(&self, torrent: &Torrent) -> Option<String> {
+        use size_format::SizeFormatterSI;
+
+        let before = *self.downloaded.get(&torrent.id)?;
+        let elapsed = self.taken.elapsed();
+        let delta = torrent.downloaded_ever - before;
+        // Nothing to average over, or the data was rechecked in between
+        if elapsed.as_secs() == 0 || delta < 0 {
+            return None;
+        }
+
+        Some(format!(
+            "+{}B in {} ({}B/s avg)",
+            SizeFormatterSI::new(delta as u64),
+            format_age(elapsed),
+            SizeFormatterSI::new(delta as u64 / elapsed.as_secs())
+        ))
+    }
+}
+
+fn format_status_entry(torrent: &Torrent, since: Option<&StatusSnapshot>) -> String {
+    use size_format::SizeFormatterSI;
+
+    let percent = (torrent.percent_done * 100.0) as i64;
+    let status_emoji = status_label(torrent.status);
+
+    let size_str = SizeFormatterSI::new(torrent.total_size as u64).to_string();
+
+    let mut entry = format!(
+        "{} {} ({}%)\n  Size: {}, Downloaded: {}, Uploaded: {}\n  ⬇️ {}B/s ⬆️ {}B/s\n",
+        status_emoji,
+        torrent.name,
+        percent,
+        size_str,
+        SizeFormatterSI::new(torrent.downloaded_ever as u64).to_string(),
+        SizeFormatterSI::new(torrent.uploaded_ever as u64).to_string(),
+        SizeFormatterSI::new(torrent.rate_download.max(0) as u64),
+        SizeFormatterSI::new(torrent.rate_upload.max(0) as u64)
+    );
+
+    let eta = if torrent.status == 4 { format_eta(torrent.eta) } else { "—".to_string() };
+    entry.push_str(&format!("  ETA: {}\n", eta));
+
+    if let Some(delta) = since.and_then(|snapshot| snapshot.delta(torrent)) {
+        entry.push_str(&format!("  Δ {}\n", delta));
+    }
+
+    // Peer info only matters while the torrent is downloading or seeding
+    if torrent.status == 4 || torrent.status == 6 {
+        entry.push_str(&format!(
+            "  👥 {} peers ({}⬇️ {}⬆️), {} trackers\n",
+            torrent.peers_connected,
+            torrent.peers_sending_to_us,
+            torrent.peers_getting_from_us,
+            torrent.trackers.len()
+        ));
+    }
+
+    // Speed trend sampled by the notifier, only meaningful while downloading
+    if torrent.status == 4 {
+        if let Some(spark) = crate::notifier::speed_sparkline(torrent.id) {
+            entry.push_str(&format!("  📈 {}\n", spark));
+        }
+    }
+
+    if let Some(requester) = torrent.added_by() {
+        entry.push_str(&format!("  👤 added by {}\n", requester));
+    }
+
+    entry
+}
+
+fn format_status_grouped(torrents: &[Torrent], since: Option<&StatusSnapshot>) -> String {
+    use size_format::SizeFormatterSI;
+
+    let groups = [
+        (Some(Media::TV), Media::TV.label()),
+        (Some(Media::Movie), Media::Movie.label()),
+        (Some(Media::Anime), Media::Anime.label()),
+        (None, "📁 Unknown"),
+    ];
+
+    let mut status = String::from("📊 Active Downloads:\n");
+
+    for (media, label) in groups.iter() {
+        let group: Vec<&Torrent> = torrents
+            .iter()
+            .filter(|t| &get_media_type_from_path(&t.download_dir) == media)
+            .collect();
+
+        if group.is_empty() {
+            continue;
+        }
+
+        let downloaded: i64 = group.iter().map(|t| t.downloaded_ever).sum();
+        let size: i64 = group.iter().map(|t| t.total_size).sum();
+
+        status.push_str(&format!(
+            "\n{} ({}) - {} of {}\n\n",
+            label,
+            group.len(),
+            SizeFormatterSI::new(downloaded as u64),
+            SizeFormatterSI::new(size as u64)
+        ));
+
+        for torrent in group {
+            status.push_str(&format_status_entry(torrent, since));
+        }
+    }
+
+    status
+}
+
+const STATUS_PAGE_SIZE: usize = 15;
+
+/// One /status page starting at `offset`, and the offset of the next page if any
+///
+/// With `since`, each entry also shows what it downloaded after that snapshot.
+fn format_status_page(torrents: &[Torrent], offset: usize, since: Option<&StatusSnapshot>) -> (String, Option<usize>) {
+    let end = std::cmp::min(offset + STATUS_PAGE_SIZE, torrents.len());
+    let mut status = String::from("📊 Active Downloads:\n\n");
+
+    for torrent in &torrents[offset..end] {
+        status.push_str(&format_status_entry(torrent, since));
+    }
+
+    if end < torrents.len() {
+        status.push_str(&format!(
+            "\nShowing {}-{} of {}. Send 'next' for more",
+            offset + 1,
+            end,
+            torrents.len()
+        ));
+        (status, Some(end))
+    } else {
+        (status, None)
+    }
+}
+
+async fn set_status_page(
+    status_pages: &Arc<Mutex<Vec<(ChatId, usize)>>>,
+    chat_id: ChatId,
+    next: Option<usize>,
+) {
+    let mut pages = status_pages.lock().await;
+    pages.retain(|(chat, _)| *chat != chat_id);
+    if let Some(next) = next {
+        pages.push((chat_id, next));
+    }
+    // Keep only last 100 cursors to avoid memory issues
+    if pages.len() > 100 {
+        pages.remove(0);
+    }
+}
+
+/// Remember this /status and hand back the previous one of the chat
+async fn take_status_snapshot(
+    status_snapshots: &Arc<Mutex<Vec<(ChatId, StatusSnapshot, Option<StatusSnapshot>)>>>,
+    chat_id: ChatId,
+    torrents: &[Torrent],
+) -> Option<StatusSnapshot> {
+    let mut snapshots = status_snapshots.lock().await;
+    let previous = snapshots
+        .iter()
+        .position(|(chat, ..)| *chat == chat_id)
+        .map(|position| snapshots.remove(position).1);
+
+    snapshots.push((chat_id, StatusSnapshot::new(torrents), previous.clone()));
+    // Keep only last 100 snapshots to avoid memory issues
+    if snapshots.len() > 100 {
+        snapshots.remove(0);
+    }
+
+    previous
+}
+
+async fn dispatch_status(
+    text: Vec<String>,
+    chat_id: ChatId,
+    status_pages: &Arc<Mutex<Vec<(ChatId, usize)>>>,
+    status_snapshots: &Arc<Mutex<Vec<(ChatId, StatusSnapshot, Option<StatusSnapshot>)>>>,
+) -> Result<String, String> {
+    let torrents = torrent_backend()?.get_torrents().await?;
+
+    if torrents.is_empty() {
+        return Ok("📊 No active downloads".to_string());
+    }
+
+    let previous = take_status_snapshot(status_snapshots, chat_id, &torrents).await;
+
+    if text.get(1).map(|mode| mode.as_str()) == Some("grouped") {
+        return Ok(format_status_grouped(&torrents, previous.as_ref()));
+    }
+
+    let (status, next) = format_status_page(&torrents, 0, previous.as_ref());
+    set_status_page(status_pages, chat_id, next).await;
+
+    Ok(status)
+}
+
+/// Next page of the last /status sent in this chat
+async fn dispatch_status_next(
+    chat_id: ChatId,
+    status_pages: &Arc<Mutex<Vec<(ChatId, usize)>>>,
+    status_snapshots: &Arc<Mutex<Vec<(ChatId, StatusSnapshot, Option<StatusSnapshot>)>>>,
+) -> Result<String, String> {
+    let offset = status_pages
+        .lock()
+        .await
+        .iter()
+        .find(|(chat, _)| *chat == chat_id)
+        .map(|(_, offset)| *offset)
+        .ok_or("Nothing more to show, send /status first")?;
+
+    let torrents = torrent_backend()?.get_torrents().await?;
+    // Torrents may have been removed since the previous page
+    if offset >= torrents.len() {
+        set_status_page(status_pages, chat_id, None).await;
+        return Ok("📊 No more downloads".to_string());
+    }
+
+    // Same comparison as the first page
+    let since = status_snapshots
+        .lock()
+        .await
+        .iter()
+        .find(|(chat, ..)| *chat == chat_id)
+        .and_then(|(.., previous)| previous.clone());
+
+    let (status, next) = format_status_page(&torrents, offset, since.as_ref());
+    set_status_page(status_pages, chat_id, next).await;
+
+    Ok(status)
+}
+
+fn format_torrent_list(
+    torrents: &[Torrent],
+    filter: Option<Media>,
+    action: TorrentListAction,
+) -> (String, Vec<i64>) {
+    let mut list = String::new();
+    let mut ids = Vec::new();
+
+    let mut number = 1;
+    for torrent in torrents {
+        let media_type = get_media_type_from_path(&torrent.download_dir);
+
+        if let Some(filter_media) = &filter {
+            if media_type.as_ref() != Some(filter_media) {
+                continue;
+            }
+        }
+
+        let media_label = media_type.as_ref().map_or("📁 Unknown", |media| media.label());
+
+        let percent = (torrent.percent_done * 100.0) as i64;
+
+        list.push_str(&format!(
+            "{}. {} - {} ({}%)\n",
+            number, media_label, torrent.name, percent
+        ));
+        ids.push(torrent.id);
+        number += 1;
+    }
+
+    if list.is_empty() {
+        list = "No downloads found".to_string();
+    } else {
+        list.insert_str(0, &format!("{}\n\n", action.prompt()));
+    }
+
+    (list, ids)
+}
+
+async fn dispatch_torrent_list(
+    filter: Option<Media>,
+    action: TorrentListAction,
+) -> Result<(String, Vec<i64>), String> {
+    if action.transmission_only() {
+        require_transmission()?;
+    }
+
+    let torrents = torrent_backend()?.get_torrents().await?;
+    Ok(format_torrent_list(&torrents, filter, action))
 }
 
 async fn dispatch_delete(
+    selection: &str,
+    torrent_ids: Vec<i64>,
+) -> Result<String, String> {
+    let indices = parse_index_selection(selection, torrent_ids.len())?;
+
+    let ids: Vec<i64> = indices.iter().map(|index| torrent_ids[index - 1]).collect();
+    torrent_backend()?.delete_torrent(ids).await?;
+
+    match indices.len() {
+        1 => Ok("🗑️ Torrent deleted".to_string()),
+        count => Ok(format!("🗑️ {} torrents deleted", count)),
+    }
+}
+
+async fn dispatch_failed_list() -> Result<(String, Vec<i64>), String> {
+    require_transmission()?;
+    let torrents = torrent_backend()?.get_torrents().await?;
+    let failed: Vec<Torrent> = torrents.into_iter().filter(|t| t.failed_to_start()).collect();
+
+    if failed.is_empty() {
+        return Ok(("✅ No torrents failed to start".to_string(), Vec::new()));
+    }
+
+    let (mut list, ids) = format_torrent_list(&failed, None, TorrentListAction::Retry);
+    list.push_str("\nErrors:\n");
+    for torrent in &failed {
+        list.push_str(&format!("• {}: {}\n", torrent.name, torrent.error_string));
+    }
+
+    Ok((list, ids))
+}
+
+async fn dispatch_stalled_list() -> Result<(String, Vec<i64>), String> {
+    require_transmission()?;
+    let torrents = torrent_backend()?.get_torrents().await?;
+    let stalled: Vec<Torrent> = torrents.into_iter().filter(|t| t.is_stalled()).collect();
+
+    if stalled.is_empty() {
+        return Ok(("✅ No stalled downloads".to_string(), Vec::new()));
+    }
+
+    let (mut list, ids) = format_torrent_list(&stalled, None, TorrentListAction::Retry);
+    list.push_str("\nPeers:\n");
+    for torrent in &stalled {
+        let flag = if torrent.is_dead() { "💀 likely dead" } else { "⏸️ waiting" };
+        list.push_str(&format!(
+            "• {}: {} ({} peers)\n",
+            torrent.name, flag, torrent.peers_connected
+        ));
+    }
+    list.push_str("\nUse /delete-torrent to remove one instead");
+
+    Ok((list, ids))
+}
+
+async fn dispatch_retry(
     index: usize,
     torrent_ids: Vec<i64>,
 ) -> Result<String, String> {
-    if index == 0 || index > torrent_ids.len() {
-        return Err("Invalid index".to_string());
+    check_index(index, torrent_ids.len())?;
+
+    let name = readd_torrent(torrent_ids[index - 1]).await?;
+
+    Ok(format!("🔁 Re-added {}", name))
+}
+
+fn format_ratio(ratio: f64) -> String {
+    if ratio < 0.0 {
+        "-".to_string()
+    } else {
+        format!("{:.2}", ratio)
+    }
+}
+
+fn format_ratio_report(torrents: &[Torrent], session: Option<f64>) -> (String, Vec<i64>) {
+    let downloaded: i64 = torrents.iter().map(|t| t.downloaded_ever).sum();
+    let uploaded: i64 = torrents.iter().map(|t| t.uploaded_ever).sum();
+    let aggregate = if downloaded > 0 {
+        uploaded as f64 / downloaded as f64
+    } else {
+        -1.0
+    };
+
+    let mut sorted: Vec<&Torrent> = torrents.iter().collect();
+    sorted.sort_by(|a, b| b.upload_ratio.total_cmp(&a.upload_ratio));
+
+    let mut list = format!(
+        "📈 Overall ratio: {}\n{}\n✅ = seed ratio limit met\n\n",
+        format_ratio(aggregate),
+        TorrentListAction::Stop.prompt()
+    );
+    let mut ids = Vec::new();
+
+    for (i, torrent) in sorted.iter().enumerate() {
+        let mark = if torrent.met_ratio_limit(session) { "✅ " } else { "" };
+        let limit = torrent
+            .ratio_limit(session)
+            .map(|limit| format!(" / {:.2}", limit))
+            .unwrap_or_default();
+
+        list.push_str(&format!(
+            "{}. {}{} - {}{}\n",
+            i + 1,
+            mark,
+            torrent.name,
+            format_ratio(torrent.upload_ratio),
+            limit
+        ));
+        ids.push(torrent.id);
+    }
+
+    (list, ids)
+}
+
+async fn dispatch_ratio_report() -> Result<(String, Vec<i64>), String> {
+    require_transmission()?;
+    let backend = torrent_backend()?;
+    let torrents = backend.get_torrents().await?;
+
+    if torrents.is_empty() {
+        return Ok(("No downloads found".to_string(), Vec::new()));
     }
 
+    Ok(format_ratio_report(&torrents, backend.session_ratio_limit().await?))
+}
+
+async fn dispatch_stop(
+    index: usize,
+    torrent_ids: Vec<i64>,
+) -> Result<String, String> {
+    check_index(index, torrent_ids.len())?;
+
+    let name = stop_torrent(torrent_ids[index - 1]).await?;
+
+    Ok(format!("⏹️ Stopped {}", name))
+}
+
+async fn dispatch_start(
+    index: usize,
+    torrent_ids: Vec<i64>,
+) -> Result<String, String> {
+    check_index(index, torrent_ids.len())?;
+
+    let name = start_torrent(torrent_ids[index - 1]).await?;
+
+    Ok(format!("▶️ Started {}", name))
+}
+
+async fn dispatch_reannounce(
+    index: usize,
+    torrent_ids: Vec<i64>,
+) -> Result<String, String> {
+    check_index(index, torrent_ids.len())?;
+
+    let name = reannounce_torrent(torrent_ids[index - 1]).await?;
+
+    Ok(format!("📣 Reannounce requested for {}", name))
+}
+
+const MAX_PEER_LIMIT: u16 = 1000;
+
+async fn dispatch_peer_limit(
+    index: usize,
+    text: &[String],
+    torrent_ids: Vec<i64>,
+) -> Result<String, String> {
+    check_index(index, torrent_ids.len())?;
+
+    let limit = match text {
+        [_, limit] => limit
+            .parse::<u16>()
+            .ok()
+            .filter(|limit| (1..=MAX_PEER_LIMIT).contains(limit))
+            .ok_or_else(|| format!("Peer limit must be a number from 1 to {}", MAX_PEER_LIMIT))?,
+        _ => return Err("Reply with the number and the limit, e.g. 2 50".to_string()),
+    };
+
+    set_peer_limit(torrent_ids[index - 1], limit).await?;
+
+    Ok(format!("👥 Peer limit set to {}", limit))
+}
+
+/// `text` is the reply without the command, e.g. ["2", "1.5"]
+async fn dispatch_seed_ratio(index: usize, text: &[String], torrent_ids: Vec<i64>) -> Result<String, String> {
+    check_index(index, torrent_ids.len())?;
+
+    let ratio = match text {
+        [_, ratio] => parse_seed_ratio(ratio)?,
+        _ => return Err("Reply with the number and the ratio, e.g. 2 1.5".to_string()),
+    };
+
     let id = torrent_ids[index - 1];
-    delete_torrent(vec![id]).await?;
+    let torrents = torrent_backend()?.get_torrents().await?;
+    let name = torrents
+        .iter()
+        .find(|torrent| torrent.id == id)
+        .map(|torrent| torrent.name.clone())
+        .ok_or("Torrent not found, it may have been removed")?;
+
+    set_seed_ratio(id, ratio).await?;
+
+    Ok(format!("🌱 Seed ratio for {} set to {}", name, ratio))
+}
+
+fn parse_seed_ratio(value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|ratio| ratio.is_finite() && *ratio > 0.0)
+        .ok_or_else(|| format!("Seed ratio must be a positive number, not {}", value))
+}
+
+/// Running torrents for /stop, stopped ones for /start
+async fn dispatch_state_list(action: TorrentListAction) -> Result<(String, Vec<i64>), String> {
+    require_transmission()?;
+    let torrents = torrent_backend()?.get_torrents().await?;
+    let stopped = action == TorrentListAction::Start;
+    let torrents: Vec<Torrent> = torrents.into_iter().filter(|t| (t.status == 0) == stopped).collect();
+
+    if torrents.is_empty() {
+        let text = if stopped { "No stopped torrents" } else { "No running torrents" };
+        return Ok((text.to_string(), Vec::new()));
+    }
+
+    Ok(format_torrent_list(&torrents, None, action))
+}
+
+async fn dispatch_torrent_files(
+    index: usize,
+    torrent_ids: Vec<i64>,
+) -> Result<String, String> {
+    check_index(index, torrent_ids.len())?;
+
+    let details = get_torrent_files_detailed(torrent_ids[index - 1]).await?;
 
-    Ok("🗑️ Torrent deleted".to_string())
+    Ok(format_torrent_files(&details))
+}
+
+fn format_torrent_files(details: &TorrentFiles) -> String {
+    use size_format::SizeFormatterSI;
+
+    let mut list = format!("📂 {}\n\n", details.name);
+
+    if details.files.is_empty() {
+        list.push_str("No file list yet, metadata is still being fetched");
+        return list;
+    }
+
+    if details.files.len() == 1 {
+        list.push_str("Single file torrent\n");
+    }
+
+    let prefix = format!("{}/", details.name);
+    for (i, file) in details.files.iter().enumerate().take(50) {
+        let percent = if file.length > 0 {
+            (file.bytes_completed as f64 / file.length as f64 * 100.0) as i64
+        } else {
+            100
+        };
+
+        let stats = details.file_stats.get(i);
+        let state = match stats {
+            Some(stats) if !stats.wanted => "⏭️",
+            _ if percent >= 100 => "✅",
+            _ => "⬇️",
+        };
+        let priority = match stats.map(|stats| stats.priority) {
+            Some(-1) => " (low priority)",
+            Some(1) => " (high priority)",
+            _ => "",
+        };
+
+        list.push_str(&format!(
+            "{}. {} {} ({}%) - {}B{}\n",
+            i + 1,
+            state,
+            file.name.strip_prefix(&prefix).unwrap_or(&file.name),
+            percent,
+            SizeFormatterSI::new(file.length as u64),
+            priority
+        ));
+    }
+
+    if details.files.len() > 50 {
+        list.push_str(&format!("... and {} more files\n", details.files.len() - 50));
+    }
+
+    list
+}
+
+async fn dispatch_torrent_details(index: usize, torrent_ids: Vec<i64>) -> Result<String, String> {
+    check_index(index, torrent_ids.len())?;
+
+    let details = get_torrent_details(torrent_ids[index - 1]).await?;
+
+    Ok(format_torrent_details(&details))
+}
+
+/// The file list of `format_torrent_files` followed by the torrent error
+/// and each tracker with its last announce
+fn format_torrent_details(details: &TorrentDetails) -> String {
+    let mut text = format_torrent_files(&details.files);
+
+    if !details.error_string.is_empty() {
+        text.push_str(&format!("\n⚠️ Error: {}\n", details.error_string));
+    }
+
+    text.push_str("\n📡 Trackers\n");
+    if details.tracker_stats.is_empty() {
+        text.push_str("No trackers\n");
+    }
+    for tracker in &details.tracker_stats {
+        // Transmission reports -1 when the tracker never said
+        let peers = if tracker.seeder_count >= 0 && tracker.leecher_count >= 0 {
+            format!(" - {} seeders, {} leechers", tracker.seeder_count, tracker.leecher_count)
+        } else {
+            String::new()
+        };
+
+        if tracker.last_announce_succeeded || tracker.last_announce_result.is_empty() {
+            text.push_str(&format!("✅ {}{}\n", tracker.host, peers));
+        } else {
+            text.push_str(&format!("❌ {}: {}\n", tracker.host, tracker.last_announce_result));
+        }
+    }
+
+    text
+}
+
+async fn dispatch_where(index: usize, torrent_ids: Vec<i64>) -> Result<String, String> {
+    check_index(index, torrent_ids.len())?;
+
+    let torrents = torrent_backend()?.get_torrents().await?;
+    format_torrent_location(&torrents, torrent_ids[index - 1])
+}
+
+/// `/where N` picks from the same numbering `/where` lists
+async fn dispatch_where_index(index: &str) -> Result<String, String> {
+    let index = index
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid number: {}", index))?;
+
+    let torrents = torrent_backend()?.get_torrents().await?;
+    let (_, ids) = format_torrent_list(&torrents, None, TorrentListAction::Where);
+    check_index(index, ids.len())?;
+
+    format_torrent_location(&torrents, ids[index - 1])
+}
+
+/// Full path of a torrent's data in a <pre> block, easy to copy, along with
+/// its download dir and media
+fn format_torrent_location(torrents: &[Torrent], id: i64) -> Result<String, String> {
+    let torrent = torrents
+        .iter()
+        .find(|torrent| torrent.id == id)
+        .ok_or("Torrent not found, it may have been removed")?;
+
+    let data_path = std::path::Path::new(&torrent.download_dir).join(&torrent.name);
+    let media = get_media_type_from_path(&torrent.download_dir);
+
+    Ok(format!(
+        "<pre>{}</pre>\n📂 {}\nDownload dir: {}\nMedia: {}",
+        escape_html(&data_path.to_string_lossy()),
+        escape_html(&torrent.name),
+        escape_html(&torrent.download_dir),
+        media.as_ref().map_or("📁 Unknown", |media| media.label())
+    ))
 }
 
 async fn dispatch_stop_seed() -> Result<String, String> {
-    stop_seeding_all().await?;
+    torrent_backend()?.stop_seeding_all().await?;
     Ok("⏹️ Stopped seeding for all downloads".to_string())
 }
 
+async fn dispatch_verify_all(text: Vec<String>) -> Result<String, String> {
+    if text.get(1).map(|arg| arg.as_str()) != Some("confirm") {
+        return Ok(
+            "⚠️ Verifying rereads every downloaded file, it's I/O heavy and can take hours on a large library.\nSend /verify-all confirm to start"
+                .to_string(),
+        );
+    }
+
+    require_transmission()?;
+    let count = verify_all().await?;
+    Ok(format!("🔍 Queued {} torrents for verification", count))
+}
+
+async fn dispatch_set_default_dir(text: Vec<String>) -> Result<String, String> {
+    if text.len() != 2 {
+        return Err("Usage: /set-default-dir <absolute path>".to_string());
+    }
+
+    let path = text[1].clone();
+    // The path lives on the Transmission host, so it can only be checked for shape
+    if !std::path::Path::new(&path).is_absolute() {
+        return Err(format!("{} is not an absolute path", path));
+    }
+
+    require_transmission()?;
+    set_default_download_dir(path.clone()).await?;
+    Ok(format!("📁 Transmission now downloads to {} by default", path))
+}
+
+const ERRORS_PER_CHAT: usize = 10;
+
+// Query parameters that carry credentials in indexer and tracker URLs
+const SECRET_PARAMS: &[&str] = &["apikey=", "api_key=", "passkey=", "token="];
+
+/// Mask credentials before an error is kept or shown again
+fn redact(text: &str) -> String {
+    let mut redacted = text.to_string();
+
+    if let Ok(token) = env::var("TELEGRAM_BOT_TOKEN") {
+        if !token.is_empty() {
+            redacted = redacted.replace(&token, "***");
+        }
+    }
+
+    for param in SECRET_PARAMS {
+        let mut from = 0;
+        while let Some(found) = redacted[from..].find(param) {
+            let start = from + found + param.len();
+            let end = redacted[start..]
+                .find(|c: char| c == '&' || c.is_whitespace())
+                .map_or(redacted.len(), |end| start + end);
+            redacted.replace_range(start..end, "***");
+            from = start + 3;
+        }
+    }
+
+    redacted
+}
+
+async fn record_error(
+    chat_id: ChatId,
+    command: &str,
+    error: &str,
+    chat_errors: &Arc<Mutex<Vec<(ChatId, Instant, String, String)>>>,
+) {
+    let mut errors = chat_errors.lock().await;
+    errors.push((chat_id, Instant::now(), command.to_string(), redact(error)));
+
+    // Keep only the newest ERRORS_PER_CHAT of this chat
+    let count = errors.iter().filter(|(chat, ..)| *chat == chat_id).count();
+    if count > ERRORS_PER_CHAT {
+        if let Some(oldest) = errors.iter().position(|(chat, ..)| *chat == chat_id) {
+            errors.remove(oldest);
+        }
+    }
+}
+
+fn format_age(age: std::time::Duration) -> String {
+    match age.as_secs() {
+        secs if secs < 60 => format!("{}s", secs),
+        secs if secs < 3600 => format!("{}m", secs / 60),
+        secs if secs < 86400 => format!("{}h", secs / 3600),
+        secs => format!("{}d", secs / 86400),
+    }
+}
+
+async fn dispatch_errors(
+    chat_id: ChatId,
+    chat_errors: &Arc<Mutex<Vec<(ChatId, Instant, String, String)>>>,
+) -> Result<String, String> {
+    let errors = chat_errors.lock().await;
+    let mut list = String::new();
+
+    for (_, when, command, error) in errors.iter().rev().filter(|(chat, ..)| *chat == chat_id) {
+        list.push_str(&format!(
+            "• {} ago, {}: {}\n",
+            format_age(when.elapsed()),
+            command,
+            error
+        ));
+    }
+
+    if list.is_empty() {
+        return Ok("✅ No recent errors in this chat".to_string());
+    }
+
+    Ok(format!("🧾 Recent errors, newest first:\n\n{}", list))
+}
+
 async fn dispatch_storage() -> Result<String, String> {
     get_storage_info()
 }
@@ -261,110 +1948,703 @@ fn list_files_in_directory(dir_path: &str) -> Result<Vec<String>, String> {
     Ok(files)
 }
 
-fn format_file_list(files: &[String], _base_path: &str) -> (String, Vec<String>) {
+const FILE_PAGE_SIZE: usize = 15;
+
+/// Torrent whose data is the path, lives inside it or contains it
+fn torrent_for_path<'a>(file_path: &str, torrents: &'a [Torrent]) -> Option<&'a Torrent> {
+    let path = std::path::Path::new(file_path);
+
+    torrents.iter().find(|torrent| {
+        let content = std::path::Path::new(&torrent.download_dir).join(&torrent.name);
+        content.starts_with(path) || path.starts_with(&content)
+    })
+}
+
+fn torrent_state(torrent: &Torrent) -> &'static str {
+    match torrent.status {
+        5 | 6 => "seeding",
+        3 | 4 => "downloading",
+        0 => "stopped torrent",
+        _ => "in Transmission",
+    }
+}
+
+/// Torrents for the file lists, a Transmission outage only loses the warnings
+async fn torrents_for_file_list() -> Vec<Torrent> {
+    let torrents = match torrent_backend() {
+        Ok(backend) => backend.get_torrents().await,
+        Err(err) => Err(err),
+    };
+
+    torrents.unwrap_or_else(|err| {
+        println!("Listing files without torrent warnings: {}", err);
+        Vec::new()
+    })
+}
+
+/// One page of a file list, numbered from the start of the whole list
+fn format_file_list(files: &[String], page: usize, torrents: &[Torrent]) -> String {
     let mut list = String::new();
-    let mut paths = Vec::new();
 
-    let mut number = 1;
-    for file_path in files {
+    let start = page * FILE_PAGE_SIZE;
+    let mut number = start + 1;
+    for file_path in files.iter().skip(start).take(FILE_PAGE_SIZE) {
         // Get just the file/folder name
         let display_name = std::path::Path::new(file_path)
             .file_name()
             .and_then(|n| n.to_str())
             .unwrap_or(file_path);
         
+        let warning = torrent_for_path(file_path, torrents)
+            .map(|torrent| format!(" ⚠️ {}", torrent_state(torrent)))
+            .unwrap_or_default();
+
         list.push_str(&format!(
-            "{}. {}\n",
-            number, display_name
+            "{}. {}{}\n",
+            number, display_name, warning
         ));
-        paths.push(file_path.clone());
         number += 1;
     }
 
-    if list.is_empty() {
-        list = "No files found".to_string();
+    if list.is_empty() {
+        return "No files found".to_string();
+    }
+
+    list.insert_str(0, "Reply with the numbers to delete, e.g. 2, 1 3 5 or 1-4 (file):\n\n");
+
+    let pages = (files.len() + FILE_PAGE_SIZE - 1) / FILE_PAGE_SIZE;
+    if pages > 1 {
+        let mut hints = Vec::new();
+        if page > 0 {
+            hints.push("'prev'");
+        }
+        if page + 1 < pages {
+            hints.push("'next'");
+        }
+        list.push_str(&format!(
+            "\nPage {} of {}. Reply {} to change page",
+            page + 1,
+            pages,
+            hints.join(" or ")
+        ));
+    }
+
+    list
+}
+
+async fn dispatch_delete_file_list(media: Media) -> Result<(String, Vec<String>), String> {
+    let path = media_path(&media)?;
+
+    let files = list_files_in_directory(&path)?;
+    let torrents = torrents_for_file_list().await;
+    Ok((format_file_list(&files, 0, &torrents), files))
+}
+
+/// Indices picked by a reply to a file list and whether it ends with "force"
+///
+/// "all" deletes the whole library folder, so it has to be "all confirm".
+fn parse_file_selection(selection: &str, max: usize) -> Result<(Vec<usize>, bool), String> {
+    let (selection, force) = match selection.trim().strip_suffix("force") {
+        Some(selection) => (selection.trim(), true),
+        None => (selection.trim(), false),
+    };
+
+    if selection.eq_ignore_ascii_case("all") {
+        return Err(format!(
+            "⚠️ This deletes all {} files/folders from disk.\nReply with 'all confirm' to delete them",
+            max
+        ));
+    }
+    let selection = match selection.strip_suffix("confirm") {
+        Some(all) if all.trim().eq_ignore_ascii_case("all") => "all",
+        _ => selection,
+    };
+
+    Ok((parse_index_selection(selection, max)?, force))
+}
+
+/// Delete the selected files, unless some still belong to a torrent and the
+/// reply doesn't end with "force"
+async fn dispatch_delete_file(
+    selection: &str,
+    file_paths: Vec<String>,
+) -> Result<String, String> {
+    let (indices, force) = parse_file_selection(selection, file_paths.len())?;
+
+    if !force {
+        let torrents = torrents_for_file_list().await;
+        let in_use: Vec<String> = indices
+            .iter()
+            .filter_map(|index| {
+                torrent_for_path(&file_paths[index - 1], &torrents)
+                    .map(|torrent| format!("{}. {} ({})", index, torrent.name, torrent_state(torrent)))
+            })
+            .collect();
+
+        if !in_use.is_empty() {
+            return Err(format!(
+                "⚠️ Deleting these breaks their torrents:\n{}\nReply with the same numbers followed by 'force' to delete anyway",
+                in_use.join("\n")
+            ));
+        }
+    }
+
+    if let [index] = indices[..] {
+        return delete_path(&file_paths[index - 1]);
+    }
+
+    let mut deleted = 0;
+    let mut errors = Vec::new();
+    for index in indices {
+        match delete_path(&file_paths[index - 1]) {
+            Ok(_) => deleted += 1,
+            Err(err) => errors.push(format!("{}. {}", index, err)),
+        }
+    }
+
+    let mut text = format!("🗑️ Deleted {} files/folders", deleted);
+    if !errors.is_empty() {
+        text.push_str(&format!("\n\n❌ Failed:\n{}", errors.join("\n")));
+    }
+
+    Ok(text)
+}
+
+fn delete_path(file_path: &str) -> Result<String, String> {
+    use std::fs;
+    use std::path::Path;
+
+    let path = Path::new(file_path);
+
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+            .map_err(|e| format!("Failed to delete directory: {}", e))?;
+        Ok(format!("🗑️ Directory deleted: {}", path.file_name().unwrap_or_default().to_string_lossy()))
+    } else if path.is_file() {
+        fs::remove_file(path)
+            .map_err(|e| format!("Failed to delete file: {}", e))?;
+        Ok(format!("🗑️ File deleted: {}", path.file_name().unwrap_or_default().to_string_lossy()))
+    } else {
+        Err("Path does not exist".to_string())
+    }
+}
+
+/// Only directories below one of RESTRUCTURE_ALLOWED_PATHS can be scanned on demand
+fn validate_restructure_source(path: &str) -> Result<String, String> {
+    use std::path::Path;
+
+    let allowed: Vec<String> = env::var("RESTRUCTURE_ALLOWED_PATHS")
+        .map(|val| val.split(',').map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect())
+        .unwrap_or_default();
+
+    if allowed.is_empty() {
+        return Err("Set RESTRUCTURE_ALLOWED_PATHS to restructure a custom directory".to_string());
+    }
+
+    let canonical = Path::new(path)
+        .canonicalize()
+        .map_err(|e| format!("Directory {} is not accessible: {}", path, e))?;
+
+    if !canonical.is_dir() {
+        return Err(format!("Path is not a directory: {}", path));
+    }
+
+    let is_allowed = allowed.iter().any(|root| {
+        Path::new(root)
+            .canonicalize()
+            .map(|root| canonical.starts_with(root))
+            .unwrap_or(false)
+    });
+
+    if !is_allowed {
+        return Err(format!("{} is outside RESTRUCTURE_ALLOWED_PATHS", path));
+    }
+
+    Ok(canonical.to_string_lossy().to_string())
+}
+
+fn parse_restructure_media(arg: &str) -> Result<Media, String> {
+    match arg.to_lowercase().as_str() {
+        "tv" => Ok(Media::TV),
+        "movie" => Ok(Media::Movie),
+        "anime" => Ok(Media::Anime.resolve()),
+        _ => Err("Invalid media type. Use 'tv', 'movie' or 'anime'".to_string()),
+    }
+}
+
+/// Library root restructured files go to, ACTUAL_*_PATH when the bot sees
+/// the media under a different path than Transmission
+fn restructure_base_path(media: &Media) -> Result<String, String> {
+    let actual_env_var = match media {
+        Media::TV => "ACTUAL_TV_PATH",
+        Media::Movie => "ACTUAL_MOVIE_PATH",
+        Media::Anime => "ACTUAL_ANIME_PATH",
+    };
+
+    env::var(actual_env_var)
+        .ok()
+        .map(Ok)
+        .unwrap_or_else(|| media_path(media))
+}
+
+/// Quick count of the files /restructure would report as unparseable
+async fn dispatch_unparseable(text: Vec<String>) -> Result<String, String> {
+    use size_format::SizeFormatterSI;
+
+    if text.len() < 2 {
+        return Err("Usage: /unparseable <tv|movie|anime>".to_string());
+    }
+
+    let media = parse_restructure_media(&text[1])?;
+    let base_path = restructure_base_path(&media)?;
+
+    let (parseable, unparseable, size) = crate::restructure::tally_unparseable(media, &base_path).await?;
+
+    Ok(format!(
+        "🔍 {} parseable, {} unparseable ({}B)",
+        parseable,
+        unparseable,
+        SizeFormatterSI::new(size)
+    ))
+}
+
+/// Show where /restructure would put a file with this name, without touching the disk
+async fn dispatch_preview_name(text: Vec<String>) -> Result<String, String> {
+    if text.len() < 3 {
+        return Err("Usage: /preview-name <tv|movie|anime> <filename>".to_string());
+    }
+
+    let media = parse_restructure_media(&text[1])?;
+    let base_path = restructure_base_path(&media)?;
+    let filename = text[2..].join(" ");
+
+    let target = crate::restructure::preview_target_path(media, &filename, &base_path).await?;
+
+    Ok(format!("📝 {}\n   → {}", filename, target))
+}
+
+/// Link completed downloads of a category into the HARVEST_*_PATH library,
+/// leaving the originals seeding
+async fn dispatch_harvest(text: Vec<String>) -> Result<String, String> {
+    if text.len() < 2 {
+        return Err("Usage: /harvest <tv|movie|anime>".to_string());
+    }
+
+    let media = parse_restructure_media(&text[1])?;
+    let harvest_env_var = match media {
+        Media::TV => "HARVEST_TV_PATH",
+        Media::Movie => "HARVEST_MOVIE_PATH",
+        Media::Anime => "HARVEST_ANIME_PATH",
+    };
+    let library_path = env::var(harvest_env_var)
+        .map_err(|_| format!("Set {} to the library /harvest should fill", harvest_env_var))?;
+    let transmission_root = media_path(&media)?;
+    // Where the bot sees the downloads, when it differs from Transmission's view
+    let local_root = restructure_base_path(&media)?;
+
+    let torrents = torrent_backend()?.get_torrents().await?;
+    let mut files = Vec::new();
+    for torrent in torrents.iter().filter(|t| {
+        t.percent_done >= 1.0 && get_media_type_from_path(&t.download_dir).as_ref() == Some(&media)
+    }) {
+        let download_dir = torrent.download_dir.replacen(&transmission_root, &local_root, 1);
+        let path = std::path::Path::new(&download_dir).join(&torrent.name);
+        files.extend(crate::restructure::torrent_video_files(&path.to_string_lossy())?);
+    }
+
+    if files.is_empty() {
+        return Ok("✅ No completed downloads to harvest".to_string());
+    }
+
+    crate::restructure::harvest_files(media, &files, &library_path).await
+}
+
+async fn dispatch_restructure(
+    text: Vec<String>,
+) -> Result<(String, Option<crate::restructure::RestructurePlan>), String> {
+    use crate::restructure::PlanTarget;
+
+    if text.len() < 2 {
+        return Err("Usage: /restructure <tv|movie|anime|auto> [directory] [--since 7d] [--cleanup] [--cleanup-junk]".to_string());
+    }
+
+    let target = if text[1].as_str() == "auto" {
+        PlanTarget::Auto {
+            tv_path: restructure_base_path(&Media::TV)?,
+            movie_path: restructure_base_path(&Media::Movie)?,
+        }
+    } else {
+        let media = parse_restructure_media(&text[1])?;
+        let base_path = restructure_base_path(&media)?;
+        PlanTarget::Single(media, base_path)
+    };
+
+    let mut directory = None;
+    let mut modified_since = None;
+    let mut cleanup = crate::restructure::Cleanup::default();
+    let mut args = text[2..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--since" => {
+                let window = args.next().ok_or("Pass a duration after --since (e.g. 7d)")?;
+                let window = crate::restructure::parse_duration(window)?;
+                modified_since = std::time::SystemTime::now().checked_sub(window);
+            }
+            "--cleanup" => cleanup.empty_dirs = true,
+            "--cleanup-junk" => {
+                if crate::restructure::junk_extensions().is_empty() {
+                    return Err("Set RESTRUCTURE_CLEANUP_EXTENSIONS (e.g. nfo,txt,jpg) to use --cleanup-junk".to_string());
+                }
+                cleanup.junk = true;
+            }
+            path => directory = Some(path.to_string()),
+        }
+    }
+
+    // Files are still moved into the media root, only the scanned directory changes
+    let scan_path = match (directory, &target) {
+        (Some(path), _) => validate_restructure_source(&path)?,
+        (None, PlanTarget::Single(_, base_path)) => base_path.clone(),
+        (None, PlanTarget::Auto { .. }) => {
+            return Err("Usage: /restructure auto <directory> [--since 7d] [--cleanup] [--cleanup-junk]".to_string())
+        }
+    };
+
+    let mut plan = crate::restructure::generate_restructure_plan(target, &scan_path, modified_since).await?;
+    plan.cleanup = cleanup;
+
+    if plan.operations.is_empty()
+        && plan.unparseable_files.is_empty()
+        && plan.multi_season_files.is_empty()
+    {
+        Ok(("✅ Nothing to restructure".to_string(), None))
+    } else {
+        let text = crate::restructure::format_restructure_plan(&plan);
+        Ok((text, Some(plan)))
+    }
+}
+
+/// Escape text for messages sent with `ParseMode::Html`
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// Only the <pre> search results are HTML, everything else goes out as plain
+// text so file names with <, > or & can't break the message
+fn is_html(text: &str) -> bool {
+    text.starts_with("<pre>")
+}
+
+const SEND_ATTEMPTS: u32 = 4;
+
+/// Seconds Telegram asks to wait in a "Too Many Requests: retry after N" error
+fn retry_after(error: &str) -> Option<u64> {
+    let (_, after) = error.split_once("retry after ")?;
+    after.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok()
+}
+
+// Telegram rejected the message itself, sending it again won't help
+fn is_permanent_send_error(error: &str) -> bool {
+    ["Bad Request", "Forbidden", "Unauthorized", "Not Found"]
+        .iter()
+        .any(|kind| error.contains(kind))
+}
+
+// Telegram caps inline keyboards at 100 buttons
+const MAX_LIST_BUTTONS: usize = 50;
+const BUTTON_LABEL_CHARS: usize = 60;
+
+/// One button per numbered line ("3. ...") of a list, carrying the number as
+/// callback data so a press is handled like replying with it
+fn list_keyboard(text: &str) -> Option<InlineKeyboardMarkup> {
+    let mut keyboard = InlineKeyboardMarkup::new();
+    let mut buttons = 0;
+
+    for line in text.lines().take_while(|_| buttons < MAX_LIST_BUTTONS) {
+        let line = line.trim_start_matches("<pre>").trim_end_matches("</pre>");
+        let Some((number, _)) = line.split_once(". ") else {
+            continue;
+        };
+        if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+
+        let line = line.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&");
+        let mut label: String = line.chars().take(BUTTON_LABEL_CHARS).collect();
+        if line.chars().count() > BUTTON_LABEL_CHARS {
+            label.push('…');
+        }
+
+        keyboard.add_row(vec![InlineKeyboardButton::callback(label, number)]);
+        buttons += 1;
+    }
+
+    if buttons > 0 {
+        Some(keyboard)
+    } else {
+        None
+    }
+}
+
+// Magnet lists are small, anything bigger isn't one
+const MAGNET_LIST_MAX_BYTES: i64 = 1024 * 1024;
+
+fn media_word(word: &str) -> Option<Media> {
+    match word.to_lowercase().as_str() {
+        "tv" => Some(Media::TV),
+        "movie" => Some(Media::Movie),
+        "anime" => Some(Media::Anime),
+        _ => None,
+    }
+}
+
+/// Magnets of an imported list with their media, a "tv ", "movie " or
+/// "anime " prefix on the line wins over `default`. Blank and # lines are
+/// ignored, the other lines that aren't magnets are counted as skipped
+fn parse_magnet_list(text: &str, default: Option<Media>) -> (Vec<(Option<Media>, String)>, usize) {
+    let mut magnets = Vec::new();
+    let mut skipped = 0;
+
+    for line in text.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (media, magnet) = match line.split_once(char::is_whitespace) {
+            Some((word, rest)) if media_word(word).is_some() => (media_word(word), rest.trim()),
+            _ => (default.clone(), line),
+        };
+
+        if magnet.starts_with("magnet:?") && !magnet.contains(char::is_whitespace) {
+            magnets.push((media, magnet.to_string()));
+        } else {
+            skipped += 1;
+        }
+    }
+
+    (magnets, skipped)
+}
+
+/// Text of a document sent to the bot, through the Telegram file API
+async fn download_document(api: &Api, document: &Document) -> Result<String, String> {
+    if document.file_size.is_some_and(|size| size > MAGNET_LIST_MAX_BYTES) {
+        return Err("The file is too big for a magnet list".to_string());
+    }
+
+    let file = api
+        .send(document.get_file())
+        .await
+        .map_err(|err| format!("Failed to get the file from Telegram: {}", err))?;
+    let token = env::var("TELEGRAM_BOT_TOKEN").map_err(|_| "TELEGRAM_BOT_TOKEN not set".to_string())?;
+    let url = file.get_url(&token).ok_or("Telegram didn't give a download path for the file")?;
+
+    let https = hyper_rustls::HttpsConnector::with_native_roots();
+    let client: hyper::client::Client<_> = hyper::client::Client::builder().build(https);
+    let uri = url.parse::<hyper::Uri>().map_err(|err| format!("Invalid file url: {}", err))?;
+    let response = client
+        .get(uri)
+        .await
+        .map_err(|err| format!("Failed to download the file: {}", err))?;
+    if !response.status().is_success() {
+        return Err(format!("Telegram replied with {} to the file download", response.status()));
+    }
+
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|err| format!("Failed to download the file: {}", err))?;
+
+    Ok(String::from_utf8_lossy(&body).to_string())
+}
+
+/// Add every magnet of a .txt list, with the media from the caption or the line
+async fn import_magnet_list(api: &Api, message: &Message, document: &Document, caption: Option<&str>) -> Result<String, String> {
+    let default = caption
+        .and_then(|caption| caption.split_whitespace().next())
+        .and_then(media_word);
+    let text = download_document(api, document).await?;
+
+    let (magnets, skipped) = parse_magnet_list(&text, default);
+    if magnets.is_empty() {
+        return Err("No magnet links in the file, put one per line".to_string());
+    }
+
+    let backend = torrent_backend()?;
+    let requester = requester_name(message);
+    let total = magnets.len();
+    let mut added = 0;
+    let mut failures = Vec::new();
+
+    for (line, (media, magnet)) in magnets.into_iter().enumerate() {
+        let name = crate::torrentfile::parse_magnet_metadata(&magnet)
+            .ok()
+            .and_then(|(name, _)| name)
+            .unwrap_or_else(|| format!("Magnet {}", line + 1));
+
+        let Some(media) = media else {
+            failures.push(format!("{}: no category, caption the file or prefix the line with tv, movie or anime", name));
+            continue;
+        };
+
+        let location = TorrentLocation { content: magnet, is_magnet: true };
+        match backend.add_torrent(location, media, requester.clone()).await {
+            Ok(_) => added += 1,
+            Err(err) => failures.push(format!("{}: {}", name, err)),
+        }
+    }
+
+    let mut summary = format!("📥 Added {} of {} magnets", added, total);
+    if skipped > 0 {
+        summary.push_str(&format!("\n• {} lines skipped, they aren't magnet links", skipped));
+    }
+    if !failures.is_empty() {
+        summary.push_str(&format!("\n• {} failed:\n", failures.len()));
+        for failure in failures.iter().take(10) {
+            summary.push_str(&format!("  - {}\n", failure));
+        }
+        if failures.len() > 10 {
+            summary.push_str(&format!("  ... and {} more\n", failures.len() - 10));
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Documents sent to the bot, .txt files are imported as magnet lists
+pub async fn handle_document(api: &Api, message: &Message) -> Result<(), ()> {
+    let MessageKind::Document { ref data, ref caption } = message.kind else {
+        return Ok(());
+    };
+    let is_text = data
+        .file_name
+        .as_ref()
+        .is_some_and(|name| name.to_lowercase().ends_with(".txt"));
+    if !is_text {
+        return Ok(());
+    }
+
+    // Adding is refused the same way as /torrent-tv
+    let groups = allowed_groups();
+    let result = if groups.is_empty() {
+        Err("Importing magnets only works once this chat is added to TELEGRAM_ALLOWED_GROUPS (see /chat-id)".to_string())
+    } else if !groups.contains(&message.chat.id()) {
+        return Ok(());
     } else {
-        list.insert_str(0, "Reply with the number to delete (file):\n\n");
-    }
-
-    (list, paths)
-}
+        import_magnet_list(api, message, data, caption.as_deref()).await
+    };
 
-async fn dispatch_delete_file_list(media: Media) -> Result<(String, Vec<String>), String> {
-    let path = match media {
-        Media::TV => env::var("TRANSMISSION_TV_PATH")
-            .map_err(|_| "TRANSMISSION_TV_PATH env var is not set".to_string())?,
-        Media::Movie => env::var("TRANSMISSION_MOVIE_PATH")
-            .map_err(|_| "TRANSMISSION_MOVIE_PATH env var is not set".to_string())?,
+    println!("{:?}", result);
+    let text = match result {
+        Ok(text) => text,
+        Err(err) => format!("❌ {}", err),
     };
+    send_message(api, message, text)
+        .await
+        .map_err(|err| println!("{}", err))?;
 
-    let files = list_files_in_directory(&path)?;
-    Ok(format_file_list(&files, &path))
+    Ok(())
 }
 
-async fn dispatch_delete_file(
-    index: usize,
-    file_paths: Vec<String>,
-) -> Result<String, String> {
-    use std::fs;
-    use std::path::Path;
-    
-    if index == 0 || index > file_paths.len() {
-        return Err("Invalid index".to_string());
-    }
+/// Button press on a list keyboard
+///
+/// The press becomes a reply to the list from the user who pressed it, so it
+/// goes through the same access checks and handlers as typing the number.
+pub async fn handle_callback(api: &Api, query: CallbackQuery, state: &mut BotState) -> Result<(), ()> {
+    api.spawn(query.acknowledge());
 
-    let file_path = &file_paths[index - 1];
-    let path = Path::new(file_path);
+    let (Some(data), Some(MessageOrChannelPost::Message(list))) = (query.data, query.message) else {
+        return Ok(());
+    };
 
-    if path.is_dir() {
-        fs::remove_dir_all(path)
-            .map_err(|e| format!("Failed to delete directory: {}", e))?;
-        Ok(format!("🗑️ Directory deleted: {}", path.file_name().unwrap_or_default().to_string_lossy()))
-    } else if path.is_file() {
-        fs::remove_file(path)
-            .map_err(|e| format!("Failed to delete file: {}", e))?;
-        Ok(format!("🗑️ File deleted: {}", path.file_name().unwrap_or_default().to_string_lossy()))
-    } else {
-        Err("Path does not exist".to_string())
-    }
+    let mut message = list.clone();
+    message.from = query.from;
+    message.reply_to_message = Some(Box::new(MessageOrChannelPost::Message(list)));
+
+    handle_message(api, &message, vec![data], state).await
+}
+
+/// Reply to `message`, retrying rate limits after the delay Telegram asks
+/// for and network errors with a growing backoff
+pub async fn send_message(api: &Api, message: &Message, text: String) -> Result<MessageId, String> {
+    send_message_with_keyboard(api, message, text, None).await
 }
 
-pub async fn send_message(api: &Api, message: &Message, text: String) -> Result<Option<MessageId>, ()> {
+async fn send_message_with_keyboard(
+    api: &Api,
+    message: &Message,
+    text: String,
+    keyboard: Option<InlineKeyboardMarkup>,
+) -> Result<MessageId, String> {
+    let html = is_html(&text);
     let mut reply = message.text_reply(text);
+    if html {
+        reply.parse_mode(ParseMode::Html);
+    }
+    if let Some(keyboard) = keyboard {
+        reply.reply_markup(keyboard);
+    }
 
-    let result = api.send(reply.parse_mode(ParseMode::Html)).await;
-    match result {
-        Ok(sent_msg) => {
-            use telegram_bot::MessageOrChannelPost;
-            let msg_id = match sent_msg {
-                MessageOrChannelPost::Message(m) => m.id,
-                MessageOrChannelPost::ChannelPost(cp) => cp.id,
-            };
-            println!("Reply sent with id: {:?}", msg_id);
-            Ok(Some(msg_id))
-        }
-        Err(err) => {
-            println!("Error when sending telegram message: {}", err);
-            Ok(None)
+    let mut backoff = std::time::Duration::from_secs(1);
+    let mut attempt = 1;
+
+    loop {
+        let error = match api.send(&mut reply).await {
+            Ok(sent_msg) => {
+                use telegram_bot::MessageOrChannelPost;
+                let msg_id = match sent_msg {
+                    MessageOrChannelPost::Message(m) => m.id,
+                    MessageOrChannelPost::ChannelPost(cp) => cp.id,
+                };
+                println!("Reply sent with id: {:?}", msg_id);
+                return Ok(msg_id);
+            }
+            Err(err) => err.to_string(),
+        };
+
+        if attempt >= SEND_ATTEMPTS || is_permanent_send_error(&error) {
+            return Err(format!("Error when sending telegram message: {}", error));
         }
+
+        let delay = match retry_after(&error) {
+            Some(seconds) => std::time::Duration::from_secs(seconds),
+            None => {
+                let delay = backoff;
+                backoff *= 2;
+                delay
+            }
+        };
+
+        println!(
+            "Sending telegram message failed ({}), attempt {} of {}. Retrying in {}s",
+            error,
+            attempt,
+            SEND_ATTEMPTS,
+            delay.as_secs()
+        );
+        tokio::time::sleep(delay).await;
+        attempt += 1;
     }
 }
 // Holds a pending list to be stored after message is sent and message ID is known
 enum PendingList {
-    Torrent(Vec<i64>),
-    File(Vec<String>),
+    Torrent(Vec<i64>, TorrentListAction),
+    File(Vec<String>, usize),
     Restructure(crate::restructure::RestructurePlan),
+    Confirmation(TorrentLocation, Media),
+    Moves(Vec<crate::restructure::MoveOperation>, String, crate::restructure::Cleanup),
 }
 
 async fn add_response(
     response: Result<TelegramJackettResponse, String>,
     responses: &mut Arc<Mutex<Vec<TelegramJackettResponse>>>,
+    chat_id: ChatId,
 ) -> Result<String, String> {
     match response {
         Ok(response) => {
             let mut r = responses.lock().await;
 
-            let reply_text = format_telegram_response(response.clone());
+            let reply_text = format_telegram_response(response.clone(), search_format(chat_id.into()));
             r.push(response);
             Ok(reply_text)
         }
@@ -372,14 +2652,62 @@ async fn add_response(
     }
 }
 
+const DEFAULT_PENDING_LIST_TTL_SECS: u64 = 600;
+
+/// How long a list or plan takes replies, PENDING_LIST_TTL_SECS (10 minutes
+/// by default). Ids and paths in older lists may point to something else by now
+fn pending_list_ttl() -> Duration {
+    let secs = env::var("PENDING_LIST_TTL_SECS")
+        .ok()
+        .and_then(|val| val.trim().parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_PENDING_LIST_TTL_SECS);
+
+    Duration::from_secs(secs)
+}
+
+/// Drop the list or plan `reply_id` points to when it's past the TTL,
+/// telling whether it was
+async fn expire_pending_list(
+    reply_id: MessageId,
+    torrent_lists: &Arc<Mutex<Vec<(Vec<i64>, TorrentListAction, String, MessageId, Instant)>>>,
+    file_lists: &Arc<Mutex<Vec<(Vec<String>, usize, String, MessageId, Instant)>>>,
+    restructure_plans: &Arc<Mutex<Vec<(crate::restructure::RestructurePlan, String, MessageId, Instant)>>>,
+) -> bool {
+    let ttl = pending_list_ttl();
+    let expired = |id: &MessageId, sent: &Instant| *id == reply_id && sent.elapsed() > ttl;
+
+    let mut lists = torrent_lists.lock().await;
+    let before = lists.len();
+    lists.retain(|(.., id, sent)| !expired(id, sent));
+    if lists.len() < before {
+        return true;
+    }
+    drop(lists);
+
+    let mut lists = file_lists.lock().await;
+    let before = lists.len();
+    lists.retain(|(.., id, sent)| !expired(id, sent));
+    if lists.len() < before {
+        return true;
+    }
+    drop(lists);
+
+    let mut plans = restructure_plans.lock().await;
+    let before = plans.len();
+    plans.retain(|(.., id, sent)| !expired(id, sent));
+    plans.len() < before
+}
+
 async fn add_torrent_list(
     text: String,
     torrent_ids: Vec<i64>,
-    torrent_lists: &mut Arc<Mutex<Vec<(Vec<i64>, String, MessageId)>>>,
+    action: TorrentListAction,
+    torrent_lists: &mut Arc<Mutex<Vec<(Vec<i64>, TorrentListAction, String, MessageId, Instant)>>>,
     message_id: MessageId,
 ) -> String {
     let mut lists = torrent_lists.lock().await;
-    lists.push((torrent_ids, text.clone(), message_id));
+    lists.push((torrent_ids, action, text.clone(), message_id, Instant::now()));
     // Keep only last 100 lists to avoid memory issues
     if lists.len() > 100 {
         lists.remove(0);
@@ -390,11 +2718,12 @@ async fn add_torrent_list(
 async fn add_file_list(
     text: String,
     file_paths: Vec<String>,
-    file_lists: &mut Arc<Mutex<Vec<(Vec<String>, String, MessageId)>>>,
+    page: usize,
+    file_lists: &mut Arc<Mutex<Vec<(Vec<String>, usize, String, MessageId, Instant)>>>,
     message_id: MessageId,
 ) -> String {
     let mut lists = file_lists.lock().await;
-    lists.push((file_paths, text.clone(), message_id));
+    lists.push((file_paths, page, text.clone(), message_id, Instant::now()));
     // Keep only last 100 lists to avoid memory issues
     if lists.len() > 100 {
         lists.remove(0);
@@ -405,11 +2734,11 @@ async fn add_file_list(
 async fn add_restructure_plan(
     text: String,
     plan: crate::restructure::RestructurePlan,
-    plans: &mut Arc<Mutex<Vec<(crate::restructure::RestructurePlan, String, MessageId)>>>,
+    plans: &mut Arc<Mutex<Vec<(crate::restructure::RestructurePlan, String, MessageId, Instant)>>>,
     message_id: MessageId,
 ) -> String {
     let mut p = plans.lock().await;
-    p.push((plan, text.clone(), message_id));
+    p.push((plan, text.clone(), message_id, Instant::now()));
     // Keep only last 100 plans to avoid memory issues
     if p.len() > 100 {
         p.remove(0);
@@ -417,34 +2746,106 @@ async fn add_restructure_plan(
     text
 }
 
-fn transmission_path(env_var: String) -> Result<String, String> {
-    env::var(&env_var).map_err(|_| format!("{} env var is not set", env_var))
+async fn add_confirmation(
+    text: String,
+    location: TorrentLocation,
+    media: Media,
+    confirmations: &mut Arc<Mutex<Vec<(TorrentLocation, Media, MessageId)>>>,
+    message_id: MessageId,
+) -> String {
+    let mut c = confirmations.lock().await;
+    c.push((location, media, message_id));
+    // Keep only last 100 confirmations to avoid memory issues
+    if c.len() > 100 {
+        c.remove(0);
+    }
+    text
+}
+
+async fn add_pending_moves(
+    text: String,
+    operations: Vec<crate::restructure::MoveOperation>,
+    scan_path: String,
+    cleanup: crate::restructure::Cleanup,
+    pending_moves: &mut Arc<Mutex<Vec<(Vec<crate::restructure::MoveOperation>, String, crate::restructure::Cleanup, MessageId)>>>,
+    message_id: MessageId,
+) -> String {
+    let mut moves = pending_moves.lock().await;
+    moves.push((operations, scan_path, cleanup, message_id));
+    // Keep only last 100 pending applies to avoid memory issues
+    if moves.len() > 100 {
+        moves.remove(0);
+    }
+    text
+}
+
+const DEFAULT_RESTRUCTURE_CONFIRM_THRESHOLD: usize = 50;
+
+/// Applies moving more files than this need a second "confirm"
+fn restructure_confirm_threshold() -> usize {
+    env::var("RESTRUCTURE_CONFIRM_THRESHOLD")
+        .ok()
+        .and_then(|val| val.trim().parse::<usize>().ok())
+        .unwrap_or(DEFAULT_RESTRUCTURE_CONFIRM_THRESHOLD)
 }
 
 pub async fn handle_message(
     api: &Api,
     message: &Message,
-    text: Vec<String>,
-    responses: &mut Arc<Mutex<Vec<TelegramJackettResponse>>>,
-    torrent_lists: &mut Arc<Mutex<Vec<(Vec<i64>, String, MessageId)>>>,
-    file_lists: &mut Arc<Mutex<Vec<(Vec<String>, String, MessageId)>>>,
-    restructure_plans: &mut Arc<Mutex<Vec<(crate::restructure::RestructurePlan, String, MessageId)>>>,
+    mut text: Vec<String>,
+    state: &mut BotState,
 ) -> Result<(), ()> {
+    if let Some(first) = text.first_mut() {
+        *first = resolve_command(first);
+    }
+
+    let BotState {
+        responses,
+        torrent_lists,
+        file_lists,
+        restructure_plans,
+        in_flight_adds,
+        confirmations,
+        uncategorized_picks,
+        status_pages,
+        status_snapshots,
+        pending_moves,
+        chat_errors,
+    } = state;
     let chat_id = message.chat.id();
+    let requester = requester_name(message);
+    let admin = is_admin(message);
     let mut result: Result<String, String> = Err("🤷🏻‍I didn't get it!".to_string());
     let mut pending_list: Option<PendingList> = None;
+    // Search results and delete lists also get a button per entry
+    let mut with_buttons = false;
 
     let prefix = text.first().unwrap();
     let suffix = text.last().unwrap();
+    // The arms below take `text`, keep the command for the error log
+    let command = prefix.clone();
 
     if prefix.as_str() == "/chat-id" {
         result = dispatch_chat_id(message.clone()).await;
     }
 
-    if allowed_groups().is_empty() || allowed_groups().contains(&chat_id) {
-        if let Some(reply) = message.reply_to_message.clone() {
+    let groups = allowed_groups();
+    if groups.is_empty() && !is_read_only(message, prefix) {
+        result = Err(
+            "Only /search, /status and /storage work until this chat is added to TELEGRAM_ALLOWED_GROUPS (see /chat-id)"
+                .to_string(),
+        );
+    } else if groups.is_empty() || groups.contains(&chat_id) {
+        let mut page_matched = false;
+        let mut expired = false;
+        // A /cancel reply dismisses the list instead of answering it
+        if let Some(reply) = message.reply_to_message.clone().filter(|_| prefix.as_str() != "/cancel") {
             let num: Option<u16>;
             let mut media: Option<Media> = None;
+            let mut space_check = false;
+            let mut inspect = false;
+            let mut classify = false;
+            let mut set_ratio = false;
 
             match prefix.as_str() {
                 "tv" => {
@@ -455,16 +2856,83 @@ pub async fn handle_message(
                     media = Some(Media::Movie);
                     num = suffix.parse::<u16>().ok();
                 }
+                "anime" => {
+                    media = Some(Media::Anime);
+                    num = suffix.parse::<u16>().ok();
+                }
+                "space" => {
+                    space_check = true;
+                    num = suffix.parse::<u16>().ok();
+                }
+                "inspect" => {
+                    inspect = true;
+                    // inspect tv 3 settles the category up front
+                    media = match text.get(1).map(|word| word.to_lowercase()).as_deref() {
+                        Some("tv") => Some(Media::TV),
+                        Some("movie") => Some(Media::Movie),
+                        Some("anime") => Some(Media::Anime),
+                        _ => None,
+                    };
+                    num = suffix.parse::<u16>().ok();
+                }
+                "classify" => {
+                    classify = true;
+                    num = None;
+                }
+                "/set-ratio" => {
+                    set_ratio = true;
+                    num = text.get(1).and_then(|index| index.parse::<u16>().ok());
+                }
                 _ => {
                     num = prefix.parse::<u16>().ok();
                 }
             }
 
-            // Check restructure plans first — these don't need a numeric index
-            let mut restructure_matched = false;
+            let reply_id = match *reply {
+                telegram_bot::MessageOrChannelPost::Message(ref m) => m.id,
+                telegram_bot::MessageOrChannelPost::ChannelPost(ref cp) => cp.id,
+            };
+            expired = expire_pending_list(reply_id, torrent_lists, file_lists, restructure_plans).await;
+            if expired {
+                result = Err(format!(
+                    "⌛ That list is older than {}, send the command again for a fresh one",
+                    format_age(pending_list_ttl())
+                ));
+            }
+
+            // Check restructure plans first — these don't need a numeric index.
+            // An expired list already has its answer, nothing below may match it
+            let mut restructure_matched = expired;
             {
+                let reply_msg_id = match *reply {
+                    telegram_bot::MessageOrChannelPost::Message(ref m) => m.id,
+                    telegram_bot::MessageOrChannelPost::ChannelPost(ref cp) => cp.id,
+                };
+                let mut moves_guard = pending_moves.lock().await;
+                if let Some(position) = moves_guard
+                    .iter()
+                    .position(|(.., stored_id)| *stored_id == reply_msg_id)
+                {
+                    restructure_matched = true;
+                    result = match prefix.to_lowercase().as_str() {
+                        "confirm" if !admin => Err(ADMINS_ONLY.to_string()),
+                        "confirm" => {
+                            let (operations, scan_path, cleanup, _) = moves_guard.remove(position);
+                            drop(moves_guard);
+                            crate::restructure::execute_moves(&operations, &scan_path, cleanup).await
+                        }
+                        "cancel" => {
+                            moves_guard.remove(position);
+                            Ok("❌ Restructure cancelled".to_string())
+                        }
+                        _ => Err("Reply 'confirm' to move the files or 'cancel'".to_string()),
+                    };
+                }
+            }
+
+            if !restructure_matched {
                 let restructure_guard = restructure_plans.lock().await;
-                for (plan, _list_text, stored_id) in restructure_guard.iter() {
+                for (plan, _list_text, stored_id, _) in restructure_guard.iter() {
                     let reply_msg_id = match *reply {
                         telegram_bot::MessageOrChannelPost::Message(ref m) => m.id,
                         telegram_bot::MessageOrChannelPost::ChannelPost(ref cp) => cp.id,
@@ -476,13 +2944,28 @@ pub async fn handle_message(
                             restructure_matched = true;
                             break;
                         }
+                        if !admin {
+                            result = Err(ADMINS_ONLY.to_string());
+                            restructure_matched = true;
+                            break;
+                        }
 
                         // Parse reply and execute
                         let full_reply = text.join(" ");
                         match crate::restructure::parse_restructure_reply(&full_reply, plan) {
                             Ok(operations) => {
+                                let scan_path = plan.scan_path.clone();
+                                let cleanup = plan.cleanup;
                                 drop(restructure_guard);
-                                result = crate::restructure::execute_moves(&operations).await;
+                                if operations.len() > restructure_confirm_threshold() {
+                                    result = Ok(format!(
+                                        "⚠️ This will move {} files — reply 'confirm' to go ahead or 'cancel'",
+                                        operations.len()
+                                    ));
+                                    pending_list = Some(PendingList::Moves(operations, scan_path, cleanup));
+                                } else {
+                                    result = crate::restructure::execute_moves(&operations, &scan_path, cleanup).await;
+                                }
                                 restructure_matched = true;
                             }
                             Err(e) => {
@@ -495,57 +2978,176 @@ pub async fn handle_message(
                 }
             }
 
+            // Then uncertain picks waiting for a category confirmation
+            let mut confirmation_matched = false;
             if !restructure_matched {
-                if let Some(num) = num {
-                    if let Some(reply_text) = reply.text() {
-                        let mut matched = false;
-
-                        // 1) check FILE lists
-                        {
-                            let file_lists_guard = file_lists.lock().await;
-                            for (file_paths, _list_text, stored_id) in file_lists_guard.iter() {
-                                let reply_msg_id = match *reply {
-                                    telegram_bot::MessageOrChannelPost::Message(ref m) => m.id,
-                                    telegram_bot::MessageOrChannelPost::ChannelPost(ref cp) => cp.id,
-                                };
-                                if reply_msg_id == *stored_id {
-                                    let paths = file_paths.clone();
-                                    drop(file_lists_guard);
-                                    result = dispatch_delete_file(num as usize, paths).await;
-                                    matched = true;
-                                    break;
+                let reply_msg_id = match *reply {
+                    telegram_bot::MessageOrChannelPost::Message(ref m) => m.id,
+                    telegram_bot::MessageOrChannelPost::ChannelPost(ref cp) => cp.id,
+                };
+                let mut confirmations_guard = confirmations.lock().await;
+                if let Some(position) = confirmations_guard
+                    .iter()
+                    .position(|(_, _, stored_id)| *stored_id == reply_msg_id)
+                {
+                    confirmation_matched = true;
+                    let chosen = match prefix.to_lowercase().as_str() {
+                        "tv" => Ok(Some(Media::TV)),
+                        "movie" => Ok(Some(Media::Movie)),
+                        "anime" => Ok(Some(Media::Anime)),
+                        "ok" | "yes" => Ok(Some(confirmations_guard[position].1.clone())),
+                        "cancel" => Ok(None),
+                        _ => Err("Reply with 'ok', 'tv', 'movie', 'anime' or 'cancel'".to_string()),
+                    };
+
+                    result = match chosen {
+                        Ok(chosen) => {
+                            let (location, _, _) = confirmations_guard.remove(position);
+                            drop(confirmations_guard);
+                            match chosen {
+                                Some(media) => {
+                                    add_torrent_abortable(chat_id, in_flight_adds, location, media, &requester).await
                                 }
+                                None => Ok("❌ Add cancelled".to_string()),
                             }
                         }
+                        Err(e) => Err(e),
+                    };
+                }
+            }
+
+            // "next"/"prev" on a file list sends the neighbouring page
+            if !restructure_matched && !confirmation_matched && matches!(prefix.as_str(), "next" | "prev") {
+                let reply_msg_id = match *reply {
+                    telegram_bot::MessageOrChannelPost::Message(ref m) => m.id,
+                    telegram_bot::MessageOrChannelPost::ChannelPost(ref cp) => cp.id,
+                };
+                let file_lists_guard = file_lists.lock().await;
+                let stored = file_lists_guard
+                    .iter()
+                    .find(|(_, _, _, stored_id, _)| *stored_id == reply_msg_id)
+                    .map(|(paths, page, ..)| (paths.clone(), *page));
+                drop(file_lists_guard);
+
+                if let Some((paths, page)) = stored {
+                    page_matched = true;
+                    let pages = (paths.len() + FILE_PAGE_SIZE - 1) / FILE_PAGE_SIZE;
+                    let new_page = if prefix.as_str() == "next" { page + 1 } else { page.wrapping_sub(1) };
+
+                    result = if new_page < pages {
+                        let torrents = torrents_for_file_list().await;
+                        let list = format_file_list(&paths, new_page, &torrents);
+                        pending_list = Some(PendingList::File(paths, new_page));
+                        Ok(list)
+                    } else {
+                        Err("No more pages".to_string())
+                    };
+                }
+            }
+
+            if !restructure_matched && !confirmation_matched && !page_matched {
+                let reply_msg_id = match *reply {
+                    telegram_bot::MessageOrChannelPost::Message(ref m) => m.id,
+                    telegram_bot::MessageOrChannelPost::ChannelPost(ref cp) => cp.id,
+                };
+                // Deletes take several numbers and ranges, e.g. "1 3 5" or "1-4"
+                let selection = text.join(" ");
+                let mut matched = false;
+
+                // 1) check FILE lists
+                if !set_ratio {
+                    let file_lists_guard = file_lists.lock().await;
+                    for (file_paths, _page, _list_text, stored_id, _) in file_lists_guard.iter() {
+                        if reply_msg_id == *stored_id {
+                            let paths = file_paths.clone();
+                            drop(file_lists_guard);
+                            result = if admin {
+                                dispatch_delete_file(&selection, paths).await
+                            } else {
+                                Err(ADMINS_ONLY.to_string())
+                            };
+                            matched = true;
+                            break;
+                        }
+                    }
+                }
 
-                        // 2) if not matched, check TORRENT lists
-                        if !matched {
-                            let lists = torrent_lists.lock().await;
-                            for (torrent_ids, _list_text, stored_id) in lists.iter() {
-                                let reply_msg_id = match *reply {
-                                    telegram_bot::MessageOrChannelPost::Message(ref m) => m.id,
-                                    telegram_bot::MessageOrChannelPost::ChannelPost(ref cp) => cp.id,
-                                };
-                                if reply_msg_id == *stored_id {
-                                    result = dispatch_delete(num as usize, torrent_ids.clone()).await;
-                                    matched = true;
-                                    break;
-                                }
-                            }
+                // 2) if not matched, check TORRENT lists
+                if !matched {
+                    let lists = torrent_lists.lock().await;
+                    for (torrent_ids, action, _list_text, stored_id, _) in lists.iter() {
+                        if reply_msg_id == *stored_id {
+                            let ids = torrent_ids.clone();
+                            let action = *action;
                             drop(lists);
+                            // A qBittorrent list's ids mean nothing to Transmission
+                            let unsupported = if set_ratio || action.transmission_only() {
+                                require_transmission().err()
+                            } else {
+                                None
+                            };
+                            result = match unsupported {
+                                Some(err) => Err(err),
+                                None => match (action, num) {
+                                    (_, Some(num)) if set_ratio => dispatch_seed_ratio(num as usize, &text[1..], ids).await,
+                                    (_, _) if action.admin_only() && !admin => Err(ADMINS_ONLY.to_string()),
+                                    (TorrentListAction::Delete, _) => dispatch_delete(&selection, ids).await,
+                                    (TorrentListAction::Files, Some(num)) => dispatch_torrent_files(num as usize, ids).await,
+                                    (TorrentListAction::Details, Some(num)) => dispatch_torrent_details(num as usize, ids).await,
+                                    (TorrentListAction::Where, Some(num)) => dispatch_where(num as usize, ids).await,
+                                    (TorrentListAction::Retry, Some(num)) => dispatch_retry(num as usize, ids).await,
+                                    (TorrentListAction::Stop, Some(num)) => dispatch_stop(num as usize, ids).await,
+                                    (TorrentListAction::Start, Some(num)) => dispatch_start(num as usize, ids).await,
+                                    (TorrentListAction::Reannounce, Some(num)) => dispatch_reannounce(num as usize, ids).await,
+                                    (TorrentListAction::Peers, Some(num)) => dispatch_peer_limit(num as usize, &text, ids).await,
+                                    (TorrentListAction::Ratio, Some(num)) => dispatch_seed_ratio(num as usize, &text, ids).await,
+                                    (TorrentListAction::Inspect, _) => dispatch_inspect_reply(&prefix, ids).await,
+                                    (_, None) => Err("Not a number, reply with the index".to_string()),
+                                },
+                            };
+                            matched = true;
+                            break;
                         }
+                    }
+                }
 
-                        // 3) If not a delete reply, try Jackett response
-                        if !matched {
+                // 3) If not a delete reply, try Jackett response
+                if !matched && set_ratio {
+                    result = Err("Reply /set-ratio to a torrent list".to_string());
+                } else if !matched && classify {
+                    if let Some(reply_text) = reply.text() {
+                        let torrents = responses.lock().await.clone();
+                        result = classify_results(&reply_text, torrents);
+                    }
+                } else if !matched {
+                    if let Some(num) = num {
+                        if let Some(reply_text) = reply.text() {
                             let r = responses.lock().await;
-                            result = pick_choices(num, reply_text, r.clone(), media).await;
+                            let torrents = r.clone();
+                            drop(r);
+                            result = if space_check {
+                                dispatch_space(num, reply_text, torrents)
+                            } else {
+                                let picked = if inspect {
+                                    dispatch_inspect(num, reply_text, torrents, media, chat_id, in_flight_adds, &requester).await
+                                } else {
+                                    pick_choices(num, reply_text, torrents, media, message, in_flight_adds, uncategorized_picks).await
+                                };
+                                match picked {
+                                    Ok((text, pending)) => {
+                                        pending_list = pending;
+                                        Ok(text)
+                                    }
+                                    Err(e) => Err(e),
+                                }
+                            };
                         }
+                    } else {
+                        result = Err(
+                            "Not a number.\nPossible solutions: (index), movie (index) or tv (index) "
+                                .to_string(),
+                        )
                     }
-                } else {
-                    result = Err(
-                        "Not a number.\nPossible solutions: (index), movie (index) or tv (index) "
-                            .to_string(),
-                    )
                 }
             }
         }
@@ -563,22 +3165,135 @@ pub async fn handle_message(
             }
 
             let response = dispatch_from_imdb_url(url.clone()).await;
-            result = add_response(response, responses).await;
+            result = add_response(response, responses, chat_id).await;
+            with_buttons = true;
         };
 
         result = match prefix.as_str() {
-            "/torrent-tv" => dispatch_tv(text).await,
-            "/torrent-movie" => dispatch_movie(text).await,
+            admin_command if ADMIN_COMMANDS.contains(&admin_command) && !admin => Err(ADMINS_ONLY.to_string()),
+            "/torrent-tv" => dispatch_tv(text, chat_id, in_flight_adds, &requester).await,
+            "/torrent-movie" => dispatch_movie(text, chat_id, in_flight_adds, &requester).await,
+            "/abort" => dispatch_abort(chat_id, in_flight_adds).await,
+            "/cancel" => {
+                dispatch_cancel(
+                    message,
+                    responses,
+                    torrent_lists,
+                    file_lists,
+                    restructure_plans,
+                    pending_moves,
+                    confirmations,
+                )
+                .await
+            }
             "/help" => Ok(HELP.to_string()),
             "/search" => {
-                let response = dispatch_search(text).await;
-                add_response(response, responses).await
+                with_buttons = true;
+                let response = dispatch_search(text, None).await;
+                add_response(response, responses, chat_id).await
             }
-            "/status" => dispatch_status().await,
+            "/search-tv" | "/search-movie" => {
+                with_buttons = true;
+                let media = if prefix == "/search-tv" { Media::TV } else { Media::Movie };
+                let response = dispatch_search(text, Some(media)).await;
+                add_response(response, responses, chat_id).await
+            }
+            "/status" => dispatch_status(text, chat_id, status_pages, status_snapshots).await,
+            "next" if !page_matched && !expired => dispatch_status_next(chat_id, status_pages, status_snapshots).await,
             "/delete-torrent" => {
-                match dispatch_delete_list(None).await {
+                with_buttons = true;
+                match dispatch_torrent_list(None, TorrentListAction::Delete).await {
+                    Ok((text, ids)) => {
+                        pending_list = Some(PendingList::Torrent(ids, TorrentListAction::Delete));
+                        Ok(text)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            "/retry-failed" => {
+                match dispatch_failed_list().await {
+                    Ok((text, ids)) => {
+                        if !ids.is_empty() {
+                            pending_list = Some(PendingList::Torrent(ids, TorrentListAction::Retry));
+                        }
+                        Ok(text)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            "/stalled" => {
+                match dispatch_stalled_list().await {
+                    Ok((text, ids)) => {
+                        if !ids.is_empty() {
+                            pending_list = Some(PendingList::Torrent(ids, TorrentListAction::Retry));
+                        }
+                        Ok(text)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            "/ratio-report" => {
+                match dispatch_ratio_report().await {
+                    Ok((text, ids)) => {
+                        if !ids.is_empty() {
+                            pending_list = Some(PendingList::Torrent(ids, TorrentListAction::Stop));
+                        }
+                        Ok(text)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            "/reannounce" => {
+                match dispatch_torrent_list(None, TorrentListAction::Reannounce).await {
+                    Ok((text, ids)) => {
+                        pending_list = Some(PendingList::Torrent(ids, TorrentListAction::Reannounce));
+                        Ok(text)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            // As a reply it was answered by the torrent list above
+            "/set-ratio" if message.reply_to_message.is_none() => {
+                match dispatch_torrent_list(None, TorrentListAction::Ratio).await {
+                    Ok((text, ids)) => {
+                        pending_list = Some(PendingList::Torrent(ids, TorrentListAction::Ratio));
+                        Ok(text)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            "/peers" => {
+                match dispatch_torrent_list(None, TorrentListAction::Peers).await {
+                    Ok((text, ids)) => {
+                        pending_list = Some(PendingList::Torrent(ids, TorrentListAction::Peers));
+                        Ok(text)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            "/torrent-files" => {
+                match dispatch_torrent_list(None, TorrentListAction::Files).await {
+                    Ok((text, ids)) => {
+                        pending_list = Some(PendingList::Torrent(ids, TorrentListAction::Files));
+                        Ok(text)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            "/where" => match text.get(1) {
+                Some(index) => dispatch_where_index(index).await,
+                None => match dispatch_torrent_list(None, TorrentListAction::Where).await {
                     Ok((text, ids)) => {
-                        pending_list = Some(PendingList::Torrent(ids));
+                        pending_list = Some(PendingList::Torrent(ids, TorrentListAction::Where));
+                        Ok(text)
+                    }
+                    Err(e) => Err(e),
+                },
+            },
+            "/details" => {
+                match dispatch_torrent_list(None, TorrentListAction::Details).await {
+                    Ok((text, ids)) => {
+                        pending_list = Some(PendingList::Torrent(ids, TorrentListAction::Details));
                         Ok(text)
                     }
                     Err(e) => Err(e),
@@ -587,7 +3302,7 @@ pub async fn handle_message(
             "/delete-tv" => {
                 match dispatch_delete_file_list(Media::TV).await {
                     Ok((text, paths)) => {
-                        pending_list = Some(PendingList::File(paths));
+                        pending_list = Some(PendingList::File(paths, 0));
                         Ok(text)
                     }
                     Err(e) => Err(e),
@@ -596,62 +3311,70 @@ pub async fn handle_message(
             "/delete-movie" => {
                 match dispatch_delete_file_list(Media::Movie).await {
                     Ok((text, paths)) => {
-                        pending_list = Some(PendingList::File(paths));
+                        pending_list = Some(PendingList::File(paths, 0));
+                        Ok(text)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            "/delete-anime" => {
+                match dispatch_delete_file_list(Media::Anime).await {
+                    Ok((text, paths)) => {
+                        pending_list = Some(PendingList::File(paths, 0));
                         Ok(text)
                     }
                     Err(e) => Err(e),
                 }
             }
             "/restructure" => {
-                if text.len() < 2 {
-                    Err("Usage: /restructure <tv|movie>".to_string())
+                match dispatch_restructure(text).await {
+                    Ok((text, plan)) => {
+                        pending_list = plan.map(PendingList::Restructure);
+                        Ok(text)
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            "/harvest" => dispatch_harvest(text).await,
+            "/unparseable" => dispatch_unparseable(text).await,
+            "/preview-name" => dispatch_preview_name(text).await,
+            "/stop-seed" => dispatch_stop_seed().await,
+            "/stop" | "/start" => {
+                let action = if prefix.as_str() == "/stop" {
+                    TorrentListAction::Stop
                 } else {
-                    let media = match text[1].to_lowercase().as_str() {
-                        "tv" => Some(Media::TV),
-                        "movie" => Some(Media::Movie),
-                        _ => None,
-                    };
-
-                    match media {
-                        Some(m) => {
-                            let actual_env_var = match m {
-                                Media::TV => "ACTUAL_TV_PATH",
-                                Media::Movie => "ACTUAL_MOVIE_PATH",
-                            };
-                            let transmission_env_var = match m {
-                                Media::TV => "TRANSMISSION_TV_PATH".to_string(),
-                                Media::Movie => "TRANSMISSION_MOVIE_PATH".to_string(),
-                            };
-
-                            let base_path_result = env::var(actual_env_var)
-                                .ok()
-                                .map(Ok)
-                                .unwrap_or_else(|| transmission_path(transmission_env_var));
-
-                            match base_path_result {
-                                Ok(base_path) => {
-                                    match crate::restructure::generate_restructure_plan(m, &base_path).await {
-                                        Ok(plan) => {
-                                            if plan.operations.is_empty() && plan.unparseable_files.is_empty() {
-                                                Ok("✅ Nothing to restructure".to_string())
-                                            } else {
-                                                let text = crate::restructure::format_restructure_plan(&plan);
-                                                pending_list = Some(PendingList::Restructure(plan));
-                                                Ok(text)
-                                            }
-                                        }
-                                        Err(e) => Err(e),
-                                    }
-                                }
-                                Err(e) => Err(e),
-                            }
+                    TorrentListAction::Start
+                };
+                match dispatch_state_list(action).await {
+                    Ok((text, ids)) => {
+                        if !ids.is_empty() {
+                            pending_list = Some(PendingList::Torrent(ids, action));
                         }
-                        None => Err("Invalid media type. Use 'tv' or 'movie'".to_string()),
+                        Ok(text)
                     }
+                    Err(e) => Err(e),
                 }
             }
-            "/stop-seed" => dispatch_stop_seed().await,
+            "/verify-all" => dispatch_verify_all(text).await,
+            "/set-default-dir" => dispatch_set_default_dir(text).await,
             "/storage" => dispatch_storage().await,
+            "/notify" => dispatch_notify(text, chat_id),
+            "/search-format" => dispatch_search_format(text, chat_id),
+            "/watch" => dispatch_watch(text, chat_id),
+            "/watches" => dispatch_watches(chat_id),
+            "/unwatch" => dispatch_unwatch(text, chat_id),
+            "/watch-pause" => dispatch_watch_pause(chat_id, true),
+            "/watch-resume" => dispatch_watch_pause(chat_id, false),
+            "/watch-now" => {
+                with_buttons = true;
+                let response = dispatch_watch_now(text, chat_id).await;
+                add_response(response, responses, chat_id).await
+            }
+            "/version" => Ok(version_info()),
+            "/errors" => dispatch_errors(chat_id, chat_errors).await,
+            "/pending" => {
+                dispatch_pending(responses, torrent_lists, file_lists, restructure_plans, pending_moves, confirmations).await
+            }
             _ => result,
         };
     }
@@ -660,27 +3383,145 @@ pub async fn handle_message(
     match result {
         Ok(text) => {
             if !text.is_empty() {
-                if let Ok(sent_id_opt) = send_message(api, message, text.clone()).await {
-                    if let (Some(sent_id), Some(pending)) = (sent_id_opt, pending_list) {
-                        match pending {
-                            PendingList::Torrent(ids) => {
-                                // store mapping for replies to this message
-                                let _ = add_torrent_list(text, ids, torrent_lists, sent_id).await;
-                            }
-                            PendingList::File(paths) => {
-                                let _ = add_file_list(text, paths, file_lists, sent_id).await;
-                            }
-                            PendingList::Restructure(plan) => {
-                                let _ = add_restructure_plan(text, plan, restructure_plans, sent_id).await;
-                            }
+                let keyboard = if with_buttons { list_keyboard(&text) } else { None };
+                let sent_id = send_message_with_keyboard(api, message, text.clone(), keyboard)
+                    .await
+                    .map_err(|err| println!("{}", err))?;
+
+                if let Some(pending) = pending_list {
+                    match pending {
+                        PendingList::Torrent(ids, action) => {
+                            // store mapping for replies to this message
+                            let _ = add_torrent_list(text, ids, action, torrent_lists, sent_id).await;
+                        }
+                        PendingList::File(paths, page) => {
+                            let _ = add_file_list(text, paths, page, file_lists, sent_id).await;
+                        }
+                        PendingList::Restructure(plan) => {
+                            let _ = add_restructure_plan(text, plan, restructure_plans, sent_id).await;
+                        }
+                        PendingList::Confirmation(location, media) => {
+                            let _ = add_confirmation(text, location, media, confirmations, sent_id).await;
+                        }
+                        PendingList::Moves(operations, scan_path, cleanup) => {
+                            let _ = add_pending_moves(text, operations, scan_path, cleanup, pending_moves, sent_id).await;
                         }
                     }
                 }
             }
         }
         Err(text) => {
-            let _ = send_message(api, message, format!("❌ {}", text.clone())).await?;
+            record_error(chat_id, &command, &text, chat_errors).await;
+            send_message(api, message, format!("❌ {}", text.clone()))
+                .await
+                .map_err(|err| println!("{}", err))?;
         }
     };
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_groups_skip_invalid_entries() {
+        assert_eq!(
+            parse_chat_ids("123,,abc,456"),
+            vec![ChatId::new(123), ChatId::new(456)]
+        );
+        assert_eq!(parse_chat_ids(" -100, 7 "), vec![ChatId::new(-100), ChatId::new(7)]);
+    }
+
+    #[test]
+    fn magnet_lists_take_the_line_prefix_over_the_caption() {
+        let text = "# exported\nmagnet:?xt=urn:btih:aaa\n\ntv magnet:?xt=urn:btih:bbb\nhttp://example.com/x.torrent\nmovie not a magnet\n";
+        let (magnets, skipped) = parse_magnet_list(text, Some(Media::Movie));
+
+        assert_eq!(
+            magnets,
+            vec![
+                (Some(Media::Movie), "magnet:?xt=urn:btih:aaa".to_string()),
+                (Some(Media::TV), "magnet:?xt=urn:btih:bbb".to_string()),
+            ]
+        );
+        assert_eq!(skipped, 2);
+        assert_eq!(parse_magnet_list("magnet:?xt=urn:btih:aaa", None).0[0].0, None);
+    }
+
+    #[test]
+    fn quality_filters_are_left_out_of_the_query() {
+        let text = ["/search", "Dune", "1080P", "-cam", "2021"].iter().map(|w| w.to_string()).collect();
+        let (words, filters) = split_search_filters(text).unwrap();
+
+        assert_eq!(words, vec!["/search", "Dune", "2021"]);
+        assert_eq!(filters.resolution, Some("1080p"));
+        assert!(filters.exclude_cam);
+    }
+
+    #[test]
+    fn seed_ratios_must_be_positive_numbers() {
+        assert_eq!(parse_seed_ratio("1.5"), Ok(1.5));
+        assert_eq!(parse_seed_ratio("2"), Ok(2.0));
+        assert!(parse_seed_ratio("0").is_err());
+        assert!(parse_seed_ratio("-1").is_err());
+        assert!(parse_seed_ratio("inf").is_err());
+        assert_eq!(parse_seed_ratio("x").unwrap_err(), "Seed ratio must be a positive number, not x");
+    }
+
+    fn downloaded(id: i64, downloaded_ever: i64) -> Torrent {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "name": "Show", "status": 4, "percentDone": 0.5, "downloadDir": "/data/tv",
+            "totalSize": 2_000_000_000i64, "downloadedEver": downloaded_ever, "uploadedEver": 0, "uploadRatio": 0.0,
+            "rateDownload": 0, "rateUpload": 0, "eta": -1,
+            "seedRatioLimit": 0.0, "seedIdleLimit": 30,
+            "peersConnected": 0, "peersSendingToUs": 0, "peersGettingFromUs": 0,
+            "error": 0, "errorString": "", "metadataPercentComplete": 1.0
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn status_deltas_cover_what_was_there_before() {
+        use size_format::SizeFormatterSI;
+
+        let mut snapshot = StatusSnapshot::new(&[downloaded(1, 100_000_000), downloaded(2, 500_000_000)]);
+        snapshot.taken = Instant::now() - Duration::from_secs(300);
+
+        assert_eq!(
+            snapshot.delta(&downloaded(1, 700_000_000)),
+            Some(format!(
+                "+{}B in 5m ({}B/s avg)",
+                SizeFormatterSI::new(600_000_000),
+                SizeFormatterSI::new(2_000_000)
+            ))
+        );
+        // Added after the previous /status, nothing to compare with
+        assert_eq!(snapshot.delta(&downloaded(3, 700_000_000)), None);
+
+        // 2 was removed since, the next snapshot forgets it and a torrent
+        // added back under its id starts over without a negative delta
+        assert!(!StatusSnapshot::new(&[downloaded(1, 700_000_000)]).downloaded.contains_key(&2));
+        assert_eq!(snapshot.delta(&downloaded(2, 1_000)), None);
+    }
+
+    #[test]
+    fn pending_sections_show_the_newest_few() {
+        let entries = (1..=5).map(|i| format!("#{}", i)).collect();
+        assert_eq!(
+            pending_section("🗂️ Torrent lists", entries),
+            "🗂️ Torrent lists: 5\n• #5\n• #4\n• #3\n…and 2 more\n"
+        );
+        assert_eq!(pending_section("📂 File lists", vec!["#1".to_string()]), "📂 File lists: 1\n• #1\n");
+        assert_eq!(pending_section("📂 File lists", Vec::new()), "📂 File lists: 0\n");
+    }
+
+    #[test]
+    fn deleting_every_file_needs_a_confirm() {
+        assert_eq!(parse_file_selection("1-3 force", 5), Ok((vec![1, 2, 3], true)));
+        assert!(parse_file_selection("all", 5).unwrap_err().contains("all confirm"));
+        assert!(parse_file_selection("ALL force", 5).is_err());
+        assert_eq!(parse_file_selection("all confirm", 3), Ok((vec![1, 2, 3], false)));
+        assert_eq!(parse_file_selection("all confirm force", 2), Ok((vec![1, 2], true)));
+    }
+}
@@ -0,0 +1,133 @@
+use std::env;
+
+use crate::jackett::TorrentLocation;
+use crate::transmission::{Media, Torrent};
+
+/// Torrent client the bot adds to, lists and removes downloads from
+pub(crate) trait TorrentClient {
+    /// Add the torrent to the folder of its media and return that folder
+    async fn add_torrent(&self, location: TorrentLocation, media: Media, requester: String) -> Result<String, String>;
+
+    async fn get_torrents(&self) -> Result<Vec<Torrent>, String>;
+
+    /// Remove the torrents, keeping their data
+    async fn delete_torrent(&self, ids: Vec<i64>) -> Result<(), String>;
+
+    async fn stop_seeding_all(&self) -> Result<(), String>;
+
+    /// Seed ratio limit of torrents without their own, None when it's off
+    async fn session_ratio_limit(&self) -> Result<Option<f64>, String>;
+}
+
+pub struct Transmission;
+
+impl TorrentClient for Transmission {
+    async fn add_torrent(&self, location: TorrentLocation, media: Media, requester: String) -> Result<String, String> {
+        crate::transmission::add_torrent(location, media, requester).await
+    }
+
+    async fn get_torrents(&self) -> Result<Vec<Torrent>, String> {
+        crate::transmission::get_torrents().await
+    }
+
+    async fn delete_torrent(&self, ids: Vec<i64>) -> Result<(), String> {
+        crate::transmission::delete_torrent(ids).await
+    }
+
+    async fn stop_seeding_all(&self) -> Result<(), String> {
+        crate::transmission::stop_seeding_all().await
+    }
+
+    async fn session_ratio_limit(&self) -> Result<Option<f64>, String> {
+        crate::transmission::session_ratio_limit().await
+    }
+}
+
+pub struct QBittorrent;
+
+impl TorrentClient for QBittorrent {
+    async fn add_torrent(&self, location: TorrentLocation, media: Media, requester: String) -> Result<String, String> {
+        crate::qbittorrent::add_torrent(location, media, requester).await
+    }
+
+    async fn get_torrents(&self) -> Result<Vec<Torrent>, String> {
+        crate::qbittorrent::get_torrents().await
+    }
+
+    async fn delete_torrent(&self, ids: Vec<i64>) -> Result<(), String> {
+        crate::qbittorrent::delete_torrent(ids).await
+    }
+
+    async fn stop_seeding_all(&self) -> Result<(), String> {
+        crate::qbittorrent::stop_seeding_all().await
+    }
+
+    async fn session_ratio_limit(&self) -> Result<Option<f64>, String> {
+        crate::qbittorrent::session_ratio_limit().await
+    }
+}
+
+/// Client picked by TORRENT_BACKEND
+pub enum TorrentBackend {
+    Transmission(Transmission),
+    QBittorrent(QBittorrent),
+}
+
+impl TorrentClient for TorrentBackend {
+    async fn add_torrent(&self, location: TorrentLocation, media: Media, requester: String) -> Result<String, String> {
+        match self {
+            TorrentBackend::Transmission(transmission) => transmission.add_torrent(location, media, requester).await,
+            TorrentBackend::QBittorrent(qbittorrent) => qbittorrent.add_torrent(location, media, requester).await,
+        }
+    }
+
+    async fn get_torrents(&self) -> Result<Vec<Torrent>, String> {
+        match self {
+            TorrentBackend::Transmission(transmission) => transmission.get_torrents().await,
+            TorrentBackend::QBittorrent(qbittorrent) => qbittorrent.get_torrents().await,
+        }
+    }
+
+    async fn delete_torrent(&self, ids: Vec<i64>) -> Result<(), String> {
+        match self {
+            TorrentBackend::Transmission(transmission) => transmission.delete_torrent(ids).await,
+            TorrentBackend::QBittorrent(qbittorrent) => qbittorrent.delete_torrent(ids).await,
+        }
+    }
+
+    async fn stop_seeding_all(&self) -> Result<(), String> {
+        match self {
+            TorrentBackend::Transmission(transmission) => transmission.stop_seeding_all().await,
+            TorrentBackend::QBittorrent(qbittorrent) => qbittorrent.stop_seeding_all().await,
+        }
+    }
+
+    async fn session_ratio_limit(&self) -> Result<Option<f64>, String> {
+        match self {
+            TorrentBackend::Transmission(transmission) => transmission.session_ratio_limit().await,
+            TorrentBackend::QBittorrent(qbittorrent) => qbittorrent.session_ratio_limit().await,
+        }
+    }
+}
+
+/// Commands outside `TorrentClient` talk to Transmission directly, the ids
+/// of a qBittorrent list would point them at the wrong torrents
+pub fn require_transmission() -> Result<(), String> {
+    match torrent_backend()? {
+        TorrentBackend::Transmission(_) => Ok(()),
+        TorrentBackend::QBittorrent(_) => {
+            Err("This command only works with Transmission, TORRENT_BACKEND is qbittorrent".to_string())
+        }
+    }
+}
+
+/// TORRENT_BACKEND is `transmission` (default) or `qbittorrent`
+pub fn torrent_backend() -> Result<TorrentBackend, String> {
+    let backend = env::var("TORRENT_BACKEND").unwrap_or_default();
+
+    match backend.trim().to_lowercase().as_str() {
+        "" | "transmission" => Ok(TorrentBackend::Transmission(Transmission)),
+        "qbittorrent" => Ok(TorrentBackend::QBittorrent(QBittorrent)),
+        other => Err(format!("Unknown TORRENT_BACKEND {}, use transmission or qbittorrent", other)),
+    }
+}
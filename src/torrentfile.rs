@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+
+use url::form_urlencoded;
+
+use crate::jackett::TorrentLocation;
+use crate::restructure::{episode_marker, VIDEO_EXTENSIONS};
+use crate::transmission::Media;
+
+// Nesting deeper than this is not something a real .torrent needs
+const MAX_DEPTH: usize = 64;
+
+/// A decoded bencode value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bencode {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    Dict(BTreeMap<Vec<u8>, Bencode>),
+}
+
+impl Bencode {
+    pub fn get(&self, key: &str) -> Option<&Bencode> {
+        match self {
+            Bencode::Dict(dict) => dict.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Bencode::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_string(&self) -> Option<String> {
+        match self {
+            Bencode::Bytes(bytes) => Some(String::from_utf8_lossy(bytes).to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Bencode]> {
+        match self {
+            Bencode::List(list) => Some(list),
+            _ => None,
+        }
+    }
+}
+
+fn read_until(bytes: &[u8], pos: &mut usize, delimiter: u8) -> Result<String, String> {
+    let start = *pos;
+    let end = bytes[start..]
+        .iter()
+        .position(|b| *b == delimiter)
+        .map(|offset| start + offset)
+        .ok_or("Unterminated bencode value")?;
+    *pos = end + 1;
+
+    String::from_utf8(bytes[start..end].to_vec()).map_err(|_| "Invalid bencode number".to_string())
+}
+
+fn decode_value(bytes: &[u8], pos: &mut usize, depth: usize) -> Result<Bencode, String> {
+    if depth > MAX_DEPTH {
+        return Err("Bencode nesting is too deep".to_string());
+    }
+
+    match bytes.get(*pos) {
+        Some(b'i') => {
+            *pos += 1;
+            let number = read_until(bytes, pos, b'e')?;
+            number
+                .parse::<i64>()
+                .map(Bencode::Int)
+                .map_err(|_| format!("Invalid bencode integer: {}", number))
+        }
+        Some(b'l') => {
+            *pos += 1;
+            let mut list = Vec::new();
+            while bytes.get(*pos) != Some(&b'e') {
+                if *pos >= bytes.len() {
+                    return Err("Unterminated bencode list".to_string());
+                }
+                list.push(decode_value(bytes, pos, depth + 1)?);
+            }
+            *pos += 1;
+            Ok(Bencode::List(list))
+        }
+        Some(b'd') => {
+            *pos += 1;
+            let mut dict = BTreeMap::new();
+            while bytes.get(*pos) != Some(&b'e') {
+                if *pos >= bytes.len() {
+                    return Err("Unterminated bencode dictionary".to_string());
+                }
+                let key = match decode_value(bytes, pos, depth + 1)? {
+                    Bencode::Bytes(key) => key,
+                    _ => return Err("Bencode dictionary key is not a string".to_string()),
+                };
+                let value = decode_value(bytes, pos, depth + 1)?;
+                dict.insert(key, value);
+            }
+            *pos += 1;
+            Ok(Bencode::Dict(dict))
+        }
+        Some(b'0'..=b'9') => {
+            let length = read_until(bytes, pos, b':')?;
+            let length = length
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid bencode string length: {}", length))?;
+            let end = pos
+                .checked_add(length)
+                .filter(|end| *end <= bytes.len())
+                .ok_or("Bencode string exceeds the input")?;
+            let value = bytes[*pos..end].to_vec();
+            *pos = end;
+            Ok(Bencode::Bytes(value))
+        }
+        Some(other) => Err(format!("Unexpected bencode byte: {}", *other as char)),
+        None => Err("Unexpected end of bencode input".to_string()),
+    }
+}
+
+/// Decode a complete bencoded document
+pub fn decode_bencode(bytes: &[u8]) -> Result<Bencode, String> {
+    let mut pos = 0;
+    decode_value(bytes, &mut pos, 0)
+}
+
+/// Extract the torrent name and total size in bytes from .torrent metainfo
+pub fn parse_torrent_metadata(bytes: &[u8]) -> Result<(String, u64), String> {
+    let metainfo = decode_bencode(bytes)?;
+    let info = metainfo.get("info").ok_or("Torrent file has no info dictionary")?;
+
+    let name = info
+        .get("name")
+        .and_then(|name| name.as_string())
+        .ok_or("Torrent file has no name")?;
+
+    // Single-file torrents carry `length`, multi-file ones a `files` list
+    let size = match info.get("length").and_then(|length| length.as_int()) {
+        Some(length) => length.max(0) as u64,
+        None => info
+            .get("files")
+            .and_then(|files| files.as_list())
+            .ok_or("Torrent file has neither length nor files")?
+            .iter()
+            .filter_map(|file| file.get("length").and_then(|length| length.as_int()))
+            .map(|length| length.max(0) as u64)
+            .sum(),
+    };
+
+    Ok((name, size))
+}
+
+/// Read the display name (`dn`) and exact length (`xl`) hints of a magnet link
+pub fn parse_magnet_metadata(magnet: &str) -> Result<(Option<String>, Option<u64>), String> {
+    let query = magnet
+        .strip_prefix("magnet:?")
+        .ok_or("Not a magnet link")?;
+
+    let mut has_info_hash = false;
+    let mut name = None;
+    let mut size = None;
+
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        match key.as_ref() {
+            "xt" if value.starts_with("urn:btih:") || value.starts_with("urn:btmh:") => {
+                has_info_hash = true
+            }
+            "dn" => name = Some(value.to_string()),
+            "xl" => size = value.parse::<u64>().ok(),
+            _ => {}
+        }
+    }
+
+    if !has_info_hash {
+        return Err("Magnet link has no info hash".to_string());
+    }
+
+    Ok((name, size))
+}
+
+/// Name and total size of a magnet (`dn` and `xl`) or base64 encoded .torrent, when known
+pub fn location_metadata(location: &TorrentLocation) -> (Option<String>, Option<u64>) {
+    if location.is_magnet {
+        parse_magnet_metadata(&location.content).unwrap_or_default()
+    } else {
+        base64::decode(&location.content)
+            .ok()
+            .and_then(|bytes| parse_torrent_metadata(&bytes).ok())
+            .map_or((None, None), |(name, size)| (Some(name), Some(size)))
+    }
+}
+
+/// Display name of a magnet (`dn`) or base64 encoded .torrent, when it has one
+pub fn location_name(location: &TorrentLocation) -> Option<String> {
+    location_metadata(location).0
+}
+
+/// Names of all files inside the torrent (just `info.name` for single-file torrents)
+pub fn torrent_file_names(bytes: &[u8]) -> Result<Vec<String>, String> {
+    let metainfo = decode_bencode(bytes)?;
+    let info = metainfo.get("info").ok_or("Torrent file has no info dictionary")?;
+
+    match info.get("files").and_then(|files| files.as_list()) {
+        Some(files) => Ok(files
+            .iter()
+            .filter_map(|file| file.get("path").and_then(|path| path.as_list()))
+            .map(|path| {
+                path.iter()
+                    .filter_map(|part| part.as_string())
+                    .collect::<Vec<String>>()
+                    .join("/")
+            })
+            .collect()),
+        None => info
+            .get("name")
+            .and_then(|name| name.as_string())
+            .map(|name| vec![name])
+            .ok_or_else(|| "Torrent file has no name".to_string()),
+    }
+}
+
+fn is_video_file(name: &str) -> bool {
+    let name = name.to_lowercase();
+    VIDEO_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
+
+/// Best-effort guess of the media type from the files a torrent contains
+///
+/// Episode-numbered videos mean TV, a single un-numbered video means a movie.
+/// Anything else is left for the user to decide.
+pub fn guess_media_from_files(names: &[String]) -> Option<Media> {
+    let videos: Vec<&String> = names.iter().filter(|name| is_video_file(name)).collect();
+
+    if videos.iter().any(|name| episode_marker(name).is_some()) {
+        Some(Media::TV)
+    } else if videos.len() == 1 {
+        Some(Media::Movie)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SINGLE_FILE: &[u8] = b"d8:announce15:http://tracker/4:infod6:lengthi1000e4:name9:movie.mkvee";
+    const MULTI_FILE: &[u8] =
+        b"d4:infod5:filesld6:lengthi700e4:pathl3:S015:a.mkveed6:lengthi300e4:pathl3:S015:b.srteee4:name4:Showee";
+
+    #[test]
+    fn nested_dicts_and_lists_decode() {
+        let value = decode_bencode(b"d1:ad1:bli1ei-2e3:xyzee1:c0:e").unwrap();
+
+        let list = value.get("a").and_then(|a| a.get("b")).and_then(|b| b.as_list()).unwrap();
+        assert_eq!(list[0].as_int(), Some(1));
+        assert_eq!(list[1].as_int(), Some(-2));
+        assert_eq!(list[2].as_string().as_deref(), Some("xyz"));
+        assert_eq!(value.get("c").and_then(|c| c.as_string()).as_deref(), Some(""));
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        for input in [&b""[..], b"i42", b"l1:a", b"d4:info", b"d4:infoi1e", b"4:ab", b"12"].iter() {
+            assert!(decode_bencode(input).is_err(), "{:?}", String::from_utf8_lossy(input));
+        }
+    }
+
+    #[test]
+    fn bad_lengths_and_integers_are_errors() {
+        assert_eq!(decode_bencode(b"-3:abc").unwrap_err(), "Unexpected bencode byte: -");
+        assert_eq!(decode_bencode(b"l-3:abce").unwrap_err(), "Unexpected bencode byte: -");
+        assert_eq!(
+            decode_bencode(b"99999999999999999999:a").unwrap_err(),
+            "Invalid bencode string length: 99999999999999999999"
+        );
+        // Fits a usize, but adding the position overflows
+        assert_eq!(
+            decode_bencode(format!("{}:a", usize::MAX).as_bytes()).unwrap_err(),
+            "Bencode string exceeds the input"
+        );
+        assert_eq!(
+            decode_bencode(b"i99999999999999999999e").unwrap_err(),
+            "Invalid bencode integer: 99999999999999999999"
+        );
+        assert!(decode_bencode(b"di1e1:ae").is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_refused() {
+        let input = format!("{}{}", "l".repeat(MAX_DEPTH + 2), "e".repeat(MAX_DEPTH + 2));
+        assert_eq!(decode_bencode(input.as_bytes()).unwrap_err(), "Bencode nesting is too deep");
+    }
+
+    #[test]
+    fn single_file_torrents_use_their_length_and_name() {
+        assert_eq!(parse_torrent_metadata(SINGLE_FILE).unwrap(), ("movie.mkv".to_string(), 1000));
+        assert_eq!(torrent_file_names(SINGLE_FILE).unwrap(), vec!["movie.mkv"]);
+    }
+
+    #[test]
+    fn multi_file_torrents_add_up_their_files() {
+        assert_eq!(parse_torrent_metadata(MULTI_FILE).unwrap(), ("Show".to_string(), 1000));
+        assert_eq!(torrent_file_names(MULTI_FILE).unwrap(), vec!["S01/a.mkv", "S01/b.srt"]);
+
+        assert_eq!(
+            parse_torrent_metadata(b"d4:infod4:name4:Showee").unwrap_err(),
+            "Torrent file has neither length nor files"
+        );
+        assert_eq!(parse_torrent_metadata(b"de").unwrap_err(), "Torrent file has no info dictionary");
+    }
+
+    #[test]
+    fn magnet_hints_are_optional_but_the_info_hash_is_not() {
+        assert_eq!(
+            parse_magnet_metadata("magnet:?xt=urn:btih:abc&dn=The+Matrix&xl=1000").unwrap(),
+            (Some("The Matrix".to_string()), Some(1000))
+        );
+        assert_eq!(parse_magnet_metadata("magnet:?xt=urn:btih:abc").unwrap(), (None, None));
+        assert_eq!(parse_magnet_metadata("magnet:?xt=urn:btih:abc&xl=big").unwrap(), (None, None));
+        assert_eq!(
+            parse_magnet_metadata("magnet:?dn=The+Matrix&xl=1000").unwrap_err(),
+            "Magnet link has no info hash"
+        );
+        assert_eq!(parse_magnet_metadata("magnet:?xt=urn:sha1:abc").unwrap_err(), "Magnet link has no info hash");
+        assert_eq!(parse_magnet_metadata("http://example.com/x.torrent").unwrap_err(), "Not a magnet link");
+    }
+
+    #[test]
+    fn locations_read_magnets_and_encoded_torrent_files() {
+        let torrent = TorrentLocation {
+            content: base64::encode(MULTI_FILE),
+            is_magnet: false,
+        };
+        assert_eq!(location_metadata(&torrent), (Some("Show".to_string()), Some(1000)));
+
+        let magnet = TorrentLocation {
+            content: "magnet:?xt=urn:btih:abc&dn=Show".to_string(),
+            is_magnet: true,
+        };
+        assert_eq!(location_name(&magnet).as_deref(), Some("Show"));
+
+        let broken = TorrentLocation {
+            content: "not base64!".to_string(),
+            is_magnet: false,
+        };
+        assert_eq!(location_metadata(&broken), (None, None));
+    }
+
+    #[test]
+    fn media_guess_from_file_names() {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(guess_media_from_files(&names(&["Show/Show.S01E01.mkv", "Show/Show.S01E02.mkv"])), Some(Media::TV));
+        assert_eq!(guess_media_from_files(&names(&["Movie.2010.1080p.ts", "Movie.nfo"])), Some(Media::Movie));
+        assert_eq!(guess_media_from_files(&names(&["a.mkv", "b.mkv"])), None);
+        assert_eq!(guess_media_from_files(&names(&["Album/01.flac"])), None);
+    }
+}
@@ -3,15 +3,15 @@ use hyper::{client, Body, Request, Response};
 use serde::Deserialize;
 use serde_json::json;
 use std::env;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
 
 use crate::jackett::TorrentLocation;
 
-fn transmission_path(env: String) -> Result<String, String> {
-    env::var(env).map_err(|_| {
-        "TRANSMISSION_TV_PATH or TRANSMISSION_MOVIE_PATH env var is not set".to_string()
-    })
-}
-
 fn transmission_url() -> String {
     env::var("TRANSMISSION_URL").map_or("http://localhost:9091".to_string(), |url| url)
 }
@@ -20,6 +20,40 @@ fn transmission_url() -> String {
 pub enum Media {
     TV,
     Movie,
+    Anime,
+}
+
+impl Media {
+    /// Env var with the Transmission download dir of this media
+    pub fn path_env(&self) -> &'static str {
+        match self {
+            Media::TV => "TRANSMISSION_TV_PATH",
+            Media::Movie => "TRANSMISSION_MOVIE_PATH",
+            Media::Anime => "TRANSMISSION_ANIME_PATH",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Media::TV => "📺 TV",
+            Media::Movie => "🎬 Movie",
+            Media::Anime => "🍥 Anime",
+        }
+    }
+
+    /// Anime is handled as TV when TRANSMISSION_ANIME_PATH is not set
+    pub fn resolve(self) -> Media {
+        match self {
+            Media::Anime if env::var(Media::Anime.path_env()).is_err() => Media::TV,
+            media => media,
+        }
+    }
+}
+
+/// Transmission download dir for the media
+pub fn media_path(media: &Media) -> Result<String, String> {
+    let media = media.clone().resolve();
+    env::var(media.path_env()).map_err(|_| format!("{} env var is not set", media.path_env()))
 }
 
 #[derive(Debug, Deserialize)]
@@ -43,12 +77,129 @@ pub struct Torrent {
     pub downloaded_ever: i64,
     #[serde(rename = "uploadedEver")]
     pub uploaded_ever: i64,
-    #[allow(dead_code)]
+    // Negative when Transmission can't compute it (nothing downloaded yet)
+    #[serde(rename = "uploadRatio")]
+    pub upload_ratio: f64,
+    #[serde(rename = "rateDownload")]
+    pub rate_download: i64,
+    #[serde(rename = "rateUpload")]
+    pub rate_upload: i64,
+    // Seconds left, negative when Transmission can't tell
+    pub eta: i64,
     #[serde(rename = "seedRatioLimit")]
     pub seed_ratio_limit: f64,
+    // 0 follows the session limit, 1 uses seedRatioLimit, 2 seeds forever
+    #[serde(rename = "seedRatioMode", default)]
+    pub seed_ratio_mode: i64,
     #[allow(dead_code)]
     #[serde(rename = "seedIdleLimit")]
     pub seed_idle_limit: i64,
+    #[serde(rename = "peersConnected")]
+    pub peers_connected: i64,
+    #[serde(rename = "peersSendingToUs")]
+    pub peers_sending_to_us: i64,
+    #[serde(rename = "peersGettingFromUs")]
+    pub peers_getting_from_us: i64,
+    #[serde(default)]
+    pub trackers: Vec<serde_json::Value>,
+    pub error: i64,
+    #[serde(rename = "errorString")]
+    pub error_string: String,
+    #[serde(rename = "metadataPercentComplete")]
+    pub metadata_percent_complete: f64,
+    // Missing on Transmission versions without label support
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+const ADDED_BY_LABEL: &str = "added-by:";
+
+/// Transmission label recording who added a torrent
+pub fn added_by_label(requester: &str) -> String {
+    format!("{}{}", ADDED_BY_LABEL, requester)
+}
+
+impl Torrent {
+    /// Requester stored by `added_by_label`, if any
+    pub fn added_by(&self) -> Option<&str> {
+        self.labels.iter().find_map(|label| label.strip_prefix(ADDED_BY_LABEL))
+    }
+
+    /// Ratio the torrent seeds to: its own limit, `session` (the client's
+    /// limit, None when that's off) or none when it seeds forever
+    pub fn ratio_limit(&self, session: Option<f64>) -> Option<f64> {
+        match self.seed_ratio_mode {
+            1 => Some(self.seed_ratio_limit).filter(|limit| *limit > 0.0),
+            2 => None,
+            _ => session,
+        }
+    }
+
+    /// Uploaded at least its seed ratio limit
+    pub fn met_ratio_limit(&self, session: Option<f64>) -> bool {
+        self.ratio_limit(session).is_some_and(|limit| self.upload_ratio >= limit)
+    }
+
+    /// Downloading but not receiving anything
+    pub fn is_stalled(&self) -> bool {
+        self.status == 4 && self.rate_download == 0 && self.percent_done < 1.0
+    }
+
+    /// Stalled with nobody to download from, it will most likely never finish
+    pub fn is_dead(&self) -> bool {
+        self.is_stalled() && self.peers_connected == 0
+    }
+
+    /// Errored before the metadata ever arrived, so it will never start on its own
+    pub fn failed_to_start(&self) -> bool {
+        self.error != 0 && self.metadata_percent_complete < 1.0
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TorrentFile {
+    pub name: String,
+    pub length: i64,
+    #[serde(rename = "bytesCompleted")]
+    pub bytes_completed: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TorrentFileStats {
+    pub wanted: bool,
+    pub priority: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TorrentFiles {
+    pub name: String,
+    pub files: Vec<TorrentFile>,
+    #[serde(rename = "fileStats")]
+    pub file_stats: Vec<TorrentFileStats>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TrackerStat {
+    pub host: String,
+    #[serde(rename = "lastAnnounceSucceeded")]
+    pub last_announce_succeeded: bool,
+    #[serde(rename = "lastAnnounceResult")]
+    pub last_announce_result: String,
+    #[serde(rename = "seederCount")]
+    pub seeder_count: i64,
+    #[serde(rename = "leecherCount")]
+    pub leecher_count: i64,
+}
+
+/// Files, tracker stats and error of a single torrent, for /details
+#[derive(Debug, Deserialize, Clone)]
+pub struct TorrentDetails {
+    #[serde(flatten)]
+    pub files: TorrentFiles,
+    #[serde(rename = "trackerStats", default)]
+    pub tracker_stats: Vec<TrackerStat>,
+    #[serde(rename = "errorString", default)]
+    pub error_string: String,
 }
 
 fn transmission_credentials() -> Option<String> {
@@ -90,48 +241,130 @@ async fn request_transmission_rpc(
 }
 
 
+static SESSION_ID: OnceLock<Mutex<Option<String>>> = OnceLock::new();
+
+// Last X-Transmission-Session-Id, sent up front so only the first request
+// and the ones after Transmission rotates it need the 409 round trip
+fn session_id() -> &'static Mutex<Option<String>> {
+    SESSION_ID.get_or_init(|| Mutex::new(None))
+}
+
 async fn request_transmission_with_retry(
     client: &client::Client<hyper_rustls::HttpsConnector<client::HttpConnector>>,
     method: &str,
     arguments: serde_json::Value,
 ) -> Result<Response<Body>, String> {
-    let transmission_response =
-        request_transmission_rpc(client, method, arguments.clone(), None).await;
-
-    if transmission_response.is_err() {
-        return Err("Transmission replied with error".to_string());
-    }
+    let cached = session_id().lock().unwrap().clone();
+    let response = request_transmission_rpc(client, method, arguments.clone(), cached)
+        .await
+        .map_err(|_| "Transmission replied with error".to_string())?;
 
-    let response = transmission_response.unwrap();
-    if response.status() == 409 {
-        let headers = response.headers();
-        let header_value = headers.get("X-Transmission-Session-Id");
-        if header_value.is_none() {
-            return Err("First request to transmission didn't bring the token".to_string());
-        }
+    // No session id yet, or the cached one went stale
+    let response = if response.status() == 409 {
+        let session_value = response
+            .headers()
+            .get("X-Transmission-Session-Id")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        *session_id().lock().unwrap() = session_value.clone();
 
-        let session_value = header_value.unwrap().to_str().unwrap().to_string();
-        let retry_response = request_transmission_rpc(client, method, arguments, Some(session_value))
+        let session_value = session_value.ok_or("First request to transmission didn't bring the token")?;
+        request_transmission_rpc(client, method, arguments, Some(session_value))
             .await
-            .map_err(|e| format!("Error on retry: {:?}", e))?;
+            .map_err(|e| format!("Error on retry: {:?}", e))?
+    } else {
+        response
+    };
 
-        if retry_response.status().is_success() {
-            Ok(retry_response)
-        } else {
-            Err(format!("Error on transmission {}", retry_response.status()))
-        }
-    } else if response.status().is_success() {
+    if response.status().is_success() {
         Ok(response)
     } else {
         Err(format!("Error on transmission {}", response.status()))
     }
 }
 
+type WriteJob = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+static WRITE_QUEUE: OnceLock<mpsc::UnboundedSender<WriteJob>> = OnceLock::new();
+
+fn write_interval() -> Duration {
+    env::var("TRANSMISSION_WRITE_INTERVAL_MS")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+        .map(Duration::from_millis)
+        .unwrap_or_default()
+}
+
+/// Single worker that runs mutating RPCs one at a time, in the order they were queued
+fn write_queue() -> &'static mpsc::UnboundedSender<WriteJob> {
+    WRITE_QUEUE.get_or_init(|| {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<WriteJob>();
+        let interval = write_interval();
+
+        tokio::spawn(async move {
+            while let Some(job) = receiver.recv().await {
+                job.await;
+                if !interval.is_zero() {
+                    tokio::time::sleep(interval).await;
+                }
+            }
+        });
+
+        sender
+    })
+}
+
+/// Queue a write on the worker and wait for its result
+///
+/// If the caller stops waiting (e.g. `/abort`), the write is dropped as well.
+/// Queued operations must not queue further writes themselves.
+async fn serialized<F, T>(operation: F) -> Result<T, String>
+where
+    F: Future<Output = Result<T, String>> + Send + 'static,
+    T: Send + 'static,
+{
+    let (mut sender, receiver) = oneshot::channel();
+
+    let job: WriteJob = Box::pin(async move {
+        let result = tokio::select! {
+            result = operation => Some(result),
+            _ = sender.closed() => None,
+        };
+
+        if let Some(result) = result {
+            let _ = sender.send(result);
+        }
+    });
+
+    write_queue()
+        .send(job)
+        .map_err(|_| "Transmission write queue is closed".to_string())?;
+
+    receiver
+        .await
+        .map_err(|_| "Transmission write was dropped".to_string())?
+}
+
+/// Plain mutating RPC whose response body doesn't matter
+async fn request_torrent_write(method: &'static str, arguments: serde_json::Value) -> Result<(), String> {
+    let https = hyper_rustls::HttpsConnector::with_native_roots();
+    let client: client::Client<_> = client::Client::builder().build(https);
+
+    request_transmission_with_retry(&client, method, arguments).await?;
+    Ok(())
+}
+
 fn is_debug_enabled() -> bool {
     env::var("DEBUG_TORRENT").is_ok()
 }
 
-async fn request_add_torrent(location: TorrentLocation, path: String) -> Result<(), String> {
+/// Add a torrent, returning its id when Transmission reports it
+async fn request_add_torrent(
+    location: TorrentLocation,
+    path: String,
+    labels: Vec<String>,
+    paused: bool,
+) -> Result<Option<i64>, String> {
     let https = hyper_rustls::HttpsConnector::with_native_roots();
     let client: client::Client<_> = client::Client::builder().build(https);
 
@@ -139,7 +372,7 @@ async fn request_add_torrent(location: TorrentLocation, path: String) -> Result<
         println!("[DEBUG] Adding torrent - is_magnet: {}, content_length: {}", location.is_magnet, location.content.len());
     }
 
-    let arguments = if location.is_magnet {
+    let mut arguments = if location.is_magnet {
         if location.content.len() < 10 || !location.content.starts_with("magnet:") {
             return Err(format!("Invalid magnet link: {}", location.content.chars().take(50).collect::<String>()));
         }
@@ -159,123 +392,253 @@ async fn request_add_torrent(location: TorrentLocation, path: String) -> Result<
             "metainfo": location.content,
         })
     };
+
+    // Transmission versions without labels ignore the unknown argument
+    if !labels.is_empty() {
+        arguments["labels"] = json!(labels);
+    }
+    if paused {
+        arguments["paused"] = json!(true);
+    }
     
     if is_debug_enabled() {
         println!("[DEBUG] Request arguments: download-dir={}, has_content={}", path, !location.content.is_empty());
     }
 
-    let transmission_response = request_transmission_rpc(&client, "torrent-add", arguments.clone(), None).await;
+    let response = request_transmission_with_retry(&client, "torrent-add", arguments).await?;
 
-    if transmission_response.is_err() {
-        return Err("Transmission replied with error".to_string());
+    // Read response body to check what Transmission actually said
+    let body_bytes = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| format!("Failed to read response body: {}", e))?;
+
+    let response_text = String::from_utf8_lossy(&body_bytes);
+    if is_debug_enabled() {
+        println!("[DEBUG] Transmission response: {}", response_text);
     }
 
-    let response = transmission_response.unwrap();
-    if response.status() == 409 {
-        let headers = response.headers();
-        let header_value = headers.get("X-Transmission-Session-Id");
-        if header_value.is_none() {
-            return Err("First request to transmission didn't bring the token".to_string());
-        }
+    // Parse to check for errors
+    let transmission_response: Result<TransmissionResponse, _> = serde_json::from_slice(&body_bytes);
+    let mut added_id = None;
 
-        let session_value = header_value.unwrap().to_str().unwrap().to_string();
-        let retry_response = request_transmission_rpc(&client, "torrent-add", arguments, Some(session_value))
-            .await;
-        
-        if retry_response.is_err() {
-            return Err("Transmission retry failed".to_string());
+    if let Ok(trans_resp) = transmission_response {
+        if trans_resp.result != "success" {
+            return Err(format!("Transmission error: {}", trans_resp.result));
         }
-        
-        let final_response = retry_response.unwrap();
-        
-        // Read response body to check what Transmission actually said
-        let body_bytes = hyper::body::to_bytes(final_response.into_body())
-            .await
-            .map_err(|e| format!("Failed to read response body: {}", e))?;
-        
-        let response_text = String::from_utf8_lossy(&body_bytes);
-        if is_debug_enabled() {
-            println!("[DEBUG] Transmission response: {}", response_text);
-        }
-        
-        // Parse to check for errors
-        let transmission_response: Result<TransmissionResponse, _> = serde_json::from_slice(&body_bytes);
-        
-        if let Ok(trans_resp) = transmission_response {
-            if trans_resp.result != "success" {
-                return Err(format!("Transmission error: {}", trans_resp.result));
-            }
-            
-            // Check for torrent-add specific errors
-            if let Some(args) = trans_resp.arguments {
-                if let Some(error) = args.get("torrent-duplicate") {
-                    if error != &serde_json::json!(null) {
-                        return Err("Torrent already exists in Transmission".to_string());
-                    }
-                }
-                // Check for any error messages
-                if let Some(result_code) = args.get("result").and_then(|v| v.as_str()) {
-                    if result_code != "success" {
-                        return Err(format!("Transmission reported error: {}", result_code));
-                    }
+
+        // Check for torrent-add specific errors
+        if let Some(args) = trans_resp.arguments {
+            if let Some(error) = args.get("torrent-duplicate") {
+                if error != &serde_json::json!(null) {
+                    return Err("Torrent already exists in Transmission".to_string());
                 }
             }
-        } else {
-            if is_debug_enabled() {
-                println!("[DEBUG] Warning: Could not parse Transmission response as JSON");
+            // Check for any error messages
+            if let Some(result_code) = args.get("result").and_then(|v| v.as_str()) {
+                if result_code != "success" {
+                    return Err(format!("Transmission reported error: {}", result_code));
+                }
             }
+            added_id = args
+                .get("torrent-added")
+                .and_then(|added| added.get("id"))
+                .and_then(|id| id.as_i64());
         }
-        
-        Ok(())
-    } else if response.status().is_success() {
-        // Read response body to check what Transmission actually said
-        let body_bytes = hyper::body::to_bytes(response.into_body())
-            .await
-            .map_err(|e| format!("Failed to read response body: {}", e))?;
-        
-        let response_text = String::from_utf8_lossy(&body_bytes);
-        if is_debug_enabled() {
-            println!("[DEBUG] Transmission response: {}", response_text);
+    } else if is_debug_enabled() {
+        println!("[DEBUG] Warning: Could not parse Transmission response as JSON");
+    }
+
+    Ok(added_id)
+}
+
+/// Parse sizes like "20GB", "500 MB" or "1.5T" (SI units) into bytes
+fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim().to_uppercase();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number.parse::<f64>().ok()?;
+
+    let multiplier = match unit.trim() {
+        "" | "B" => 1.0,
+        "K" | "KB" => 1e3,
+        "M" | "MB" => 1e6,
+        "G" | "GB" => 1e9,
+        "T" | "TB" => 1e12,
+        _ => return None,
+    };
+
+    Some((number * multiplier) as u64)
+}
+
+/// MIN_FREE_BYTES, or the older MIN_FREE_SPACE, both taking plain bytes or sizes like 20GB
+fn min_free_space() -> Option<u64> {
+    let (name, value) = ["MIN_FREE_BYTES", "MIN_FREE_SPACE"]
+        .iter()
+        .find_map(|name| env::var(name).ok().map(|value| (name, value)))?;
+    let size = parse_size(&value);
+    if size.is_none() {
+        println!("Ignoring invalid {} value: {}", name, value);
+    }
+    size
+}
+
+/// Available space of the disk whose mount point is the longest prefix of `path`
+fn available_space_for(path: &str) -> Option<u64> {
+    use sysinfo::{DiskExt, System, SystemExt};
+
+    let mut system = System::new();
+    system.refresh_disks_list();
+    system.refresh_disks();
+
+    system
+        .disks()
+        .iter()
+        .filter(|disk| std::path::Path::new(path).starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+/// Refuse adds that would leave the disk below the minimum free space.
+/// Magnets without an `xl` size hint only get the floor checked
+fn check_free_space(media: &Media, path: &str, size: Option<u64>) -> Result<(), String> {
+    let min = min_free_space().unwrap_or_default();
+    if min == 0 && size.is_none() {
+        return Ok(());
+    }
+
+    match available_space_for(path) {
+        Some(available) => check_minimum(media, available, min, size.unwrap_or_default()),
+        None => Ok(()),
+    }
+}
+
+fn check_minimum(media: &Media, available: u64, min: u64, size: u64) -> Result<(), String> {
+    if available >= min.saturating_add(size) {
+        return Ok(());
+    }
+
+    let mut details = Vec::new();
+    if size > 0 {
+        details.push(format!("the torrent needs {}", format_bytes(size)));
+    }
+    if min > 0 {
+        details.push(format!("minimum is {}", format_bytes(min)));
+    }
+
+    Err(format!(
+        "⚠️ Only {} free on the {} disk ({}), refusing to add",
+        format_bytes(available),
+        media.clone().resolve().label(),
+        details.join(", ")
+    ))
+}
+
+/// Free space in the folder of `media` next to a download of `size` bytes,
+/// with a warning when it doesn't fit or would go below MIN_FREE_BYTES
+pub fn space_report(media: &Media, size: u64) -> Result<String, String> {
+    let path = media_path(media)?;
+    let available = available_space_for(&path).ok_or_else(|| format!("No disk found for {}", path))?;
+
+    let mut report = format!(
+        "{}: {} free in {}, result is {}",
+        media.label(),
+        format_bytes(available),
+        path,
+        format_bytes(size)
+    );
+
+    if size > available {
+        report.push_str("\n⚠️ It won't fit");
+    } else if let Some(min) = min_free_space().filter(|min| available - size < *min) {
+        report.push_str(&format!("\n⚠️ Would leave less than the minimum of {} free", format_bytes(min)));
+    }
+
+    Ok(report)
+}
+
+/// TV_DOWNLOAD_TEMPLATE like `{root}/{show}` gives every show its own
+/// download folder, the flat root is kept when the show can't be parsed
+async fn tv_download_dir(root: String, location: &TorrentLocation) -> String {
+    let Ok(template) = env::var("TV_DOWNLOAD_TEMPLATE") else {
+        return root;
+    };
+    let Some(name) = crate::torrentfile::location_name(location) else {
+        return root;
+    };
+
+    match crate::restructure::guess_show_name(&name).await {
+        Ok(show) => template.replace("{root}", &root).replace("{show}", &show),
+        Err(err) => {
+            println!("Adding {} to {}: {}", name, root, err);
+            root
         }
-        
-        // Parse to check for errors
-        let transmission_response: Result<TransmissionResponse, _> = serde_json::from_slice(&body_bytes);
-        
-        if let Ok(trans_resp) = transmission_response {
-            if trans_resp.result != "success" {
-                return Err(format!("Transmission error: {}", trans_resp.result));
-            }
+    }
+}
+
+/// Folder a torrent of `media` is added to, created when missing and
+/// checked for free space, whichever client adds it
+pub async fn download_dir(location: &TorrentLocation, media: &Media) -> Result<String, String> {
+    let mut path = media_path(media)?;
+    if media.clone().resolve() == Media::TV {
+        path = tv_download_dir(path, location).await;
+    }
+
+    // Only logged, the torrent client may see folders the bot can't
+    if create_media_dirs() {
+        if let Err(err) = ensure_dir(&path, true) {
+            println!("Warning: {}", err);
         }
-        
-        Ok(())
-    } else {
-        Err(format!("Error on transmission {}", response.status()))
     }
+
+    check_free_space(media, &path, crate::torrentfile::location_metadata(location).1)?;
+
+    Ok(path)
 }
 
-pub async fn add_torrent(location: TorrentLocation, media: Media) -> Result<(), String> {
-    let path = match media {
-        Media::TV => transmission_path("TRANSMISSION_TV_PATH".to_string())?,
-        Media::Movie => transmission_path("TRANSMISSION_MOVIE_PATH".to_string())?,
-    };
+/// Add the torrent to the folder of its media and return that folder
+///
+/// `requester` is stored as a label so /status can show who added it.
+pub async fn add_torrent(location: TorrentLocation, media: Media, requester: String) -> Result<String, String> {
+    let path = download_dir(&location, &media).await?;
 
-    request_add_torrent(location, path.clone()).await?;
-    Ok(())
+    serialized(request_add_torrent(location, path.clone(), vec![added_by_label(&requester)], false)).await?;
+    Ok(path)
 }
 
-pub async fn get_torrents() -> Result<Vec<Torrent>, String> {
+/// Add the torrent to the folder of its media without downloading anything
+/// yet and return its id, so its files can be looked at first
+///
+/// A .torrent file is added paused. A magnet has no files until peers send
+/// its metadata, so it runs until `hold_for_inspect` stops it.
+pub async fn add_torrent_for_inspect(location: TorrentLocation, media: Media, requester: String) -> Result<i64, String> {
+    let path = download_dir(&location, &media).await?;
+    let paused = !location.is_magnet;
+
+    serialized(request_add_torrent(location, path, vec![added_by_label(&requester)], paused))
+        .await?
+        .ok_or_else(|| "Transmission didn't say which torrent was added".to_string())
+}
+
+const TORRENT_FIELDS: &[&str] = &[
+    "id", "name", "status", "percentDone", "downloadDir",
+    "totalSize", "downloadedEver", "uploadedEver", "uploadRatio", "rateDownload", "rateUpload", "eta",
+    "seedRatioLimit", "seedRatioMode", "seedIdleLimit",
+    "peersConnected", "peersSendingToUs", "peersGettingFromUs", "trackers",
+    "error", "errorString", "metadataPercentComplete", "labels",
+];
+
+async fn request_torrent_get(arguments: serde_json::Value) -> Result<serde_json::Value, String> {
+    request_read("torrent-get", arguments).await
+}
+
+// Methods that only read, so they skip the write queue
+async fn request_read(method: &'static str, arguments: serde_json::Value) -> Result<serde_json::Value, String> {
     let https = hyper_rustls::HttpsConnector::with_native_roots();
     let client: client::Client<_> = client::Client::builder().build(https);
 
-    let arguments = json!({
-        "fields": [
-            "id", "name", "status", "percentDone", "downloadDir",
-            "totalSize", "downloadedEver", "uploadedEver",
-            "seedRatioLimit", "seedIdleLimit"
-        ]
-    });
-
-    let response = request_transmission_with_retry(&client, "torrent-get", arguments).await?;
+    let response = request_transmission_with_retry(&client, method, arguments).await?;
 
     let body_bytes = hyper::body::to_bytes(response.into_body())
         .await
@@ -288,39 +651,280 @@ pub async fn get_torrents() -> Result<Vec<Torrent>, String> {
         return Err(format!("Transmission error: {}", transmission_response.result));
     }
 
-    if let Some(args) = transmission_response.arguments {
-        if let Some(torrents_array) = args.get("torrents") {
-            let torrents: Vec<Torrent> = serde_json::from_value(torrents_array.clone())
-                .map_err(|e| format!("Failed to parse torrents: {}", e))?;
-            return Ok(torrents);
+    Ok(transmission_response.arguments.unwrap_or(serde_json::Value::Null))
+}
+
+/// Seed ratio limit of torrents that follow the session, None when it's off
+pub async fn session_ratio_limit() -> Result<Option<f64>, String> {
+    let arguments = json!({
+        "fields": ["seedRatioLimit", "seedRatioLimited"]
+    });
+
+    let args = request_read("session-get", arguments).await?;
+    let limited = args.get("seedRatioLimited").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    Ok(args
+        .get("seedRatioLimit")
+        .and_then(|v| v.as_f64())
+        .filter(|limit| limited && *limit > 0.0))
+}
+
+fn parse_torrents(args: &serde_json::Value) -> Result<Vec<Torrent>, String> {
+    match args.get("torrents") {
+        Some(torrents_array) => serde_json::from_value(torrents_array.clone())
+            .map_err(|e| format!("Failed to parse torrents: {}", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub async fn get_torrents() -> Result<Vec<Torrent>, String> {
+    let arguments = json!({
+        "fields": TORRENT_FIELDS
+    });
+
+    let args = request_torrent_get(arguments).await?;
+    parse_torrents(&args)
+}
+
+/// Fetch only the torrents active in about the last minute, along with the
+/// ids of torrents removed in that time. Transmission keeps no state between
+/// calls, so pollers running less than once a minute miss changes and should
+/// use `get_torrents`, as should commands that need the complete list.
+pub async fn get_recently_active() -> Result<(Vec<Torrent>, Vec<i64>), String> {
+    let arguments = json!({
+        "ids": "recently-active",
+        "fields": TORRENT_FIELDS
+    });
+
+    let args = request_torrent_get(arguments).await?;
+    let torrents = parse_torrents(&args)?;
+
+    let removed = args
+        .get("removed")
+        .and_then(|v| v.as_array())
+        .map(|ids| ids.iter().filter_map(|id| id.as_i64()).collect())
+        .unwrap_or_default();
+
+    Ok((torrents, removed))
+}
+
+/// Files of a single torrent with their individual progress and wanted/priority state
+pub async fn get_torrent_files_detailed(id: i64) -> Result<TorrentFiles, String> {
+    let arguments = json!({
+        "ids": [id],
+        "fields": ["name", "files", "fileStats"]
+    });
+
+    let args = request_torrent_get(arguments).await?;
+
+    let torrents: Vec<TorrentFiles> = match args.get("torrents") {
+        Some(torrents_array) => serde_json::from_value(torrents_array.clone())
+            .map_err(|e| format!("Failed to parse torrent files: {}", e))?,
+        None => Vec::new(),
+    };
+
+    torrents
+        .into_iter()
+        .next()
+        .ok_or_else(|| "Torrent not found, it may have been removed".to_string())
+}
+
+/// Files along with the tracker stats and error of a single torrent
+pub async fn get_torrent_details(id: i64) -> Result<TorrentDetails, String> {
+    let arguments = json!({
+        "ids": [id],
+        "fields": ["name", "files", "fileStats", "trackerStats", "errorString"]
+    });
+
+    let args = request_torrent_get(arguments).await?;
+
+    let torrents: Vec<TorrentDetails> = match args.get("torrents") {
+        Some(torrents_array) => serde_json::from_value(torrents_array.clone())
+            .map_err(|e| format!("Failed to parse torrent details: {}", e))?,
+        None => Vec::new(),
+    };
+
+    torrents
+        .into_iter()
+        .next()
+        .ok_or_else(|| "Torrent not found, it may have been removed".to_string())
+}
+
+const METADATA_POLL: Duration = Duration::from_secs(2);
+
+/// Files of a torrent once its metadata arrived, magnet links have none until
+/// peers send it
+async fn wait_for_files(id: i64, timeout: Duration) -> Result<TorrentFiles, String> {
+    let started = std::time::Instant::now();
+
+    loop {
+        let details = get_torrent_files_detailed(id).await?;
+        if !details.files.is_empty() {
+            return Ok(details);
+        }
+        if started.elapsed() >= timeout {
+            return Err(format!("No metadata for {} after {}s", details.name, timeout.as_secs()));
         }
+
+        tokio::time::sleep(METADATA_POLL).await;
+    }
+}
+
+async fn set_files_wanted(id: i64, count: usize, wanted: bool) -> Result<(), String> {
+    if count == 0 {
+        return Ok(());
     }
 
-    Ok(Vec::new())
+    let field = if wanted { "files-wanted" } else { "files-unwanted" };
+    let mut arguments = json!({
+        "ids": [id]
+    });
+    arguments[field] = json!((0..count).collect::<Vec<_>>());
+
+    serialized(request_torrent_write("torrent-set", arguments)).await
 }
 
-pub async fn delete_torrent(ids: Vec<i64>) -> Result<(), String> {
-    let https = hyper_rustls::HttpsConnector::with_native_roots();
-    let client: client::Client<_> = client::Client::builder().build(https);
+/// Files of a torrent from `add_torrent_for_inspect` once its metadata
+/// arrived, left stopped with every file unwanted until `start_inspected`
+pub async fn hold_for_inspect(id: i64, timeout: Duration) -> Result<TorrentFiles, String> {
+    let files = wait_for_files(id, timeout).await;
+
+    // A magnet was running to get here, unwanted files stop it fetching data
+    // before torrent-stop goes through
+    let count = files.as_ref().map(|details| details.files.len()).unwrap_or_default();
+    set_files_wanted(id, count, false).await?;
+    stop_torrent(id).await?;
+
+    files
+}
+
+/// Want every file of an inspected torrent again and start it, returns its name
+pub async fn start_inspected(id: i64) -> Result<String, String> {
+    let details = get_torrent_files_detailed(id).await?;
+    set_files_wanted(id, details.files.len(), true).await?;
+
+    start_torrent(id).await
+}
 
+pub async fn delete_torrent(ids: Vec<i64>) -> Result<(), String> {
     let arguments = json!({
         "ids": ids,
         "delete-local-data": false
     });
 
-    request_transmission_with_retry(&client, "torrent-remove", arguments).await?;
-    Ok(())
+    serialized(request_torrent_write("torrent-remove", arguments)).await
 }
 
-pub async fn stop_seeding_all() -> Result<(), String> {
-    let https = hyper_rustls::HttpsConnector::with_native_roots();
-    let client: client::Client<_> = client::Client::builder().build(https);
+/// Remove a torrent (keeping its data) and add it again from its magnet link
+///
+/// When the add fails the error carries the magnet link, the torrent is gone
+/// by then.
+pub async fn readd_torrent(id: i64) -> Result<String, String> {
+    let arguments = json!({
+        "ids": [id],
+        "fields": ["name", "magnetLink", "downloadDir", "labels"]
+    });
+
+    let args = request_torrent_get(arguments).await?;
+    let torrent = args
+        .get("torrents")
+        .and_then(|torrents| torrents.get(0))
+        .ok_or("Torrent not found, it may have been removed")?;
+
+    let field = |name: &str| torrent.get(name).and_then(|v| v.as_str()).map(|v| v.to_string());
+    let name = field("name").unwrap_or_default();
+    let magnet = field("magnetLink").ok_or("Transmission has no magnet link for this torrent")?;
+    let download_dir = field("downloadDir").ok_or("Transmission has no download dir for this torrent")?;
+    let labels = torrent
+        .get("labels")
+        .and_then(|labels| serde_json::from_value(labels.clone()).ok())
+        .unwrap_or_default();
+
+    delete_torrent(vec![id]).await?;
+
+    // Removed already, the magnet link is all that's left to add it back by hand
+    let location = TorrentLocation {
+        content: magnet.clone(),
+        is_magnet: true,
+    };
+    serialized(request_add_torrent(location, download_dir, labels, false))
+        .await
+        .map_err(|err| format!("{} was removed but adding it again failed: {}\nAdd it back with {}", name, err, magnet))?;
+
+    Ok(name)
+}
+
+/// Single torrent with the usual fields
+pub async fn get_torrent(id: i64) -> Result<Torrent, String> {
+    let arguments = json!({
+        "ids": [id],
+        "fields": TORRENT_FIELDS
+    });
+
+    let args = request_torrent_get(arguments).await?;
+    parse_torrents(&args)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| "Torrent not found, it may have been removed".to_string())
+}
+
+// Run a per-torrent method like torrent-stop and return the torrent's name
+async fn torrent_action(method: &'static str, id: i64) -> Result<String, String> {
+    let torrent = get_torrent(id).await?;
 
+    let arguments = json!({
+        "ids": [id]
+    });
+
+    serialized(request_torrent_write(method, arguments)).await?;
+    Ok(torrent.name)
+}
+
+/// Pause a torrent without removing it, returns its name
+pub async fn stop_torrent(id: i64) -> Result<String, String> {
+    torrent_action("torrent-stop", id).await
+}
+
+/// Resume a stopped torrent, returns its name
+pub async fn start_torrent(id: i64) -> Result<String, String> {
+    torrent_action("torrent-start", id).await
+}
+
+/// Ask the trackers of a torrent for peers now instead of at the next
+/// scheduled announce, returns its name
+pub async fn reannounce_torrent(id: i64) -> Result<String, String> {
+    torrent_action("torrent-reannounce", id).await
+}
+
+/// Cap how many peers a torrent connects to
+pub async fn set_peer_limit(id: i64, limit: u16) -> Result<(), String> {
+    let arguments = json!({
+        "ids": [id],
+        "peer-limit": limit
+    });
+
+    serialized(request_torrent_write("torrent-set", arguments)).await
+}
+
+/// Seed the torrent until it reaches `ratio`, overriding the global limit
+pub async fn set_seed_ratio(id: i64, ratio: f64) -> Result<(), String> {
+    let arguments = json!({
+        "ids": [id],
+        "seedRatioLimit": ratio,
+        "seedRatioMode": 1
+    });
+
+    serialized(request_torrent_write("torrent-set", arguments)).await
+}
+
+/// Run an id-based method (torrent-stop, torrent-verify, ...) on every
+/// torrent and return how many there were
+async fn all_torrents_action(method: &'static str) -> Result<usize, String> {
     // First get all torrents
     let torrents = get_torrents().await?;
     
     if torrents.is_empty() {
-        return Ok(());
+        return Ok(0);
     }
 
     let ids: Vec<i64> = torrents.iter().map(|t| t.id).collect();
@@ -329,17 +933,122 @@ pub async fn stop_seeding_all() -> Result<(), String> {
         "ids": ids
     });
 
-    request_transmission_with_retry(&client, "torrent-stop", arguments).await?;
-    Ok(())
+    serialized(request_torrent_write(method, arguments)).await?;
+    Ok(ids.len())
+}
+
+pub async fn stop_seeding_all() -> Result<(), String> {
+    all_torrents_action("torrent-stop").await.map(|_| ())
+}
+
+/// Queue every torrent for a recheck of its local data, returning the count
+pub async fn verify_all() -> Result<usize, String> {
+    all_torrents_action("torrent-verify").await
+}
+
+/// Change Transmission's global download-dir, used by torrents added outside the bot
+pub async fn set_default_download_dir(path: String) -> Result<(), String> {
+    let arguments = json!({
+        "download-dir": path
+    });
+
+    serialized(request_torrent_write("session-set", arguments)).await
+}
+
+// Configured download dirs, unset and empty ones left out
+fn media_roots() -> Vec<(Media, String)> {
+    [Media::TV, Media::Movie, Media::Anime]
+        .iter()
+        .filter_map(|media| {
+            env::var(media.path_env())
+                .ok()
+                .filter(|path| !path.is_empty())
+                .map(|path| (media.clone(), path))
+        })
+        .collect()
+}
+
+// The most specific root wins, so an anime folder inside the TV one is anime
+fn media_for_path(path: &str, roots: &[(Media, String)]) -> Option<Media> {
+    roots
+        .iter()
+        .filter(|(_, root)| Path::new(path).starts_with(root))
+        .max_by_key(|(_, root)| Path::new(root).components().count())
+        .map(|(media, _)| media.clone())
+}
+
+/// Media whose download dir contains `path`
+pub fn get_media_type_from_path(path: &str) -> Option<Media> {
+    media_for_path(path, &media_roots())
+}
+
+fn overlapping_roots(roots: &[(Media, String)]) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for (i, (media, root)) in roots.iter().enumerate() {
+        for (other_media, other_root) in &roots[i + 1..] {
+            if Path::new(root) == Path::new(other_root) {
+                warnings.push(format!(
+                    "{} and {} are the same directory, their /delete lists will be identical",
+                    media.path_env(),
+                    other_media.path_env()
+                ));
+            } else if Path::new(root).starts_with(other_root) || Path::new(other_root).starts_with(root) {
+                warnings.push(format!(
+                    "{} and {} overlap, torrents go to the media with the longer path",
+                    media.path_env(),
+                    other_media.path_env()
+                ));
+            }
+        }
+    }
+
+    warnings
 }
 
-pub fn get_media_type_from_path(path: &str, tv_path: &str, movie_path: &str) -> Option<Media> {
-    if path.starts_with(tv_path) {
-        Some(Media::TV)
-    } else if path.starts_with(movie_path) {
-        Some(Media::Movie)
+/// Print a warning for media download dirs that are the same or nested
+pub fn warn_overlapping_media_paths() {
+    for warning in overlapping_roots(&media_roots()) {
+        println!("Warning: {}", warning);
+    }
+}
+
+/// Missing media folders are created unless SKIP_MEDIA_DIR_CREATION is set,
+/// for folders that are mounted after the bot starts
+fn create_media_dirs() -> bool {
+    env::var("SKIP_MEDIA_DIR_CREATION").is_err()
+}
+
+/// Create the folder when allowed and check it can be written to
+fn ensure_dir(path: &str, create: bool) -> Result<&'static str, String> {
+    let dir = Path::new(path);
+
+    let state = if dir.is_dir() {
+        "ok"
+    } else if create {
+        std::fs::create_dir_all(dir).map_err(|err| format!("{} could not be created: {}", path, err))?;
+        "created"
     } else {
-        None
+        return Err(format!("{} does not exist", path));
+    };
+
+    let probe = dir.join(".telegram-bot-write-check");
+    std::fs::write(&probe, b"")
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|err| format!("{} is not writable: {}", path, err))?;
+
+    Ok(state)
+}
+
+/// Check the TV, movie and anime folders at startup, creating missing ones
+pub fn ensure_media_dirs() {
+    let create = create_media_dirs();
+
+    for (media, path) in media_roots() {
+        match ensure_dir(&path, create) {
+            Ok(state) => println!("{} folder {}: {}", media.label(), path, state),
+            Err(err) => println!("Warning: {} folder {}", media.label(), err),
+        }
     }
 }
 
@@ -390,3 +1099,115 @@ fn format_bytes(bytes: u64) -> String {
     use size_format::SizeFormatterSI;
     SizeFormatterSI::new(bytes).to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roots(paths: &[(Media, &str)]) -> Vec<(Media, String)> {
+        paths.iter().map(|(media, path)| (media.clone(), path.to_string())).collect()
+    }
+
+    #[test]
+    fn nested_media_paths_prefer_the_longest_root() {
+        let roots = roots(&[(Media::TV, "/data/tv"), (Media::Anime, "/data/tv/anime"), (Media::Movie, "/data")]);
+
+        assert_eq!(media_for_path("/data/tv/anime/Show", &roots), Some(Media::Anime));
+        assert_eq!(media_for_path("/data/tv/Show", &roots), Some(Media::TV));
+        assert_eq!(media_for_path("/data/movies/Film", &roots), Some(Media::Movie));
+        assert_eq!(media_for_path("/elsewhere", &roots), None);
+        // Path components, not string prefixes
+        assert_eq!(media_for_path("/data/tv2/Show", &roots), Some(Media::Movie));
+    }
+
+    #[test]
+    fn movies_nested_under_the_tv_path_are_movies() {
+        let roots = roots(&[(Media::TV, "/media/tv"), (Media::Movie, "/media/tv/movies")]);
+
+        assert_eq!(media_for_path("/media/tv/movies/The Matrix", &roots), Some(Media::Movie));
+        assert_eq!(media_for_path("/media/tv/movies", &roots), Some(Media::Movie));
+        assert_eq!(media_for_path("/media/tv/The Office", &roots), Some(Media::TV));
+    }
+
+    #[test]
+    fn identical_and_nested_media_paths_are_reported() {
+        let same = roots(&[(Media::TV, "/data/media"), (Media::Movie, "/data/media/")]);
+        let nested = roots(&[(Media::TV, "/data"), (Media::Movie, "/data/movies")]);
+        let separate = roots(&[(Media::TV, "/data/tv"), (Media::Movie, "/data/movies")]);
+
+        assert!(overlapping_roots(&same)[0].contains("same directory"));
+        assert!(overlapping_roots(&nested)[0].contains("overlap"));
+        assert!(overlapping_roots(&separate).is_empty());
+    }
+
+    #[test]
+    fn adds_below_the_minimum_free_space_are_refused() {
+        assert!(check_minimum(&Media::TV, 30_000_000_000, 20_000_000_000, 0).is_ok());
+        assert!(check_minimum(&Media::Movie, 20_000_000_000, 20_000_000_000, 0).is_ok());
+
+        let err = check_minimum(&Media::TV, 5_000_000_000, 20_000_000_000, 0).unwrap_err();
+        assert!(err.starts_with("⚠️ Only "));
+        assert!(err.contains("free on the 📺 TV disk"));
+        assert!(err.ends_with("refusing to add"));
+    }
+
+    #[test]
+    fn torrent_details_deserialize_files_and_tracker_stats() {
+        let details: TorrentDetails = serde_json::from_value(json!({
+            "name": "Show",
+            "files": [{"name": "Show/E01.mkv", "length": 1000, "bytesCompleted": 500}],
+            "fileStats": [{"wanted": true, "priority": 0}],
+            "errorString": "",
+            "trackerStats": [{
+                "host": "udp://tracker.example:1337",
+                "lastAnnounceSucceeded": false,
+                "lastAnnounceResult": "Connection failed",
+                "seederCount": -1,
+                "leecherCount": -1,
+                "tier": 0
+            }]
+        }))
+        .unwrap();
+
+        assert_eq!(details.files.name, "Show");
+        assert_eq!(details.files.files[0].bytes_completed, 500);
+        assert_eq!(details.tracker_stats[0].last_announce_result, "Connection failed");
+        assert!(!details.tracker_stats[0].last_announce_succeeded);
+    }
+
+    #[test]
+    fn known_torrent_sizes_count_against_the_free_space() {
+        assert!(check_minimum(&Media::Movie, 30_000_000_000, 20_000_000_000, 5_000_000_000).is_ok());
+        assert!(check_minimum(&Media::Movie, 30_000_000_000, 0, 40_000_000_000).is_err());
+
+        let err = check_minimum(&Media::Movie, 30_000_000_000, 20_000_000_000, 15_000_000_000).unwrap_err();
+        assert!(err.contains("the torrent needs"));
+        assert!(err.contains("minimum is"));
+    }
+
+    fn seeding(mode: i64, limit: f64, ratio: f64) -> Torrent {
+        serde_json::from_value(json!({
+            "id": 1, "name": "Show", "status": 6, "percentDone": 1.0, "downloadDir": "/data/tv",
+            "totalSize": 1000, "downloadedEver": 1000, "uploadedEver": 1500, "uploadRatio": ratio,
+            "rateDownload": 0, "rateUpload": 0, "eta": -1,
+            "seedRatioLimit": limit, "seedRatioMode": mode, "seedIdleLimit": 30,
+            "peersConnected": 0, "peersSendingToUs": 0, "peersGettingFromUs": 0,
+            "error": 0, "errorString": "", "metadataPercentComplete": 1.0
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn ratio_limits_follow_the_seed_ratio_mode() {
+        // Its own limit
+        assert_eq!(seeding(1, 1.5, 2.0).ratio_limit(Some(3.0)), Some(1.5));
+        assert!(seeding(1, 1.5, 2.0).met_ratio_limit(Some(3.0)));
+        // The session limit, seedRatioLimit is only a leftover then
+        assert_eq!(seeding(0, 1.5, 2.0).ratio_limit(Some(3.0)), Some(3.0));
+        assert!(!seeding(0, 1.5, 2.0).met_ratio_limit(Some(3.0)));
+        assert!(!seeding(0, 1.5, 2.0).met_ratio_limit(None));
+        // Seeds forever
+        assert_eq!(seeding(2, 1.5, 2.0).ratio_limit(Some(1.0)), None);
+        assert!(!seeding(2, 1.5, 2.0).met_ratio_limit(Some(1.0)));
+    }
+}